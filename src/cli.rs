@@ -0,0 +1,812 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::process::{self, Command};
+use std::sync::Arc;
+
+use itertools::Itertools;
+use json::{JsonValue, object};
+
+use crate::experiment::run_experiment_file;
+use crate::hidden_markov_model::HiddenMarkovModel;
+use crate::learn_net::coordinator::run_coordinator;
+use crate::learn_net::manifest::Manifest;
+use crate::learn_net::worker::run_worker;
+use crate::markov_chain::MarkovChain;
+use crate::n_gram::{NGram, ScoringMode};
+use crate::n_gram::config::LearnConfig;
+use crate::n_gram::gradient::GradientConfig;
+use crate::n_gram::shard::NgramCounts;
+use crate::n_gram::multi_label::{MultiLabelInput, MultiLabelNGram, split_labels};
+use crate::n_gram::label_tree::{LabelCooccurrence, LabelTree};
+use crate::events::{StderrSink, set_event_sink};
+use crate::parallel::set_num_threads;
+use crate::random::seeded_rng;
+use crate::text::{NormalizerConfig, TextPipeline, TokenizerConfig};
+use crate::text::stop_words::{LANGUAGES, StopWords, StopWordsConfig};
+use crate::vocabulary::Vocabulary;
+use crate::vectorizer::{Vectorizer, VectorizerConfig, Weighting};
+use crate::util::{ColumnSelector, CsvInputConfig, InputTup, get_input_data_csv_with, get_markov_data_with, shard};
+
+const USAGE: &str = "Usage: rust-datascience <command> [options]
+
+Commands:
+  ngram train      --train FILE --model FILE [--validation FILE] [--config FILE]
+                   [--max-grams N] [--scoring voting|naive_bayes] [--smoothing X] [--seed N]
+                   [--checkpoint FILE [--checkpoint-every EPOCHS]]
+  ngram count      --train FILE --output FILE [--max-grams N] [--shard K/N]
+  ngram merge      --counts FILE,FILE... --model FILE [--scoring voting|naive_bayes] [--smoothing X]
+  ngram eval       --model FILE --data FILE
+  ngram classify   --model FILE (--text TEXT | --input FILE)
+  markov train     --text FILE --model FILE [--vocab FILE] [--checkpoint FILE [--checkpoint-every N]]
+  markov count     --text FILE --output FILE [--vocab FILE] [--shard K/N]
+  markov merge     --counts FILE,FILE... --model FILE
+  markov generate  --model FILE --start WORD [--length N] [--seed N]
+  hmm train        --data FILE --model FILE [--vocab FILE]
+  hmm tag          --model FILE (--text TEXT | --input FILE) [--vocab FILE]
+  hmm sample       --model FILE [--length N] [--seed N]
+  vocab top-words  --input FILE [--top N] [--min-count N] [--output FILE]
+  vocab build      --input FILE [--top N] [--min-count N] [--output FILE]
+  multilabel train --train FILE --model FILE [--label-separator SEP] [--tune FILE]
+                   [--max-grams N] [--scoring voting|naive_bayes] [--smoothing X]
+  multilabel eval  --model FILE --data FILE [--label-separator SEP] [--tree FILE [--prior-weight X]]
+  multilabel classify --model FILE (--text TEXT | --input FILE) [--tree FILE [--prior-weight X]]
+  multilabel hierarchy --train FILE [--output FILE] [--label-separator SEP] [--smoothing X]
+  learn-net run    --manifest FILE [--listen ADDR] [--local-workers N]
+  learn-net worker --connect ADDR [--name NAME]
+  vectorize        --input FILE --output FILE [--format mtx|csv] [--weighting tf|tf_idf|bm25|bm25(K1,B)]
+                   [--max-grams N] [--sublinear] [--no-normalize] [--min-df N] [--max-features N]
+                   [--vectorizer FILE] [--save-vectorizer FILE] [--labels FILE]
+  experiment       FILE
+
+Csv options for ngram commands: [--stop-words FILE] [--stop-word-languages english,spanish] [--label-column N|NAME] [--text-column N|NAME]
+  [--delimiter C] [--no-headers] [--no-quoting] [--exclude-labels A,B] [--include-labels A,B]
+ngram and markov commands accept --tokenizer default|whitespace|unicode_word|tweet|regex(PATTERN)
+  and --normalizers porter_stemmer,lemmatizer(FILE)
+ngram commands accept --vocab FILE to map words outside the vocabulary to <UNK>,
+  use the same vocabulary for training and classifying
+multilabel commands read the same csv options, the label column holds every label
+  of the row joined by --label-separator, default |. --tune picks per label thresholds on that file
+multilabel hierarchy prints the label co-occurrence tree and saves it with --output,
+  eval and classify with --tree pick the most likely label set under it instead of the thresholds,
+  --prior-weight scales the tree prior, default 1
+Every command accepts --json to print its output as json, --quiet to hide progress on stderr
+and --threads N to limit worker threads, the default is the available parallelism
+--seed makes markov generate sample instead of taking the most likely word,
+  and overrides the gradient seed of the ngram train --config, runs with the same seed give the same output.
+  ngram train accepts --seed and --checkpoint only with a --config that selects gradient learning
+learn-net run hands the manifest jobs to the workers that connect to --listen, default 127.0.0.1:7878,
+  --local-workers starts that many workers on this machine, see learn_net/manifest.rs for the manifest
+--model /dev/stdout or --output /dev/stdout writes the file to stdout and the command summary to stderr
+--checkpoint saves the training state to FILE, gradient training of the --config every N epochs
+  (default 1) and markov training every N transitions (default 1000000), rerun the same command to resume
+ngram train --validation reports the accuracy of the trained model on that file, it is not used for learning
+count writes the counts of one shard, --shard K/N counts the K-th (from 0) of N equal parts of the input,
+  merge combines the count files of all shards into the model train would give on the whole input
+vectorize writes the document-term matrix of the csv input (same csv options as ngram commands),
+  --vectorizer reuses the terms and weighting of a vectorizer saved with --save-vectorizer instead of fitting,
+  --labels writes the row labels one per line, the csv format puts them in its first column
+hmm train data is one state and observation per line separated by a tab, in sequence order";
+
+// Positional arguments, --key value options and --flag switches
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: HashSet<String>
+}
+
+const FLAGS: [&str; 6] = ["json", "no-headers", "no-quoting", "quiet", "sublinear", "no-normalize"];
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Args, String> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut flags = HashSet::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.strip_prefix("--") {
+                Some(key) if FLAGS.contains(&key) => { flags.insert(String::from(key)); },
+                Some(key) => {
+                    let value = iter.next().ok_or(format!("Missing value for --{}", key))?;
+                    options.insert(String::from(key), value.clone());
+                },
+                None => positional.push(arg.clone())
+            }
+        }
+        Ok(Args { positional, options, flags })
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(|s| s.as_str())
+    }
+
+    fn required(&self, key: &str) -> Result<&str, String> {
+        self.get(key).ok_or(format!("Missing required option --{}", key))
+    }
+
+    fn number<T: std::str::FromStr>(&self, key: &str, def: T) -> Result<T, String> {
+        match self.get(key) {
+            Some(s) => s.parse::<T>().map_err(|_| format!("--{} must be a number, got {}", key, s)),
+            None => Ok(def)
+        }
+    }
+
+    fn json(&self) -> bool {
+        self.flags.contains("json")
+    }
+
+    fn tokenizer(&self) -> Result<TokenizerConfig, String> {
+        let name = self.get("tokenizer").unwrap_or("default");
+        let tokenizer = TokenizerConfig::parse(name).ok_or(format!("Unknown tokenizer: {}", name))?;
+        tokenizer.validate().map_err(|e| e.to_string())?;
+        Ok(tokenizer)
+    }
+
+    fn stop_words(&self) -> Result<StopWordsConfig, String> {
+        let languages = match self.get("stop-word-languages") {
+            Some(list) => list.split(',').map(String::from).collect_vec(),
+            None => Vec::new()
+        };
+        if let Some(language) = languages.iter().find(|l| !LANGUAGES.contains(&l.as_str())) {
+            return Err(format!("No bundled stop words for {}, available: {}", language, LANGUAGES.join(", ")));
+        }
+        Ok(StopWordsConfig { languages, file: self.get("stop-words").map(String::from) })
+    }
+
+    fn vocabulary(&self) -> Result<Option<Vocabulary>, String> {
+        self.get("vocab").map(Vocabulary::load).transpose()
+    }
+
+    // --shard K/N, the K-th (from 0) of N shards of the input
+    fn shard(&self) -> Result<Option<(usize, usize)>, String> {
+        let shard = match self.get("shard") {
+            Some(shard) => shard,
+            None => return Ok(None)
+        };
+        let err = format!("--shard must be INDEX/COUNT with INDEX below COUNT, got {}", shard);
+        let (index, count) = shard.split_once('/').ok_or(err.clone())?;
+        match (index.parse::<usize>(), count.parse::<usize>()) {
+            (Ok(index), Ok(count)) if index < count => Ok(Some((index, count))),
+            _ => Err(err)
+        }
+    }
+
+    fn list(&self, key: &str) -> Result<Vec<String>, String> {
+        Ok(self.required(key)?.split(',').map(String::from).collect_vec())
+    }
+
+    fn normalizers(&self) -> Result<Vec<NormalizerConfig>, String> {
+        match self.get("normalizers") {
+            Some(list) => list
+                .split(',')
+                .map(|name| NormalizerConfig::parse(name).ok_or(format!("Unknown normalizer: {}", name)))
+                .collect(),
+            None => Ok(Vec::new())
+        }
+    }
+}
+
+// A model or output written to /dev/stdout must not be mixed with the summary,
+// the summary goes to stderr then (learn net jobs collect a model this way)
+fn writes_to_stdout(args: &Args) -> bool {
+    ["model", "output"].iter().any(|key| args.get(key) == Some("/dev/stdout"))
+}
+
+// Print either the json value or the text
+fn output(args: &Args, json_value: JsonValue, text: String) {
+    let text = if args.json() { json_value.pretty(2) } else { text };
+    if writes_to_stdout(args) {
+        eprintln!("{}", text);
+    } else {
+        println!("{}", text);
+    }
+}
+
+fn csv_config(args: &Args) -> Result<CsvInputConfig, String> {
+    let mut config = CsvInputConfig::default();
+    if let Some(label) = args.get("label-column") {
+        config.label = ColumnSelector::parse(label);
+    }
+    if let Some(text) = args.get("text-column") {
+        config.text = ColumnSelector::parse(text);
+    }
+    if let Some(delimiter) = args.get("delimiter") {
+        if delimiter.len() != 1 {
+            return Err(format!("--delimiter must be a single character, got {}", delimiter));
+        }
+        config.delimiter = delimiter.as_bytes()[0];
+    }
+    config.has_headers = !args.flags.contains("no-headers");
+    config.quoting = !args.flags.contains("no-quoting");
+    let label_list = |key: &str| args.get(key).map(|s| s.split(',').map(String::from).collect_vec()).unwrap_or_default();
+    config.exclude_labels = label_list("exclude-labels");
+    config.include_labels = label_list("include-labels");
+    config.stop_words = args.stop_words()?;
+    config.tokenizer = args.tokenizer()?;
+    config.normalizers = args.normalizers()?;
+    Ok(config)
+}
+
+fn load_csv(args: &Args, file: &str) -> Result<Vec<InputTup>, String> {
+    let input = get_input_data_csv_with(file, &csv_config(args)?)?;
+    Ok(match args.vocabulary()? {
+        Some(vocabulary) => vocabulary.map_input(&input),
+        None => input
+    })
+}
+
+// Sentences from --text or one per line from --input, cleaned like the training data
+fn input_sentences(args: &Args) -> Result<Vec<(String, String)>, String> {
+    let raw = match (args.get("text"), args.get("input")) {
+        (Some(text), _) => vec![String::from(text)],
+        (None, Some(file)) => fs::read_to_string(file)
+            .map_err(|e| format!("Error reading {}: {}", file, e))?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(String::from)
+            .collect_vec(),
+        (None, None) => return Err(String::from("Missing --text or --input"))
+    };
+    let pipeline = TextPipeline::new(&args.tokenizer()?, &args.normalizers()?, args.stop_words()?.load());
+    let vocabulary = args.vocabulary()?;
+    let clean = |s: &str| match &vocabulary {
+        Some(vocabulary) => vocabulary.map_sentence(&pipeline.clean(s)),
+        None => pipeline.clean(s)
+    };
+    Ok(raw.into_iter().map(|s| { let cleaned = clean(&s); (s, cleaned) }).collect_vec())
+}
+
+// --smoothing, naive bayes and the label tree take its log so it must be positive
+fn smoothing(args: &Args) -> Result<f64, String> {
+    let smoothing = args.number("smoothing", 1.0)?;
+    if smoothing <= 0.0 {
+        return Err(format!("--smoothing must be greater than 0, got {}", smoothing));
+    }
+    Ok(smoothing)
+}
+
+fn parse_scoring(args: &Args) -> Result<ScoringMode, String> {
+    let smoothing = smoothing(args)?;
+    match args.get("scoring").unwrap_or("voting") {
+        "voting" => Ok(ScoringMode::Voting),
+        "naive_bayes" => Ok(ScoringMode::NaiveBayes(smoothing)),
+        // linear scoring reads gradient weights, which only gradient learning writes
+        "linear" => Err(String::from("--scoring linear needs gradient weights, train with a --config that selects gradient, it switches the model to linear scoring")),
+        s => Err(format!("Unknown scoring mode: {}", s))
+    }
+}
+
+// The --config of ngram train with --seed and --checkpoint applied to its gradient learning
+fn learn_config(args: &Args) -> Result<Option<LearnConfig>, String> {
+    let mut config = match args.get("config") {
+        Some(config_file) => Some(NGram::read_config(config_file).map_err(|e| e.to_string())?),
+        None => None
+    };
+    let gradient = match config.as_mut().filter(|config| config.prune_selection.gradient) {
+        Some(config) => config.gradient.get_or_insert_with(GradientConfig::default),
+        None => {
+            return match ["seed", "checkpoint", "checkpoint-every"].iter().find(|key| args.get(key).is_some()) {
+                Some(key) => Err(format!("--{} only applies to gradient learning, use a --config that selects gradient", key)),
+                None => Ok(config)
+            };
+        }
+    };
+    if args.get("seed").is_some() {
+        gradient.seed = args.number("seed", 0)?;
+    }
+    if let Some(checkpoint) = args.get("checkpoint") {
+        gradient.checkpoint = Some(String::from(checkpoint));
+        gradient.checkpoint_every = args.number("checkpoint-every", gradient.checkpoint_every)?;
+    }
+    gradient.validate().map_err(|e| e.to_string())?;
+    Ok(config)
+}
+
+fn ngram_train(args: &Args) -> Result<(), String> {
+    let scoring = parse_scoring(args)?;
+    let o_config = learn_config(args)?;
+    let training_data = load_csv(args, args.required("train")?)?;
+    let model_file = args.required("model")?;
+    let mut ngram = NGram::new(&training_data, args.number("max-grams", 3)?);
+    ngram.scoring = scoring;
+
+    let mut result = object!{ model: model_file, inputs: training_data.len() };
+    if let Some(config) = o_config {
+        // learning only sees the training data, the validation data stays held out
+        let report = ngram.learn(&training_data, Some(config));
+        result["accuracy_before"] = report.accuracy_before.into();
+        result["accuracy_after"] = report.accuracy_after.into();
+        result["removed_per_order"] = report.removed_per_order().into();
+    }
+    let mut text = format!("Trained on {} inputs, saved to {}", training_data.len(), model_file);
+    if let Some(file) = args.get("validation") {
+        let report = NGram::evaluate(&ngram.ngram_maps, &load_csv(args, file)?, &ngram.scoring);
+        result["validation_accuracy"] = report.accuracy.into();
+        text = format!("{}\nValidation accuracy: {:.4}", text, report.accuracy);
+    }
+    ngram.save(model_file);
+    output(args, result, text);
+    Ok(())
+}
+
+fn shard_input(args: &Args, input: Vec<InputTup>) -> Result<Vec<InputTup>, String> {
+    Ok(match args.shard()? {
+        Some((index, count)) => shard(&input, index, count).to_vec(),
+        None => input
+    })
+}
+
+fn ngram_count(args: &Args) -> Result<(), String> {
+    let training_data = shard_input(args, load_csv(args, args.required("train")?)?)?;
+    let output_file = args.required("output")?;
+    let counts = NgramCounts::count(&training_data, args.number("max-grams", 3)?);
+    counts.save(output_file);
+    output(args, object!{ counts: output_file, inputs: training_data.len() }, format!("Counted {} inputs, saved to {}", training_data.len(), output_file));
+    Ok(())
+}
+
+fn ngram_merge(args: &Args) -> Result<(), String> {
+    let count_files = args.list("counts")?;
+    let model_file = args.required("model")?;
+    let mut ngram = NgramCounts::merge_files(&count_files).to_ngram();
+    ngram.scoring = parse_scoring(args)?;
+    ngram.save(model_file);
+    output(args, object!{ model: model_file, shards: count_files.len() }, format!("Merged {} count files, saved to {}", count_files.len(), model_file));
+    Ok(())
+}
+
+fn ngram_eval(args: &Args) -> Result<(), String> {
+    let ngram = NGram::load(args.required("model")?);
+    let data = load_csv(args, args.required("data")?)?;
+    let report = NGram::evaluate(&ngram.ngram_maps, &data, &ngram.scoring);
+    output(args, report.to_json(), report.to_string());
+    Ok(())
+}
+
+fn ngram_classify(args: &Args) -> Result<(), String> {
+    let ngram = NGram::load(args.required("model")?);
+    let mut results = Vec::new();
+    let mut lines = Vec::new();
+    for (sentence, cleaned) in input_sentences(args)? {
+        let o_scores = ngram.classify(&cleaned);
+        let label = ngram.test_sentence(&cleaned);
+        let scores = o_scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(type_name, score)| object!{ label: type_name, score: score })
+            .collect_vec();
+        lines.push(format!("{}\t{}", label, sentence));
+        results.push(object!{ text: sentence, label: label, scores: scores });
+    }
+    output(args, JsonValue::Array(results), lines.join("\n"));
+    Ok(())
+}
+
+fn load_multi_label(args: &Args, file: &str) -> Result<Vec<MultiLabelInput>, String> {
+    Ok(split_labels(load_csv(args, file)?, args.get("label-separator").unwrap_or("|")))
+}
+
+fn multilabel_train(args: &Args) -> Result<(), String> {
+    let training_data = load_multi_label(args, args.required("train")?)?;
+    let model_file = args.required("model")?;
+    let mut model = MultiLabelNGram::train(&training_data, args.number("max-grams", 3)?, parse_scoring(args)?);
+    if let Some(tune_file) = args.get("tune") {
+        model.tune_thresholds(&load_multi_label(args, tune_file)?);
+    }
+    model.save(model_file);
+    let thresholds = model.models.iter().map(|m| object!{ label: m.label.clone(), threshold: m.threshold }).collect_vec();
+    let text = model.models.iter().map(|m| format!("{}\t{}", m.label, m.threshold)).join("\n");
+    output(args, object!{ model: model_file, inputs: training_data.len(), thresholds: thresholds }, format!("Trained {} labels on {} inputs, saved to {}\n{}", model.models.len(), training_data.len(), model_file, text));
+    Ok(())
+}
+
+fn load_label_tree(args: &Args) -> Result<Option<(LabelTree, f64)>, String> {
+    match args.get("tree") {
+        Some(file) => Ok(Some((LabelTree::load(file), args.number("prior-weight", 1.0)?))),
+        None => Ok(None)
+    }
+}
+
+fn multilabel_eval(args: &Args) -> Result<(), String> {
+    let model = MultiLabelNGram::load(args.required("model")?);
+    let data = load_multi_label(args, args.required("data")?)?;
+    let report = match load_label_tree(args)? {
+        Some((tree, prior_weight)) => model.evaluate_with_tree(&tree, &data, prior_weight),
+        None => model.evaluate(&data)
+    };
+    output(args, report.to_json(), report.to_string());
+    Ok(())
+}
+
+fn multilabel_classify(args: &Args) -> Result<(), String> {
+    let model = MultiLabelNGram::load(args.required("model")?);
+    let tree = load_label_tree(args)?;
+    let mut results = Vec::new();
+    let mut lines = Vec::new();
+    for (sentence, cleaned) in input_sentences(args)? {
+        let labels = match &tree {
+            Some((tree, prior_weight)) => model.predict_with_tree(tree, &cleaned, *prior_weight),
+            None => model.predict(&cleaned)
+        };
+        let scores = model.scores(&cleaned).into_iter().map(|(label, score)| object!{ label: label, score: score }).collect_vec();
+        lines.push(format!("{}\t{}", labels.join("|"), sentence));
+        results.push(object!{ text: sentence, labels: labels, scores: scores });
+    }
+    output(args, JsonValue::Array(results), lines.join("\n"));
+    Ok(())
+}
+
+fn multilabel_hierarchy(args: &Args) -> Result<(), String> {
+    let training_data = load_multi_label(args, args.required("train")?)?;
+    let labels = training_data
+        .iter()
+        .flat_map(|(labels, _)| labels.iter().cloned())
+        .sorted()
+        .dedup()
+        .collect_vec();
+    let stats = LabelCooccurrence::from_input(&training_data, &labels);
+    let tree = LabelTree::chow_liu(&stats, smoothing(args)?);
+    if let Some(file) = args.get("output") {
+        tree.save(file);
+    }
+    let implications = stats.implications()
+        .into_iter()
+        .map(|(label, other, p)| object!{ label: label, other: other, probability: p })
+        .collect_vec();
+    output(args, object!{ tree: tree.to_json(), implications: implications }, tree.to_string());
+    Ok(())
+}
+
+fn markov_train(args: &Args) -> Result<(), String> {
+    let text_file = args.required("text")?;
+    let model_file = args.required("model")?;
+    let input_data = markov_input(args, text_file)?;
+    let mut mc = MarkovChain::new();
+    mc.states = match args.get("checkpoint") {
+        Some(checkpoint) => MarkovChain::train_checkpointed(&input_data, checkpoint, args.number("checkpoint-every", 1000000)?),
+        None => MarkovChain::train(input_data)
+    };
+    mc.save(model_file);
+    output(args, object!{ model: model_file, states: mc.states.len() }, format!("Trained {} states, saved to {}", mc.states.len(), model_file));
+    Ok(())
+}
+
+// Transitions of the text file, words outside --vocab become <UNK>
+fn markov_input(args: &Args, text_file: &str) -> Result<Vec<InputTup>, String> {
+    let pipeline = TextPipeline::new(&args.tokenizer()?, &args.normalizers()?, StopWords::new());
+    let input_data = get_markov_data_with(text_file, &pipeline);
+    Ok(match args.vocabulary()? {
+        Some(vocabulary) => input_data
+            .into_iter()
+            .map(|(from, to)| (vocabulary.map_token(&from), vocabulary.map_token(&to)))
+            .collect_vec(),
+        None => input_data
+    })
+}
+
+fn markov_count(args: &Args) -> Result<(), String> {
+    let text_file = args.required("text")?;
+    let output_file = args.required("output")?;
+    let input_data = shard_input(args, markov_input(args, text_file)?)?;
+    MarkovChain::save_counts(&MarkovChain::count(&input_data), output_file);
+    output(args, object!{ counts: output_file, transitions: input_data.len() }, format!("Counted {} transitions, saved to {}", input_data.len(), output_file));
+    Ok(())
+}
+
+fn markov_merge(args: &Args) -> Result<(), String> {
+    let count_files = args.list("counts")?;
+    let model_file = args.required("model")?;
+    let mut mc = MarkovChain::new();
+    mc.states = MarkovChain::states_from_totals(&MarkovChain::merge_count_files(&count_files));
+    mc.save(model_file);
+    output(args, object!{ model: model_file, states: mc.states.len() }, format!("Merged {} count files into {} states, saved to {}", count_files.len(), mc.states.len(), model_file));
+    Ok(())
+}
+
+fn markov_generate(args: &Args) -> Result<(), String> {
+    let mc = MarkovChain::load(args.required("model")?);
+    let start = String::from(args.required("start")?);
+    let length = args.number("length", 20)?;
+    // sampling with a seed, otherwise always the most likely next word
+    let words = match args.get("seed") {
+        Some(_) => mc.sample(&start, length, &mut seeded_rng(args.number("seed", 0)?)),
+        None => mc.generate(&start, length)
+    };
+    output(args, object!{ words: words.clone() }, words.join(" "));
+    Ok(())
+}
+
+fn hmm_train(args: &Args) -> Result<(), String> {
+    let data_file = args.required("data")?;
+    let model_file = args.required("model")?;
+    let input = fs::read_to_string(data_file)
+        .map_err(|e| format!("Error reading {}: {}", data_file, e))?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.split_once('\t') {
+            Some((state, obs)) => Ok((String::from(state), String::from(obs))),
+            None => Err(format!("Expected state<TAB>observation, got: {}", line))
+        })
+        .collect::<Result<Vec<InputTup>, String>>()?;
+    if input.is_empty() {
+        return Err(format!("No training data in {}", data_file));
+    }
+    let hmm = match args.vocabulary()? {
+        Some(vocabulary) => HiddenMarkovModel::train_with_vocabulary(input, &vocabulary),
+        None => HiddenMarkovModel::train(input)
+    };
+    hmm.save(model_file);
+    let num_states = hmm.initial_probabilities.len();
+    output(args, object!{ model: model_file, states: num_states }, format!("Trained {} states, saved to {}", num_states, model_file));
+    Ok(())
+}
+
+fn hmm_sample(args: &Args) -> Result<(), String> {
+    let hmm = HiddenMarkovModel::load(args.required("model")?);
+    let sequence = hmm.sample(args.number("length", 20)?, &mut seeded_rng(args.number("seed", 0)?));
+    let json = sequence.iter().map(|(state, obs)| object!{ state: state.clone(), observation: obs.clone() }).collect_vec();
+    output(args, JsonValue::Array(json), sequence.iter().map(|(state, obs)| format!("{}/{}", obs, state)).join(" "));
+    Ok(())
+}
+
+fn hmm_tag(args: &Args) -> Result<(), String> {
+    let hmm = HiddenMarkovModel::load(args.required("model")?);
+    let vocabulary = args.vocabulary()?;
+    let mut results = Vec::new();
+    let mut lines = Vec::new();
+    for (sentence, _) in input_sentences(args)? {
+        let observations = sentence.split_whitespace().map(String::from).collect_vec();
+        let mapped = match &vocabulary {
+            Some(vocabulary) => observations.iter().map(|obs| vocabulary.map_token(obs)).collect_vec(),
+            None => observations.clone()
+        };
+        let states = hmm.tag(&mapped);
+        lines.push(observations.iter().zip(&states).map(|(obs, state)| format!("{}/{}", obs, state)).join(" "));
+        results.push(object!{ observations: observations, states: states });
+    }
+    output(args, JsonValue::Array(results), lines.join("\n"));
+    Ok(())
+}
+
+// Applies --top and --min-count, saves to --output and prints the words
+fn vocab_output(args: &Args, vocabulary: Vocabulary, default_top: usize) -> Result<(), String> {
+    let vocabulary = vocabulary
+        .min_count(args.number("min-count", 1)?)
+        .top(args.number("top", default_top)?);
+    if let Some(out_file) = args.get("output") {
+        vocabulary.save(out_file);
+    }
+    let ranked = vocabulary.ranked();
+    let json = ranked.iter().map(|(wd, count)| object!{ word: wd.clone(), count: *count }).collect_vec();
+    output(args, JsonValue::Array(json), ranked.iter().map(|(wd, _)| wd).join("\n"));
+    Ok(())
+}
+
+// word,count csv -> most frequent words
+fn vocab_top_words(args: &Args) -> Result<(), String> {
+    vocab_output(args, Vocabulary::from_counts_csv(args.required("input")?)?, 10000)
+}
+
+// token counts of a text corpus
+fn vocab_build(args: &Args) -> Result<(), String> {
+    let pipeline = TextPipeline::new(&args.tokenizer()?, &args.normalizers()?, args.stop_words()?.load());
+    vocab_output(args, Vocabulary::from_corpus(args.required("input")?, &pipeline), usize::MAX)
+}
+
+fn experiment(args: &Args) -> Result<(), String> {
+    let file = args.positional.get(1).ok_or("Missing experiment file")?;
+    let result = run_experiment_file(file).map_err(|e| e.to_string())?;
+    output(args, result.to_json(), result.to_string());
+    Ok(())
+}
+
+fn learn_net_run(args: &Args) -> Result<(), String> {
+    let manifest = Manifest::load(args.required("manifest")?).map_err(|e| e.to_string())?;
+    let listener = TcpListener::bind(args.get("listen").unwrap_or("127.0.0.1:7878")).map_err(|e| e.to_string())?;
+    let address = listener.local_addr().map_err(|e| e.to_string())?.to_string();
+
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    let mut local_workers = Vec::new();
+    for i in 0..args.number("local-workers", 0)? {
+        let mut command = Command::new(&exe);
+        command.args(["learn-net", "worker", "--connect", &address, "--name", &format!("local-{}", i)]);
+        if args.flags.contains("quiet") {
+            command.arg("--quiet");
+        }
+        local_workers.push(command.spawn().map_err(|e| format!("Error starting local worker: {}", e))?);
+    }
+
+    let report = run_coordinator(&manifest, listener).map_err(|e| e.to_string());
+    for mut worker in local_workers {
+        let _ = worker.wait();
+    }
+    let report = report?;
+    output(args, report.to_json(), report.to_string());
+    match report.failed() {
+        0 => Ok(()),
+        failed => Err(format!("{} of {} jobs failed", failed, report.jobs.len()))
+    }
+}
+
+fn learn_net_worker(args: &Args) -> Result<(), String> {
+    let address = args.required("connect")?;
+    let default_name = format!("worker-{}", process::id());
+    let name = args.get("name").unwrap_or(&default_name);
+    let jobs_run = run_worker(address, name).map_err(|e| format!("Worker {}: {}", name, e))?;
+    output(args, object!{ worker: name, jobs: jobs_run }, format!("Worker {} ran {} jobs", name, jobs_run));
+    Ok(())
+}
+
+fn vectorize(args: &Args) -> Result<(), String> {
+    let output_file = args.required("output")?;
+    let format = args.get("format").unwrap_or("mtx");
+    if !["mtx", "csv"].contains(&format) {
+        return Err(format!("--format must be mtx or csv, got {}", format));
+    }
+    let weighting = args.get("weighting").unwrap_or("tf_idf");
+    let max_features = match args.get("max-features") {
+        Some(_) => Some(args.number("max-features", 0)?),
+        None => None
+    };
+    let config = VectorizerConfig {
+        max_grams: args.number("max-grams", 1)?,
+        weighting: Weighting::parse(weighting).ok_or(format!("Unknown weighting: {}", weighting))?,
+        sublinear_tf: args.flags.contains("sublinear"),
+        normalize: !args.flags.contains("no-normalize"),
+        min_df: args.number("min-df", 1)?,
+        max_features
+    };
+    if config.max_grams < 1 {
+        return Err(String::from("--max-grams must be at least 1"));
+    }
+    let input = load_csv(args, args.required("input")?)?;
+    let (labels, documents): (Vec<String>, Vec<String>) = input.into_iter().unzip();
+    let vectorizer = match args.get("vectorizer") {
+        Some(file) => Vectorizer::load(file),
+        None => Vectorizer::fit(&documents, &config)
+    };
+    if let Some(file) = args.get("save-vectorizer") {
+        vectorizer.save(file);
+    }
+    let matrix = vectorizer.transform(&documents);
+    let file = fs::File::create(output_file).map_err(|e| format!("Error creating {}: {}", output_file, e))?;
+    let written = if format.eq("csv") {
+        matrix.write_csv(file, &vectorizer.terms, Some(&labels)).map_err(|e| e.to_string())
+    } else {
+        matrix.write_matrix_market(&mut std::io::BufWriter::new(file)).map_err(|e| e.to_string())
+    };
+    written.map_err(|e| format!("Error writing {}: {}", output_file, e))?;
+    if let Some(file) = args.get("labels") {
+        fs::write(file, labels.iter().map(|label| format!("{}\n", label)).join("")).map_err(|e| format!("Error writing {}: {}", file, e))?;
+    }
+    output(
+        args,
+        object!{ output: output_file, documents: matrix.rows.len(), terms: matrix.columns, non_zero: matrix.non_zero(), weighting: vectorizer.config.weighting.to_string() },
+        format!("Wrote {} documents x {} terms ({} non zero, {}) to {}", matrix.rows.len(), matrix.columns, matrix.non_zero(), vectorizer.config.weighting, output_file)
+    );
+    Ok(())
+}
+
+// args without the program name
+pub fn run(args: &[String]) -> Result<(), String> {
+    let parsed = Args::parse(args)?;
+    set_num_threads(parsed.number("threads", 0)?);
+    if !parsed.flags.contains("quiet") {
+        set_event_sink(Some(Arc::new(StderrSink)));
+    }
+    let command = parsed.positional.iter().map(|s| s.as_str()).take(2).collect_vec();
+    match command.as_slice() {
+        ["ngram", "train"] => ngram_train(&parsed),
+        ["ngram", "count"] => ngram_count(&parsed),
+        ["ngram", "merge"] => ngram_merge(&parsed),
+        ["ngram", "eval"] => ngram_eval(&parsed),
+        ["ngram", "classify"] => ngram_classify(&parsed),
+        ["markov", "train"] => markov_train(&parsed),
+        ["markov", "count"] => markov_count(&parsed),
+        ["markov", "merge"] => markov_merge(&parsed),
+        ["markov", "generate"] => markov_generate(&parsed),
+        ["hmm", "train"] => hmm_train(&parsed),
+        ["hmm", "tag"] => hmm_tag(&parsed),
+        ["hmm", "sample"] => hmm_sample(&parsed),
+        ["vocab", "top-words"] => vocab_top_words(&parsed),
+        ["vocab", "build"] => vocab_build(&parsed),
+        ["multilabel", "train"] => multilabel_train(&parsed),
+        ["multilabel", "eval"] => multilabel_eval(&parsed),
+        ["multilabel", "classify"] => multilabel_classify(&parsed),
+        ["multilabel", "hierarchy"] => multilabel_hierarchy(&parsed),
+        ["learn-net", "run"] => learn_net_run(&parsed),
+        ["learn-net", "worker"] => learn_net_worker(&parsed),
+        ["vectorize", ..] => vectorize(&parsed),
+        ["experiment", ..] => experiment(&parsed),
+        _ => Err(String::from(USAGE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Args {
+        Args::parse(&s.split(' ').map(String::from).collect_vec()).unwrap()
+    }
+
+    #[test]
+    fn parses_positionals_options_and_flags() {
+        let args = args("ngram train --train t.csv --json --max-grams 2 --quiet");
+        assert_eq!(args.positional, vec!["ngram", "train"]);
+        assert_eq!(args.get("train"), Some("t.csv"));
+        assert_eq!(args.number("max-grams", 3), Ok(2));
+        assert_eq!(args.number("threads", 4), Ok(4));
+        assert!(args.json() && args.flags.contains("quiet"));
+        assert!(args.number::<usize>("train", 0).is_err());
+        assert_eq!(args.required("model").err(), Some(String::from("Missing required option --model")));
+    }
+
+    #[test]
+    fn option_without_value() {
+        let error = Args::parse(&[String::from("ngram"), String::from("--model")]).err();
+        assert_eq!(error, Some(String::from("Missing value for --model")));
+        // a flag is not taken as the value of the option before it
+        assert_eq!(args("--text --json").get("text"), Some("--json"));
+    }
+
+    #[test]
+    fn shards() {
+        assert_eq!(args("ngram count").shard(), Ok(None));
+        assert_eq!(args("--shard 0/2").shard(), Ok(Some((0, 2))));
+        assert_eq!(args("--shard 1/2").shard(), Ok(Some((1, 2))));
+        for shard in ["1/0", "2/2", "a/b", "1", "-1/2"] {
+            assert!(args(&format!("--shard {}", shard)).shard().is_err(), "{}", shard);
+        }
+    }
+
+    #[test]
+    fn scoring_modes() {
+        assert_eq!(parse_scoring(&args("ngram train")), Ok(ScoringMode::Voting));
+        assert_eq!(parse_scoring(&args("--scoring naive_bayes")), Ok(ScoringMode::NaiveBayes(1.0)));
+        assert_eq!(parse_scoring(&args("--scoring naive_bayes --smoothing 0.5")), Ok(ScoringMode::NaiveBayes(0.5)));
+        assert!(parse_scoring(&args("--scoring naive_bayes --smoothing 0")).is_err());
+        assert!(parse_scoring(&args("--smoothing -1")).is_err());
+        assert!(parse_scoring(&args("--scoring linear")).is_err());
+        assert!(parse_scoring(&args("--scoring best")).is_err());
+    }
+
+    #[test]
+    fn csv_options() {
+        let config = csv_config(&args("--label-column 0 --text-column text --delimiter ; --no-headers --no-quoting --exclude-labels a,b --include-labels c")).unwrap();
+        assert_eq!(config.label, ColumnSelector::Index(0));
+        assert_eq!(config.text, ColumnSelector::Name(String::from("text")));
+        assert_eq!(config.delimiter, b';');
+        assert!(!config.has_headers && !config.quoting);
+        assert_eq!(config.exclude_labels, vec!["a", "b"]);
+        assert_eq!(config.include_labels, vec!["c"]);
+
+        assert_eq!(csv_config(&args("ngram train")).unwrap(), CsvInputConfig::default());
+        assert!(csv_config(&args("--delimiter ab")).is_err());
+        assert!(csv_config(&args("--stop-word-languages klingon")).is_err());
+        assert!(csv_config(&args("--tokenizer unknown")).is_err());
+    }
+
+    #[test]
+    fn gradient_options_need_gradient_learning() {
+        for option in ["--seed 1", "--checkpoint g.ckpt"] {
+            let error = learn_config(&args(&format!("ngram train {}", option))).err().unwrap();
+            assert!(error.contains("only applies to gradient learning"), "{}", error);
+        }
+        assert_eq!(learn_config(&args("ngram train")), Ok(None));
+
+        let config_file = env::temp_dir().join(format!("cli_gradient_test_{}.json", process::id())).to_string_lossy().to_string();
+        fs::write(&config_file, r#"{"gradient": {"seed": 3}}"#).unwrap();
+        let config = learn_config(&args(&format!("--config {} --seed 9 --checkpoint g.ckpt --checkpoint-every 2", config_file)));
+        let invalid = learn_config(&args(&format!("--config {} --checkpoint g.ckpt --checkpoint-every 0", config_file)));
+        fs::write(&config_file, r#"{"count": {}}"#).unwrap();
+        let without_gradient = learn_config(&args(&format!("--config {} --seed 9", config_file)));
+        fs::remove_file(&config_file).unwrap();
+
+        let gradient = config.unwrap().unwrap().gradient.unwrap();
+        assert_eq!((gradient.seed, gradient.checkpoint.as_deref(), gradient.checkpoint_every), (9, Some("g.ckpt"), 2));
+        assert!(invalid.is_err());
+        assert!(without_gradient.is_err());
+    }
+}
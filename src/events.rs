@@ -0,0 +1,164 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/*
+Progress and timing reports from long running work.
+Nothing is reported until a sink is set, embedding the crate stays silent by default.
+*/
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    // a named phase of work, like "feed" or "prune probability"
+    PhaseStarted(String),
+    PhaseFinished { phase: String, elapsed: Duration },
+    // lines, states or inputs processed, total when it is known
+    Progress { task: String, done: usize, total: Option<usize> },
+    // accuracy measured by a learning step
+    Accuracy { step: String, accuracy: f32 },
+    // a named number like removed grams or the current min probability
+    Metric { name: String, value: f64 },
+    Message(String)
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::PhaseStarted(phase) => write!(f, "{}: started", phase),
+            Event::PhaseFinished { phase, elapsed } => write!(f, "{}: finished in {:?}", phase, elapsed),
+            Event::Progress { task, done, total: Some(total) } => write!(f, "{}: {} of {}", task, done, total),
+            Event::Progress { task, done, total: None } => write!(f, "{}: {}", task, done),
+            Event::Accuracy { step, accuracy } => write!(f, "{}: accuracy {:.2}%", step, accuracy * 100.0),
+            Event::Metric { name, value } => write!(f, "{}: {}", name, value),
+            Event::Message(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+pub trait EventSink: Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+// One line per event on stderr, keeps stdout free for results
+pub struct StderrSink;
+
+impl EventSink for StderrSink {
+    fn event(&self, event: &Event) {
+        let _ = writeln!(io::stderr(), "{}", event);
+    }
+}
+
+// Keeps every event, for callers that inspect them afterwards
+pub struct CollectSink {
+    pub events: Mutex<Vec<Event>>
+}
+
+impl Default for CollectSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CollectSink {
+    pub fn new() -> CollectSink {
+        CollectSink { events: Mutex::new(Vec::new()) }
+    }
+}
+
+impl EventSink for CollectSink {
+    fn event(&self, event: &Event) {
+        self.events.lock().expect("Event sink lock poisoned").push(event.clone());
+    }
+}
+
+static SINK: RwLock<Option<Arc<dyn EventSink>>> = RwLock::new(None);
+
+// Where every event in the crate goes, None silences them again
+pub fn set_event_sink(sink: Option<Arc<dyn EventSink>>) {
+    *SINK.write().expect("Event sink lock poisoned") = sink;
+}
+
+pub fn enabled() -> bool {
+    SINK.read().expect("Event sink lock poisoned").is_some()
+}
+
+pub fn emit(event: Event) {
+    if let Some(sink) = SINK.read().expect("Event sink lock poisoned").as_ref() {
+        sink.event(&event);
+    }
+}
+
+pub fn metric(name: &str, value: f64) {
+    emit(Event::Metric { name: String::from(name), value });
+}
+
+pub fn accuracy(step: &str, accuracy: f32) {
+    emit(Event::Accuracy { step: String::from(step), accuracy });
+}
+
+pub fn progress(task: &str, done: usize, total: Option<usize>) {
+    emit(Event::Progress { task: String::from(task), done, total });
+}
+
+pub fn message(message: &str) {
+    emit(Event::Message(String::from(message)));
+}
+
+// Reports a phase that started at start and just finished
+pub fn phase_finished(phase: &str, start: Instant) {
+    emit(Event::PhaseFinished { phase: String::from(phase), elapsed: start.elapsed() });
+}
+
+// Runs f as a named phase and reports how long it took
+pub fn timed<T>(phase: &str, f: impl FnOnce() -> T) -> T {
+    emit(Event::PhaseStarted(String::from(phase)));
+    let start = Instant::now();
+    let ret_val = f();
+    phase_finished(phase, start);
+    ret_val
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Events other tests emit meanwhile can reach the sink too, so only these are checked
+    fn received(sink: &CollectSink, name: &str) -> Vec<Event> {
+        sink.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, Event::Metric { name: n, .. } if n == name) || **event == Event::PhaseStarted(String::from(name)))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn only_a_set_sink_receives_events() {
+        assert!(!enabled());
+        metric("events test before", 1.0);
+        let sink = Arc::new(CollectSink::new());
+        set_event_sink(Some(sink.clone()));
+        assert!(enabled());
+        metric("events test during", 2.0);
+        let ret_val = timed("events test phase", || 3);
+        set_event_sink(None);
+        metric("events test after", 4.0);
+        assert!(!enabled());
+
+        assert_eq!(ret_val, 3);
+        assert!(received(&sink, "events test before").is_empty());
+        assert_eq!(received(&sink, "events test during"), vec![Event::Metric { name: String::from("events test during"), value: 2.0 }]);
+        assert_eq!(received(&sink, "events test phase").len(), 1);
+        assert!(sink.events.lock().unwrap().iter().any(|event| matches!(event, Event::PhaseFinished { phase, .. } if phase == "events test phase")));
+        assert!(received(&sink, "events test after").is_empty());
+    }
+
+    #[test]
+    fn events_display_as_one_line() {
+        assert_eq!(Event::Accuracy { step: String::from("prune"), accuracy: 0.5 }.to_string(), "prune: accuracy 50.00%");
+        assert_eq!(Event::Progress { task: String::from("feed"), done: 2, total: Some(10) }.to_string(), "feed: 2 of 10");
+        assert_eq!(Event::Progress { task: String::from("feed"), done: 2, total: None }.to_string(), "feed: 2");
+    }
+}
@@ -0,0 +1,402 @@
+use std::{fs, fmt};
+use json::{JsonValue, object, parse};
+
+use crate::events;
+use crate::metrics::ClassificationReport;
+use crate::n_gram::{NGram, ScoringMode};
+use crate::n_gram::config::{ConfigError, LearnConfig, check_keys, get_bool, get_f64, get_i32, get_str, get_u64, out_of_range};
+use crate::n_gram::cross_validation::CrossValidationReport;
+use crate::n_gram::learn::PruningReport;
+use crate::text::{NormalizerConfig, TokenizerConfig};
+use crate::text::stop_words::{LANGUAGES, StopWordsConfig};
+use crate::util::{ColumnSelector, CsvInputConfig, InputTup, get_input_data_csv_with};
+
+/*
+Experiment file structure, runs the whole pipeline from data to saved model:
+only data.train is required, everything else falls back to the defaults below
+{
+    data: {
+        train: "data/twitter_training.csv",
+        validation: "data/twitter_validation.csv",
+        label_column: 2 | "label",
+        text_column: 3 | "text",
+        delimiter: ",",
+        quoting: true,
+        has_headers: true,
+        exclude_labels: ["Irrelevant"],
+        include_labels: [],
+        stop_words: "data/stop_words.txt",
+        stop_word_languages: ["english"]
+    },
+    tokenizer: { same structure as the tokenizer config in text/mod.rs },
+    normalizers: ["porter_stemmer", "lemmatizer(data/lemmas.txt)"],
+    ngram: {
+        max_grams: 3,
+        scoring: "voting" | "naive_bayes" | "linear", linear needs learn.gradient
+        smoothing: 1
+    },
+    learn: { same structure as the learn config in n_gram/config.rs },
+    evaluation: {
+        metrics: ["accuracy", "macro", "micro", "per_class", "confusion_matrix", "inconclusive"],
+        cross_validation: { folds: 5, seed: 0 }
+    },
+    output: {
+        model: "data/bow.dat",
+        report: "data/report.json"
+    }
+}
+Evaluation uses the validation data when it is set, otherwise cross validation
+when it is configured, otherwise the training data. Learning only uses the training data
+*/
+
+const METRICS: [&str; 6] = ["accuracy", "macro", "micro", "per_class", "confusion_matrix", "inconclusive"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataConfig {
+    pub train: String,
+    pub validation: Option<String>,
+    // how both files are read
+    pub csv: CsvInputConfig
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvaluationConfig {
+    // which parts of the report are printed
+    pub metrics: Vec<String>,
+    // (folds, seed)
+    pub cross_validation: Option<(usize, u64)>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputConfig {
+    pub model: Option<String>,
+    pub report: Option<String>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExperimentConfig {
+    pub data: DataConfig,
+    pub tokenizer: TokenizerConfig,
+    pub normalizers: Vec<NormalizerConfig>,
+    pub max_grams: i8,
+    pub scoring: ScoringMode,
+    pub learn: Option<LearnConfig>,
+    pub evaluation: EvaluationConfig,
+    pub output: OutputConfig
+}
+
+pub(crate) fn get_path(obj: &JsonValue, section: &str, key: &str) -> Result<Option<String>, ConfigError> {
+    Ok(get_str(obj, section, key)?.map(String::from))
+}
+
+fn get_column(obj: &JsonValue, section: &str, key: &str, def: ColumnSelector) -> Result<ColumnSelector, ConfigError> {
+    if !obj.has_key(key) { return Ok(def); }
+    if let Some(name) = obj[key].as_str() {
+        return Ok(ColumnSelector::Name(String::from(name)));
+    }
+    let column = get_i32(obj, section, key, 0)?;
+    if column < 0 {
+        return out_of_range(section, key, "at least 0");
+    }
+    Ok(ColumnSelector::Index(column as usize))
+}
+
+pub(crate) fn get_list(obj: &JsonValue, section: &str, key: &str, expected: &str) -> Result<Vec<String>, ConfigError> {
+    if !obj.has_key(key) { return Ok(Vec::new()); }
+    let wrong_type = ConfigError::WrongType(String::from(section), String::from(key), String::from(expected));
+    if !obj[key].is_array() {
+        return Err(wrong_type);
+    }
+    obj[key]
+        .members()
+        .map(|label| label.as_str().map(String::from).ok_or(wrong_type.clone()))
+        .collect()
+}
+
+impl DataConfig {
+    pub fn from_json(obj: &JsonValue) -> Result<DataConfig, ConfigError> {
+        let data_s = "data";
+        check_keys(obj, data_s, &[
+            "train", "validation", "label_column", "text_column", "delimiter",
+            "quoting", "has_headers", "exclude_labels", "include_labels", "stop_words", "stop_word_languages"
+        ])?;
+        let train = match get_path(obj, data_s, "train")? {
+            Some(train) => train,
+            None => return out_of_range(data_s, "train", "set to the training data file")
+        };
+
+        let def = CsvInputConfig::default();
+        let delimiter = match get_str(obj, data_s, "delimiter")? {
+            None => def.delimiter,
+            Some(d) if d.len() == 1 => d.as_bytes()[0],
+            Some(_) => return out_of_range(data_s, "delimiter", "a single ascii character")
+        };
+        let csv = CsvInputConfig {
+            label: get_column(obj, data_s, "label_column", def.label)?,
+            text: get_column(obj, data_s, "text_column", def.text)?,
+            delimiter,
+            quoting: get_bool(obj, data_s, "quoting", def.quoting)?,
+            has_headers: get_bool(obj, data_s, "has_headers", def.has_headers)?,
+            exclude_labels: get_list(obj, data_s, "exclude_labels", "a list of labels")?,
+            include_labels: get_list(obj, data_s, "include_labels", "a list of labels")?,
+            stop_words: StopWordsConfig {
+                languages: get_list(obj, data_s, "stop_word_languages", "a list of languages")?,
+                file: get_path(obj, data_s, "stop_words")?
+            },
+            tokenizer: def.tokenizer,
+            normalizers: def.normalizers
+        };
+        if !csv.stop_words.languages.iter().all(|l| LANGUAGES.contains(&l.as_str())) {
+            return out_of_range(data_s, "stop_word_languages", &format!("one of {}", LANGUAGES.join(", ")));
+        }
+        let by_name = matches!(csv.label, ColumnSelector::Name(_)) || matches!(csv.text, ColumnSelector::Name(_));
+        if by_name && !csv.has_headers {
+            return out_of_range(data_s, "has_headers", "true when columns are selected by name");
+        }
+
+        Ok(DataConfig {
+            train,
+            validation: get_path(obj, data_s, "validation")?,
+            csv
+        })
+    }
+}
+
+impl Default for EvaluationConfig {
+    fn default() -> EvaluationConfig {
+        EvaluationConfig {
+            metrics: METRICS.iter().map(|m| String::from(*m)).collect(),
+            cross_validation: None
+        }
+    }
+}
+
+impl EvaluationConfig {
+    pub fn from_json(obj: &JsonValue) -> Result<EvaluationConfig, ConfigError> {
+        let evaluation_s = "evaluation";
+        check_keys(obj, evaluation_s, &["metrics", "cross_validation"])?;
+        let mut config = EvaluationConfig::default();
+        if obj.has_key("metrics") {
+            if !obj["metrics"].is_array() {
+                return Err(ConfigError::WrongType(String::from(evaluation_s), String::from("metrics"), String::from("a list of metric names")));
+            }
+            let mut metrics = Vec::new();
+            for metric in obj["metrics"].members() {
+                match metric.as_str() {
+                    Some(m) if METRICS.contains(&m) => metrics.push(String::from(m)),
+                    _ => return out_of_range(evaluation_s, "metrics", &METRICS.join(", "))
+                }
+            }
+            config.metrics = metrics;
+        }
+        if obj.has_key("cross_validation") {
+            let cv = &obj["cross_validation"];
+            let cv_s = "cross_validation";
+            check_keys(cv, cv_s, &["folds", "seed"])?;
+            let folds = get_i32(cv, cv_s, "folds", 5)?;
+            if folds < 2 {
+                return out_of_range(cv_s, "folds", "at least 2");
+            }
+            let seed = get_u64(cv, cv_s, "seed", 0)?;
+            config.cross_validation = Some((folds as usize, seed));
+        }
+        Ok(config)
+    }
+}
+
+fn scoring_from_json(obj: &JsonValue) -> Result<ScoringMode, ConfigError> {
+    let ngram_s = "ngram";
+    let smoothing = get_f64(obj, ngram_s, "smoothing", 1.0)?;
+    if smoothing <= 0.0 {
+        return out_of_range(ngram_s, "smoothing", "greater than 0");
+    }
+    match get_str(obj, ngram_s, "scoring")?.unwrap_or("voting") {
+        "voting" => Ok(ScoringMode::Voting),
+        "naive_bayes" => Ok(ScoringMode::NaiveBayes(smoothing)),
+        "linear" => Ok(ScoringMode::Linear),
+        _ => out_of_range(ngram_s, "scoring", "voting, naive_bayes or linear")
+    }
+}
+
+impl ExperimentConfig {
+    pub fn from_json(obj: &JsonValue) -> Result<ExperimentConfig, ConfigError> {
+        check_keys(obj, "experiment", &["data", "tokenizer", "normalizers", "ngram", "learn", "evaluation", "output"])?;
+        if !obj.has_key("data") {
+            return out_of_range("experiment", "data", "set");
+        }
+        let data = DataConfig::from_json(&obj["data"])?;
+
+        let tokenizer = if obj.has_key("tokenizer")
+            { TokenizerConfig::from_json(&obj["tokenizer"])? }
+            else { TokenizerConfig::Default };
+        let normalizers = if obj.has_key("normalizers")
+            { NormalizerConfig::list_from_json(&obj["normalizers"])? }
+            else { Vec::new() };
+
+        let ngram = &obj["ngram"];
+        let ngram_s = "ngram";
+        let mut max_grams = 3;
+        let mut scoring = ScoringMode::default();
+        if obj.has_key(ngram_s) {
+            check_keys(ngram, ngram_s, &["max_grams", "scoring", "smoothing"])?;
+            max_grams = get_i32(ngram, ngram_s, "max_grams", max_grams)?;
+            if max_grams < 1 || max_grams > i8::MAX as i32 {
+                return out_of_range(ngram_s, "max_grams", "between 1 and 127");
+            }
+            scoring = scoring_from_json(ngram)?;
+        }
+
+        let learn = if obj.has_key("learn")
+            { Some(LearnConfig::from_json(&obj["learn"])?) }
+            else { None };
+        // linear scoring reads gradient weights, which only gradient learning writes
+        if scoring == ScoringMode::Linear && learn.as_ref().is_none_or(|learn| learn.gradient.is_none()) {
+            return out_of_range(ngram_s, "scoring", "voting or naive_bayes, linear needs a learn.gradient section");
+        }
+
+        let evaluation = if obj.has_key("evaluation")
+            { EvaluationConfig::from_json(&obj["evaluation"])? }
+            else { EvaluationConfig::default() };
+
+        let output_s = "output";
+        let mut output = OutputConfig { model: None, report: None };
+        if obj.has_key(output_s) {
+            check_keys(&obj[output_s], output_s, &["model", "report"])?;
+            output.model = get_path(&obj[output_s], output_s, "model")?;
+            output.report = get_path(&obj[output_s], output_s, "report")?;
+        }
+
+        Ok(ExperimentConfig { data, tokenizer, normalizers, max_grams: max_grams as i8, scoring, learn, evaluation, output })
+    }
+
+    pub fn read(file_name: &str) -> Result<ExperimentConfig, ConfigError> {
+        let file_contents = fs::read_to_string(file_name).map_err(|e| ConfigError::Io(format!("{}: {}", file_name, e)))?;
+        let json_data = parse(&file_contents).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        ExperimentConfig::from_json(&json_data)
+    }
+}
+
+pub enum ExperimentEvaluation {
+    Holdout(ClassificationReport),
+    CrossValidation(CrossValidationReport)
+}
+
+pub struct ExperimentResult {
+    pub model: NGram,
+    pub pruning: Option<PruningReport>,
+    pub evaluation: ExperimentEvaluation,
+    // the metrics selected in the config, used when printing
+    pub metrics: Vec<String>
+}
+
+impl ExperimentResult {
+    pub fn to_json(&self) -> JsonValue {
+        let mut obj = object!{};
+        obj["evaluation"] = match &self.evaluation {
+            ExperimentEvaluation::Holdout(report) => report.to_json(),
+            ExperimentEvaluation::CrossValidation(report) => report.to_json()
+        };
+        if let Some(pruning) = &self.pruning {
+            obj["pruning"] = object!{
+                accuracy_before: pruning.accuracy_before,
+                accuracy_after: pruning.accuracy_after,
+                removed_per_order: pruning.removed_per_order()
+            };
+        }
+        obj
+    }
+}
+
+impl fmt::Display for ExperimentResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(pruning) = &self.pruning {
+            writeln!(f, "{}\n", pruning)?;
+        }
+        let report = match &self.evaluation {
+            ExperimentEvaluation::CrossValidation(report) => return write!(f, "{}", report),
+            ExperimentEvaluation::Holdout(report) => report
+        };
+        let show = |m: &str| self.metrics.iter().any(|s| s == m);
+        if show("confusion_matrix") || show("per_class") {
+            writeln!(f, "{}\n", report)?;
+        }
+        if show("accuracy") {
+            writeln!(f, "Accuracy: {:.4}", report.accuracy)?;
+        }
+        if show("macro") {
+            writeln!(f, "Macro precision {:.4} recall {:.4} f1 {:.4}", report.macro_avg.precision, report.macro_avg.recall, report.macro_avg.f1)?;
+        }
+        if show("micro") {
+            writeln!(f, "Micro precision {:.4} recall {:.4} f1 {:.4}", report.micro_avg.precision, report.micro_avg.recall, report.micro_avg.f1)?;
+        }
+        if show("inconclusive") {
+            writeln!(f, "Inconclusive: {} of {}", report.inconclusive, report.total)?;
+        }
+        Ok(())
+    }
+}
+
+fn load_data(config: &ExperimentConfig, file: &str) -> Vec<InputTup> {
+    let mut csv = config.data.csv.clone();
+    csv.tokenizer = config.tokenizer.clone();
+    csv.normalizers = config.normalizers.clone();
+    get_input_data_csv_with(file, &csv).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Load data, train, learn, evaluate and save everything the config asks for
+pub fn run_experiment(config: &ExperimentConfig) -> ExperimentResult {
+    let training_data = events::timed("load training data", || load_data(config, &config.data.train));
+    let o_validation_data = config.data.validation.as_ref().map(|file| {
+        events::timed("load validation data", || load_data(config, file))
+    });
+
+    let mut model = events::timed("train", || NGram::new(&training_data, config.max_grams));
+    model.scoring = config.scoring;
+
+    // learning only sees the training data so the validation data stays an untouched test set
+    let pruning = config.learn.clone().map(|learn_config| events::timed("learn", || model.learn(&training_data, Some(learn_config))));
+
+    let evaluation = match (&o_validation_data, config.evaluation.cross_validation) {
+        (Some(validation_data), _) => ExperimentEvaluation::Holdout(NGram::evaluate(&model.ngram_maps, validation_data, &model.scoring)),
+        (None, Some((folds, seed))) => ExperimentEvaluation::CrossValidation(NGram::cross_validate_with(&training_data, folds, seed, &|fold_data| {
+            let mut fold_model = NGram::new(fold_data, config.max_grams);
+            fold_model.scoring = config.scoring;
+            if let Some(learn_config) = &config.learn {
+                fold_model.learn(fold_data, Some(learn_config.clone()));
+            }
+            fold_model
+        }).expect("Cross validation folds are checked by the config")),
+        (None, None) => ExperimentEvaluation::Holdout(NGram::evaluate(&model.ngram_maps, &training_data, &model.scoring))
+    };
+
+    let result = ExperimentResult { model, pruning, evaluation, metrics: config.evaluation.metrics.clone() };
+    if let Some(model_file) = &config.output.model {
+        result.model.save(model_file);
+    }
+    if let Some(report_file) = &config.output.report {
+        fs::write(report_file, result.to_json().pretty(4)).expect("Error writing report file");
+    }
+    result
+}
+
+pub fn run_experiment_file(file_name: &str) -> Result<ExperimentResult, ConfigError> {
+    Ok(run_experiment(&ExperimentConfig::read(file_name)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn experiment(s: &str) -> Result<ExperimentConfig, ConfigError> {
+        ExperimentConfig::from_json(&parse(s).unwrap())
+    }
+
+    #[test]
+    fn linear_scoring_needs_gradient_learning() {
+        let rejected = |s: &str| matches!(experiment(s), Err(ConfigError::OutOfRange(_, k, _)) if k == "scoring");
+        assert!(rejected(r#"{"data": {"train": "t.csv"}, "ngram": {"scoring": "linear"}}"#));
+        assert!(rejected(r#"{"data": {"train": "t.csv"}, "ngram": {"scoring": "linear"}, "learn": {"count": {}}}"#));
+        let config = experiment(r#"{"data": {"train": "t.csv"}, "ngram": {"scoring": "linear"}, "learn": {"gradient": {}}}"#).unwrap();
+        assert_eq!(config.scoring, ScoringMode::Linear);
+    }
+}
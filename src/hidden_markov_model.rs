@@ -1,9 +1,14 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
 
 use itertools::Itertools;
+use rand::Rng;
 
 use crate::markov_chain::MarkovChain;
+use crate::random::sample_weighted;
 use crate::util::InputTup;
+use crate::vocabulary::Vocabulary;
 
 pub struct HiddenMarkovModel {
     pub state_chain: MarkovChain, // State -> State -> Probability
@@ -41,6 +46,7 @@ impl HiddenMarkovModel {
         let initial_probabilities_groups = input
             .clone()
             .into_iter()
+            .sorted_by(|(state1, _), (state2, _)| state1.cmp(state2))
             .group_by(|(state, _)| state.to_owned());
 
         let total_inputs: f32 = input.len() as f32;
@@ -56,6 +62,32 @@ impl HiddenMarkovModel {
         HiddenMarkovModel { state_chain , observation_chain, initial_probabilities }
     }
 
+    // observations outside the vocabulary are trained as <UNK>,
+    // map tagged observations with the same vocabulary
+    pub fn train_with_vocabulary(input: Vec<InputTup>, vocabulary: &Vocabulary) -> HiddenMarkovModel {
+        let mapped = input
+            .into_iter()
+            .map(|(state, obs)| (state, vocabulary.map_token(&obs)))
+            .collect_vec();
+        HiddenMarkovModel::train(mapped)
+    }
+
+    // (state, observation) sequence drawn from the model
+    pub fn sample<R: Rng + ?Sized>(&self, length: usize, rng: &mut R) -> Vec<InputTup> {
+        let mut ret_val = Vec::new();
+        let mut o_state = sample_weighted(&self.initial_probabilities, rng);
+        while let Some(state) = o_state {
+            if ret_val.len() >= length { break; }
+            let obs = match self.observation_chain.sample_next(&state, rng) {
+                Some(obs) => obs,
+                None => break
+            };
+            o_state = self.state_chain.sample_next(&state, rng);
+            ret_val.push((state, obs));
+        }
+        ret_val
+    }
+
     pub fn predict(&self, observations: &Vec<String>) -> String {
         let mut best_prob = (String::from(""), 0 as f32);
         let all_observations = self.observation_chain.states
@@ -145,4 +177,93 @@ impl HiddenMarkovModel {
         }
         total_prob
     }
-}
\ No newline at end of file
+
+    fn log_prob(prob: f32) -> f64 {
+        // unseen transitions and observations get a tiny probability instead of -inf
+        f64::ln(f64::max(prob as f64, 1e-12))
+    }
+
+    // Most likely sequence of states for the observations (viterbi)
+    pub fn tag(&self, observations: &[String]) -> Vec<String> {
+        if observations.is_empty() { return Vec::new(); }
+        let states = self.initial_probabilities.keys().cloned().sorted().collect_vec();
+
+        // best log probability of ending in each state, and the state before it
+        let mut scores = states
+            .iter()
+            .map(|state| {
+                let initial = *self.initial_probabilities.get(state).unwrap_or(&0.0);
+                HiddenMarkovModel::log_prob(initial)
+                    + HiddenMarkovModel::log_prob(self.prob_obs_given_state(&observations[0], state))
+            })
+            .collect_vec();
+        let mut back_pointers: Vec<Vec<usize>> = Vec::new();
+        for obs in observations.iter().skip(1) {
+            let mut new_scores = Vec::new();
+            let mut pointers = Vec::new();
+            for to_state in &states {
+                let (best_from, best_score) = states
+                    .iter()
+                    .enumerate()
+                    .map(|(i, from_state)| (i, scores[i] + HiddenMarkovModel::log_prob(self.prob_state_given_state(from_state, to_state))))
+                    .max_by(|(_, s1), (_, s2)| s1.total_cmp(s2))
+                    .expect("no states in model");
+                new_scores.push(best_score + HiddenMarkovModel::log_prob(self.prob_obs_given_state(obs, to_state)));
+                pointers.push(best_from);
+            }
+            scores = new_scores;
+            back_pointers.push(pointers);
+        }
+
+        let mut best_state = scores
+            .iter()
+            .enumerate()
+            .max_by(|(_, s1), (_, s2)| s1.total_cmp(s2))
+            .expect("no states in model").0;
+        let mut path = vec![best_state];
+        for pointers in back_pointers.iter().rev() {
+            best_state = pointers[best_state];
+            path.push(best_state);
+        }
+        path.into_iter().rev().map(|i| states[i].clone()).collect_vec()
+    }
+
+    // <<STATES>> state chain lines, <<OBSERVATIONS>> observation chain lines,
+    // <<INITIAL>> one line of initial probabilities, lines in the markov chain format
+    pub fn save(&self, file_name: &str) {
+        let mut file = File::create(file_name).expect("Error creating file object");
+        file.write_all(b"<<STATES>>\n").expect("Error writing to file");
+        MarkovChain::write_states(&self.state_chain.states, &mut file);
+        file.write_all(b"<<OBSERVATIONS>>\n").expect("Error writing to file");
+        MarkovChain::write_states(&self.observation_chain.states, &mut file);
+        file.write_all(b"<<INITIAL>>\n").expect("Error writing to file");
+        let mut initial = HashMap::new();
+        initial.insert(String::from(""), self.initial_probabilities.clone());
+        MarkovChain::write_states(&initial, &mut file);
+    }
+
+    pub fn load(file_name: &str) -> HiddenMarkovModel {
+        let file = File::open(file_name).expect("Error creating file object");
+        let reader = BufReader::new(file);
+        let mut state_chain = MarkovChain::new();
+        let mut observation_chain = MarkovChain::new();
+        let mut initial_probabilities = HashMap::new();
+        let mut section = String::new();
+        for ln in reader.lines() {
+            let line = ln.expect("Error reading line");
+            if line.starts_with("<<") && line.ends_with(">>") {
+                section = line;
+                continue;
+            }
+            if line.is_empty() { continue; }
+            let (from_state, map) = MarkovChain::parse_state_line(&line);
+            match section.as_str() {
+                "<<STATES>>" => { state_chain.states.insert(from_state, map); },
+                "<<OBSERVATIONS>>" => { observation_chain.states.insert(from_state, map); },
+                "<<INITIAL>>" => { initial_probabilities = map; },
+                _ => panic!("Loading hidden markov model: line outside of a section")
+            }
+        }
+        HiddenMarkovModel { state_chain, observation_chain, initial_probabilities }
+    }
+}
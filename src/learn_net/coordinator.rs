@@ -0,0 +1,443 @@
+use itertools::Itertools;
+use json::{JsonValue, object};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::events;
+use crate::learn_net::manifest::{JobSpec, Manifest};
+use crate::learn_net::protocol::{JobRequest, Message};
+
+// A worker that sends nothing for this long past the job timeout is treated as lost
+const WORKER_GRACE: Duration = Duration::from_secs(10);
+const ACCEPT_POLL: Duration = Duration::from_millis(20);
+pub(crate) const STATUS_FILE: &str = "status.json";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JobState {
+    Pending,
+    Running,
+    Succeeded,
+    Failed
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Pending => write!(f, "pending"),
+            JobState::Running => write!(f, "running"),
+            JobState::Succeeded => write!(f, "succeeded"),
+            JobState::Failed => write!(f, "failed")
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct JobStatus {
+    pub name: String,
+    pub state: JobState,
+    pub attempts: usize,
+    // worker of the last attempt
+    pub worker: Option<String>,
+    // duration of the last attempt
+    pub elapsed: Option<Duration>,
+    pub exit_code: Option<i32>,
+    // why the last attempt failed, with the end of the job's stderr
+    pub error: Option<String>
+}
+
+#[derive(Clone, Debug)]
+pub struct RunReport {
+    pub jobs: Vec<JobStatus>,
+    pub elapsed: Duration
+}
+
+impl RunReport {
+    pub fn succeeded(&self) -> usize {
+        self.jobs.iter().filter(|job| job.state == JobState::Succeeded).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.jobs.iter().filter(|job| job.state == JobState::Failed).count()
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let jobs = self.jobs
+            .iter()
+            .map(|job| object!{
+                name: job.name.clone(),
+                state: job.state.to_string(),
+                attempts: job.attempts,
+                worker: job.worker.clone(),
+                elapsed_seconds: job.elapsed.map(|e| e.as_secs_f64()),
+                exit_code: job.exit_code,
+                error: job.error.clone()
+            })
+            .collect_vec();
+        object!{
+            succeeded: self.succeeded(),
+            failed: self.failed(),
+            elapsed_seconds: self.elapsed.as_secs_f64(),
+            jobs: jobs
+        }
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} jobs succeeded, {} failed in {:.1?}", self.succeeded(), self.jobs.len(), self.failed(), self.elapsed)?;
+        for job in &self.jobs {
+            write!(f, "\n{}: {} after {} attempt(s)", job.name, job.state, job.attempts)?;
+            if let Some(worker) = &job.worker {
+                write!(f, " on {}", worker)?;
+            }
+            if let Some(elapsed) = job.elapsed {
+                write!(f, " in {:.1?}", elapsed)?;
+            }
+            if let (JobState::Failed, Some(error)) = (job.state, &job.error) {
+                write!(f, "\n  {}", error.trim_end().replace('\n', "\n  "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct RunState {
+    jobs: Vec<JobStatus>,
+    // indexes of the jobs waiting for a worker
+    queue: VecDeque<usize>,
+    finished: usize
+}
+
+// How an attempt ended
+enum AttemptResult {
+    Succeeded(i32),
+    // the job ran and failed, the worker can take the next job
+    Failed(Option<i32>, String),
+    // the connection broke, the worker is gone
+    Lost(String)
+}
+
+struct Coordinator<'a> {
+    manifest: &'a Manifest,
+    output_dir: PathBuf,
+    started: Instant,
+    state: Mutex<RunState>,
+    changed: Condvar
+}
+
+// Hands the manifest jobs to the workers that connect to listener until every job
+// succeeded or ran out of attempts. Outputs and status.json are written to the output directory
+pub fn run_coordinator(manifest: &Manifest, listener: TcpListener) -> io::Result<RunReport> {
+    fs::create_dir_all(&manifest.output_dir)?;
+    listener.set_nonblocking(true)?;
+    let coordinator = Coordinator {
+        manifest,
+        output_dir: PathBuf::from(&manifest.output_dir),
+        started: Instant::now(),
+        state: Mutex::new(RunState {
+            jobs: manifest.jobs
+                .iter()
+                .map(|job| JobStatus { name: job.name.clone(), state: JobState::Pending, attempts: 0, worker: None, elapsed: None, exit_code: None, error: None })
+                .collect_vec(),
+            queue: (0..manifest.jobs.len()).collect(),
+            finished: 0
+        }),
+        changed: Condvar::new()
+    };
+    coordinator.write_status(&coordinator.state.lock().expect("Run state lock poisoned"));
+    events::message(&format!("learn net: waiting for workers on {}", listener.local_addr()?));
+
+    thread::scope(|scope| {
+        while !coordinator.all_finished() {
+            match listener.accept() {
+                Ok((stream, address)) => {
+                    let coordinator = &coordinator;
+                    scope.spawn(move || {
+                        if let Err(e) = coordinator.serve(stream) {
+                            events::message(&format!("learn net: worker at {} disconnected: {}", address, e));
+                        }
+                    });
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(())
+    })?;
+
+    let state = coordinator.state.lock().expect("Run state lock poisoned");
+    Ok(RunReport { jobs: state.jobs.clone(), elapsed: coordinator.started.elapsed() })
+}
+
+impl<'a> Coordinator<'a> {
+    fn all_finished(&self) -> bool {
+        let state = self.state.lock().expect("Run state lock poisoned");
+        state.finished == state.jobs.len()
+    }
+
+    // status.json is rewritten on every change so a run can be watched while it goes
+    fn write_status(&self, state: &RunState) {
+        let report = RunReport { jobs: state.jobs.clone(), elapsed: self.started.elapsed() };
+        let status_file = self.output_dir.join(STATUS_FILE);
+        if let Err(e) = fs::write(&status_file, report.to_json().pretty(2)) {
+            events::message(&format!("learn net: error writing {}: {}", status_file.display(), e));
+        }
+    }
+
+    // Next job for a worker, None once every job is finished
+    fn next_job(&self) -> Option<usize> {
+        let mut state = self.state.lock().expect("Run state lock poisoned");
+        loop {
+            if let Some(i) = state.queue.pop_front() {
+                return Some(i);
+            }
+            if state.finished == state.jobs.len() {
+                return None;
+            }
+            // a running job can still come back for a retry
+            state = self.changed.wait(state).expect("Run state lock poisoned");
+        }
+    }
+
+    fn serve(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(WORKER_GRACE))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let worker = match Message::receive(&mut reader)? {
+            Message::Hello(name) => name,
+            other => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Expected HELLO, got {:?}", other)))
+        };
+        events::message(&format!("learn net: worker {} connected", worker));
+
+        while let Some(i) = self.next_job() {
+            let spec = &self.manifest.jobs[i];
+            let input = match &spec.input {
+                Some(file) => match fs::read(file) {
+                    Ok(input) => Some(input),
+                    Err(e) => {
+                        // the input will not appear on a retry
+                        self.finish_job(i, &worker, JobState::Failed, None, Some(format!("Error reading input {}: {}", file, e)), Duration::ZERO);
+                        continue;
+                    }
+                },
+                None => None
+            };
+            let attempt = {
+                let mut state = self.state.lock().expect("Run state lock poisoned");
+                let status = &mut state.jobs[i];
+                status.attempts += 1;
+                status.state = JobState::Running;
+                status.worker = Some(worker.clone());
+                let attempt = status.attempts;
+                self.write_status(&state);
+                attempt
+            };
+            let request = JobRequest {
+                name: spec.name.clone(),
+                executable: spec.executable.clone(),
+                args: spec.args.clone(),
+                timeout: spec.timeout,
+                attempt,
+                input
+            };
+            let start = Instant::now();
+            let result = self.run_attempt(spec, &request, &mut writer, &mut reader);
+            let lost = matches!(result, AttemptResult::Lost(_));
+            self.attempt_finished(i, &worker, result, start.elapsed());
+            if lost {
+                return Err(io::Error::new(io::ErrorKind::ConnectionAborted, format!("lost while running {}", spec.name)));
+            }
+        }
+        Message::Shutdown.send(&mut writer)
+    }
+
+    // Sends the job and writes its output to a partial file that replaces
+    // the output once the job exits with 0
+    fn run_attempt(&self, spec: &JobSpec, request: &JobRequest, writer: &mut TcpStream, reader: &mut BufReader<TcpStream>) -> AttemptResult {
+        let output_file = self.output_dir.join(&spec.output);
+        let partial_file = self.output_dir.join(format!("{}.partial", spec.output));
+        let result = (|| -> io::Result<AttemptResult> {
+            reader.get_ref().set_read_timeout(Some(spec.timeout + WORKER_GRACE))?;
+            Message::Job(request.clone()).send(writer)?;
+            let mut partial = File::create(&partial_file)?;
+            loop {
+                match Message::receive(reader)? {
+                    Message::Output(bytes) => partial.write_all(&bytes)?,
+                    Message::Done { exit_code: 0, .. } => {
+                        partial.flush()?;
+                        drop(partial);
+                        fs::rename(&partial_file, &output_file)?;
+                        return Ok(AttemptResult::Succeeded(0));
+                    },
+                    Message::Done { exit_code, stderr } => {
+                        return Ok(AttemptResult::Failed(Some(exit_code), with_stderr(format!("Exited with {}", exit_code), &stderr)));
+                    },
+                    Message::Failed { error, stderr } => return Ok(AttemptResult::Failed(None, with_stderr(error, &stderr))),
+                    other => return Ok(AttemptResult::Lost(format!("Unexpected message from worker: {:?}", other)))
+                }
+            }
+        })();
+        let result = result.unwrap_or_else(|e| AttemptResult::Lost(format!("Worker lost: {}", e)));
+        if !matches!(result, AttemptResult::Succeeded(_)) && Path::new(&partial_file).exists() {
+            let _ = fs::remove_file(&partial_file);
+        }
+        result
+    }
+
+    fn attempt_finished(&self, i: usize, worker: &str, result: AttemptResult, elapsed: Duration) {
+        let (exit_code, error) = match result {
+            AttemptResult::Succeeded(exit_code) => return self.finish_job(i, worker, JobState::Succeeded, Some(exit_code), None, elapsed),
+            AttemptResult::Failed(exit_code, error) => (exit_code, error),
+            AttemptResult::Lost(error) => (None, error)
+        };
+        let mut state = self.state.lock().expect("Run state lock poisoned");
+        let attempts = state.jobs[i].attempts;
+        if attempts > self.manifest.retries {
+            drop(state);
+            return self.finish_job(i, worker, JobState::Failed, exit_code, Some(error), elapsed);
+        }
+        events::message(&format!("learn net: {} failed on {} (attempt {}), retrying: {}", state.jobs[i].name, worker, attempts, error.lines().next().unwrap_or("")));
+        let status = &mut state.jobs[i];
+        status.state = JobState::Pending;
+        status.exit_code = exit_code;
+        status.error = Some(error);
+        status.elapsed = Some(elapsed);
+        state.queue.push_back(i);
+        self.write_status(&state);
+        self.changed.notify_all();
+    }
+
+    fn finish_job(&self, i: usize, worker: &str, job_state: JobState, exit_code: Option<i32>, error: Option<String>, elapsed: Duration) {
+        let mut state = self.state.lock().expect("Run state lock poisoned");
+        let status = &mut state.jobs[i];
+        status.state = job_state;
+        status.worker = Some(String::from(worker));
+        status.exit_code = exit_code;
+        status.error = error;
+        status.elapsed = Some(elapsed);
+        if job_state == JobState::Failed {
+            events::message(&format!("learn net: {} failed after {} attempt(s)", status.name, status.attempts));
+        }
+        state.finished += 1;
+        events::progress("learn net", state.finished, Some(state.jobs.len()));
+        self.write_status(&state);
+        self.changed.notify_all();
+    }
+}
+
+fn with_stderr(error: String, stderr: &str) -> String {
+    if stderr.trim().is_empty() { error } else { format!("{}\n{}", error, stderr.trim_end()) }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::learn_net::worker::run_worker;
+    use std::env;
+    use std::process;
+
+    fn job(name: &str, executable: &str, args: &[&str], timeout: Duration) -> JobSpec {
+        JobSpec {
+            name: String::from(name),
+            executable: String::from(executable),
+            args: args.iter().map(|arg| String::from(*arg)).collect_vec(),
+            input: None,
+            output: format!("{}.out", name),
+            timeout
+        }
+    }
+
+    #[test]
+    fn runs_jobs_on_local_workers() {
+        let dir = env::temp_dir().join(format!("learn_net_test_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input_file = dir.join("input.txt");
+        fs::write(&input_file, "a\nb\nc\n").unwrap();
+
+        let second = Duration::from_secs(5);
+        let mut lines = job("lines", "wc", &["-l"], second);
+        lines.input = Some(input_file.to_string_lossy().to_string());
+        let manifest = Manifest {
+            output_dir: dir.join("out").to_string_lossy().to_string(),
+            retries: 1,
+            jobs: vec![
+                lines,
+                job("echo", "echo", &["hello"], second),
+                job("fails", "sh", &["-c", "echo broken >&2; exit 2"], second),
+                job("slow", "sleep", &["5"], Duration::from_millis(200))
+            ]
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let workers = (0..2)
+            .map(|i| {
+                let address = address.clone();
+                thread::spawn(move || run_worker(&address, &format!("test-{}", i)).unwrap())
+            })
+            .collect_vec();
+        let report = run_coordinator(&manifest, listener).unwrap();
+        let jobs_run: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+
+        let status = |name: &str| report.jobs.iter().find(|job| job.name == name).unwrap().clone();
+        assert_eq!(report.succeeded(), 2);
+        assert_eq!(jobs_run, 6);
+        assert_eq!(fs::read_to_string(dir.join("out/lines.out")).unwrap().trim(), "3");
+        assert_eq!(fs::read_to_string(dir.join("out/echo.out")).unwrap(), "hello\n");
+        let fails = status("fails");
+        assert_eq!((fails.state, fails.attempts, fails.exit_code), (JobState::Failed, 2, Some(2)));
+        assert!(fails.error.unwrap().contains("broken"));
+        let slow = status("slow");
+        assert_eq!((slow.state, slow.attempts), (JobState::Failed, 2));
+        assert!(slow.error.unwrap().starts_with("Timed out"));
+        assert!(!dir.join("out/fails.out").exists());
+        assert!(dir.join("out/status.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn input_argument_is_replaced_by_a_file() {
+        let dir = env::temp_dir().join(format!("learn_net_input_test_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input_file = dir.join("input.txt");
+        fs::write(&input_file, "a\nb\n").unwrap();
+
+        let second = Duration::from_secs(5);
+        // the file is passed as an argument, stdin gets nothing
+        let mut from_file = job("from_file", "sh", &["-c", "cat \"$0\"; cat; echo \"$0\"", "{input}"], second);
+        from_file.input = Some(input_file.to_string_lossy().to_string());
+        // the placeholder can be part of an argument
+        let mut in_option = job("in_option", "sh", &["-c", "cat \"${0#--file=}\"", "--file={input}"], second);
+        in_option.input = Some(input_file.to_string_lossy().to_string());
+        let manifest = Manifest {
+            output_dir: dir.join("out").to_string_lossy().to_string(),
+            retries: 0,
+            jobs: vec![from_file, in_option]
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let worker = thread::spawn(move || run_worker(&address, "test").unwrap());
+        let report = run_coordinator(&manifest, listener).unwrap();
+        assert_eq!(worker.join().unwrap(), 2);
+
+        assert_eq!(report.succeeded(), 2);
+        let output = fs::read_to_string(dir.join("out/from_file.out")).unwrap();
+        let (contents, path) = output.split_at(4);
+        assert_eq!(contents, "a\nb\n");
+        let path = path.trim();
+        assert!(!path.contains("{input}"));
+        // the input file is removed once the job is done
+        assert!(!Path::new(path).exists());
+        assert_eq!(fs::read_to_string(dir.join("out/in_option.out")).unwrap(), "a\nb\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use itertools::Itertools;
+use json::{JsonValue, parse};
+use std::fs;
+use std::time::Duration;
+
+use crate::experiment::{get_list, get_path};
+use crate::learn_net::coordinator::STATUS_FILE;
+use crate::n_gram::config::{ConfigError, check_keys, get_f64, get_i32, get_str, out_of_range};
+
+/*
+Manifest file structure, only output_dir and jobs are required:
+{
+    output_dir: "out",
+    retries: 2,
+    timeout_seconds: 3600,
+    jobs: [
+        {
+            name: "english",
+            executable: "target/release/rust-datascience",
+            args: ["ngram", "train", "--train", "{input}", "--model", "/dev/stdout"],
+            input: "data/english.csv",
+            output: "english.dat",
+            timeout_seconds: 600
+        }
+    ]
+}
+input is read by the coordinator and sent to the worker, the job gets it on stdin,
+or as a file when an argument contains {input}. Whatever the job writes to stdout
+is saved to output_dir/output, output defaults to <name>.out. Files the job writes
+on the worker are not collected, so commands write their result to /dev/stdout,
+rust-datascience commands print their summary to stderr then.
+output can't be status.json, the coordinator keeps the run status in that file
+A job is tried again up to retries times when it exits non zero, times out or its worker is lost
+*/
+
+const DEFAULT_TIMEOUT_SECONDS: f64 = 3600.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct JobSpec {
+    pub name: String,
+    pub executable: String,
+    pub args: Vec<String>,
+    // file sent to the worker, None runs the job without input
+    pub input: Option<String>,
+    // file name inside the output directory
+    pub output: String,
+    pub timeout: Duration
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    pub output_dir: String,
+    // extra attempts after the first one fails
+    pub retries: usize,
+    pub jobs: Vec<JobSpec>
+}
+
+fn get_timeout(obj: &JsonValue, section: &str, def: f64) -> Result<f64, ConfigError> {
+    let timeout = get_f64(obj, section, "timeout_seconds", def)?;
+    if timeout <= 0.0 {
+        return out_of_range(section, "timeout_seconds", "greater than 0");
+    }
+    Ok(timeout)
+}
+
+impl JobSpec {
+    fn from_json(obj: &JsonValue, section: &str, default_timeout: f64) -> Result<JobSpec, ConfigError> {
+        check_keys(obj, section, &["name", "executable", "args", "input", "output", "timeout_seconds"])?;
+        let name = match get_str(obj, section, "name")? {
+            Some(name) if !name.is_empty() => String::from(name),
+            _ => return out_of_range(section, "name", "a non empty string")
+        };
+        let executable = match get_path(obj, section, "executable")? {
+            Some(executable) => executable,
+            None => return out_of_range(section, "executable", "set to the program to run")
+        };
+        let output = get_path(obj, section, "output")?.unwrap_or(format!("{}.out", name));
+        if output.is_empty() || output.contains('/') || output.contains('\\') {
+            return out_of_range(section, "output", "a file name without directories");
+        }
+        if output == STATUS_FILE {
+            return out_of_range(section, "output", "a file name other than the run status file");
+        }
+        Ok(JobSpec {
+            args: get_list(obj, section, "args", "a list of strings")?,
+            input: get_path(obj, section, "input")?,
+            timeout: Duration::from_secs_f64(get_timeout(obj, section, default_timeout)?),
+            name,
+            executable,
+            output
+        })
+    }
+}
+
+impl Manifest {
+    pub fn from_json(obj: &JsonValue) -> Result<Manifest, ConfigError> {
+        let manifest_s = "manifest";
+        check_keys(obj, manifest_s, &["output_dir", "retries", "timeout_seconds", "jobs"])?;
+        let output_dir = match get_path(obj, manifest_s, "output_dir")? {
+            Some(output_dir) => output_dir,
+            None => return out_of_range(manifest_s, "output_dir", "set to the directory for the job outputs")
+        };
+        let retries = get_i32(obj, manifest_s, "retries", 0)?;
+        if retries < 0 {
+            return out_of_range(manifest_s, "retries", "at least 0");
+        }
+        let timeout = get_timeout(obj, manifest_s, DEFAULT_TIMEOUT_SECONDS)?;
+        if !obj["jobs"].is_array() || obj["jobs"].is_empty() {
+            return out_of_range(manifest_s, "jobs", "a non empty list of jobs");
+        }
+        let jobs = obj["jobs"]
+            .members()
+            .enumerate()
+            .map(|(i, job)| JobSpec::from_json(job, &format!("jobs[{}]", i), timeout))
+            .collect::<Result<Vec<JobSpec>, ConfigError>>()?;
+        if !jobs.iter().map(|job| &job.name).all_unique() {
+            return out_of_range(manifest_s, "jobs", "jobs with unique names");
+        }
+        if !jobs.iter().map(|job| &job.output).all_unique() {
+            return out_of_range(manifest_s, "jobs", "jobs with unique outputs");
+        }
+        Ok(Manifest { output_dir, retries: retries as usize, jobs })
+    }
+
+    pub fn load(file_name: &str) -> Result<Manifest, ConfigError> {
+        let contents = fs::read_to_string(file_name).map_err(|e| ConfigError::Io(format!("{}: {}", file_name, e)))?;
+        let obj = parse(&contents).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        Manifest::from_json(&obj)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(s: &str) -> Result<Manifest, ConfigError> {
+        Manifest::from_json(&parse(s).unwrap())
+    }
+
+    fn rejected_key(s: &str) -> String {
+        match manifest(s).expect_err("manifest should be rejected") {
+            ConfigError::OutOfRange(_, key, _) | ConfigError::UnknownKey(_, key) | ConfigError::WrongType(_, key, _) => key,
+            e => panic!("Unexpected error: {}", e)
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let manifest = manifest(r#"{"output_dir": "out", "jobs": [{"name": "a", "executable": "echo", "args": ["hi"]}]}"#).unwrap();
+        assert_eq!(manifest, Manifest {
+            output_dir: String::from("out"),
+            retries: 0,
+            jobs: vec![JobSpec {
+                name: String::from("a"),
+                executable: String::from("echo"),
+                args: vec![String::from("hi")],
+                input: None,
+                output: String::from("a.out"),
+                timeout: Duration::from_secs_f64(DEFAULT_TIMEOUT_SECONDS)
+            }]
+        });
+    }
+
+    #[test]
+    fn job_timeout_defaults_to_manifest_timeout() {
+        let manifest = manifest(r#"{"output_dir": "out", "timeout_seconds": 5, "jobs": [
+            {"name": "a", "executable": "echo"},
+            {"name": "b", "executable": "echo", "timeout_seconds": 1, "input": "in.csv", "output": "b.dat"}
+        ]}"#).unwrap();
+        assert_eq!(manifest.jobs[0].timeout, Duration::from_secs(5));
+        assert_eq!(manifest.jobs[1].timeout, Duration::from_secs(1));
+        assert_eq!(manifest.jobs[1].input.as_deref(), Some("in.csv"));
+        assert_eq!(manifest.jobs[1].output, "b.dat");
+    }
+
+    #[test]
+    fn rejects_invalid_manifests() {
+        assert_eq!(rejected_key(r#"{"jobs": [{"name": "a", "executable": "echo"}]}"#), "output_dir");
+        assert_eq!(rejected_key(r#"{"output_dir": "out", "jobs": []}"#), "jobs");
+        assert_eq!(rejected_key(r#"{"output_dir": "out", "retries": -1, "jobs": [{"name": "a", "executable": "echo"}]}"#), "retries");
+        assert_eq!(rejected_key(r#"{"output_dir": "out", "timeout_seconds": 0, "jobs": [{"name": "a", "executable": "echo"}]}"#), "timeout_seconds");
+        assert_eq!(rejected_key(r#"{"output_dir": "out", "workers": 2, "jobs": [{"name": "a", "executable": "echo"}]}"#), "workers");
+    }
+
+    #[test]
+    fn rejects_invalid_jobs() {
+        let job = |job: &str| rejected_key(&format!(r#"{{"output_dir": "out", "jobs": [{}]}}"#, job));
+        assert_eq!(job(r#"{"executable": "echo"}"#), "name");
+        assert_eq!(job(r#"{"name": "", "executable": "echo"}"#), "name");
+        assert_eq!(job(r#"{"name": "a"}"#), "executable");
+        assert_eq!(job(r#"{"name": "a", "executable": "echo", "args": "hi"}"#), "args");
+        assert_eq!(job(r#"{"name": "a", "executable": "echo", "output": "dir/a.out"}"#), "output");
+        assert_eq!(job(r#"{"name": "a", "executable": "echo", "output": "status.json"}"#), "output");
+        assert_eq!(job(r#"{"name": "a", "executable": "echo", "timeout_seconds": -1}"#), "timeout_seconds");
+        assert_eq!(job(r#"{"name": "a", "executable": "echo", "retries": 1}"#), "retries");
+    }
+
+    #[test]
+    fn names_and_outputs_are_unique() {
+        assert_eq!(rejected_key(r#"{"output_dir": "out", "jobs": [{"name": "a", "executable": "echo"}, {"name": "a", "executable": "echo", "output": "b.out"}]}"#), "jobs");
+        assert_eq!(rejected_key(r#"{"output_dir": "out", "jobs": [{"name": "a", "executable": "echo"}, {"name": "b", "executable": "echo", "output": "a.out"}]}"#), "jobs");
+    }
+}
@@ -0,0 +1,12 @@
+/*
+Learn net: runs a list of jobs on worker processes, possibly on other machines.
+Each job is an executable with its args, an input file and an output file name.
+The coordinator listens on a TCP address, every worker connects to it and is handed
+one job at a time: the input goes to the worker, the job's stdout streams back and
+is saved in the output directory. See manifest.rs for the job list format
+*/
+
+pub mod manifest;
+pub mod protocol;
+pub mod coordinator;
+pub mod worker;
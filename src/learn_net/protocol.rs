@@ -0,0 +1,146 @@
+use json::{JsonValue, object, parse};
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+/*
+Coordinator and workers talk in frames: a "KIND LENGTH\n" header followed by LENGTH payload bytes.
+worker -> coordinator: HELLO (worker name), then for each job OUTPUT frames followed by DONE or FAILED
+coordinator -> worker: JOB (json) followed by INPUT when the job has input, or SHUTDOWN
+*/
+
+// Largest payload accepted from the other side
+const MAX_FRAME: usize = 1 << 30;
+
+// Sent to the worker for every attempt of a job
+#[derive(Clone, Debug, PartialEq)]
+pub struct JobRequest {
+    pub name: String,
+    pub executable: String,
+    pub args: Vec<String>,
+    pub timeout: Duration,
+    // 1 for the first attempt
+    pub attempt: usize,
+    pub input: Option<Vec<u8>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Hello(String),
+    Job(JobRequest),
+    // a chunk of the job's stdout
+    Output(Vec<u8>),
+    // the job exited, -1 when it was killed by a signal, with the end of its stderr
+    Done { exit_code: i32, stderr: String },
+    // the job could not be run or timed out
+    Failed { error: String, stderr: String },
+    Shutdown
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_frame(writer: &mut impl Write, kind: &str, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(format!("{} {}\n", kind, payload.len()).as_bytes())?;
+    writer.write_all(payload)
+}
+
+fn read_frame(reader: &mut impl BufRead) -> io::Result<(String, Vec<u8>)> {
+    let mut header = String::new();
+    if reader.read_line(&mut header)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed"));
+    }
+    let (kind, length) = header
+        .trim_end()
+        .split_once(' ')
+        .ok_or(invalid(format!("Bad frame header: {}", header.trim_end())))?;
+    let length = length.parse::<usize>().map_err(|_| invalid(format!("Bad frame length: {}", length)))?;
+    if length > MAX_FRAME {
+        return Err(invalid(format!("Frame of {} bytes is too large", length)));
+    }
+    let mut payload = vec![0; length];
+    reader.read_exact(&mut payload)?;
+    Ok((String::from(kind), payload))
+}
+
+fn json_payload(payload: &[u8]) -> io::Result<JsonValue> {
+    let s = std::str::from_utf8(payload).map_err(|e| invalid(e.to_string()))?;
+    parse(s).map_err(|e| invalid(e.to_string()))
+}
+
+fn json_string(obj: &JsonValue, key: &str) -> io::Result<String> {
+    obj[key].as_str().map(String::from).ok_or(invalid(format!("Missing {} in message", key)))
+}
+
+impl Message {
+    pub fn send(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Message::Hello(name) => write_frame(writer, "HELLO", name.as_bytes())?,
+            Message::Job(job) => {
+                let header = object!{
+                    name: job.name.clone(),
+                    executable: job.executable.clone(),
+                    args: job.args.clone(),
+                    timeout_ms: job.timeout.as_millis() as u64,
+                    attempt: job.attempt,
+                    has_input: job.input.is_some()
+                };
+                write_frame(writer, "JOB", header.dump().as_bytes())?;
+                if let Some(input) = &job.input {
+                    write_frame(writer, "INPUT", input)?;
+                }
+            },
+            Message::Output(bytes) => write_frame(writer, "OUTPUT", bytes)?,
+            Message::Done { exit_code, stderr } => {
+                write_frame(writer, "DONE", object!{ exit_code: *exit_code, stderr: stderr.clone() }.dump().as_bytes())?
+            },
+            Message::Failed { error, stderr } => {
+                write_frame(writer, "FAILED", object!{ error: error.clone(), stderr: stderr.clone() }.dump().as_bytes())?
+            },
+            Message::Shutdown => write_frame(writer, "SHUTDOWN", &[])?
+        }
+        writer.flush()
+    }
+
+    pub fn receive(reader: &mut impl BufRead) -> io::Result<Message> {
+        let (kind, payload) = read_frame(reader)?;
+        match kind.as_str() {
+            "HELLO" => Ok(Message::Hello(String::from_utf8_lossy(&payload).to_string())),
+            "JOB" => {
+                let header = json_payload(&payload)?;
+                let args = header["args"]
+                    .members()
+                    .map(|arg| arg.as_str().map(String::from).ok_or(invalid(String::from("Job args must be strings"))))
+                    .collect::<io::Result<Vec<String>>>()?;
+                let input = if header["has_input"].as_bool().unwrap_or(false) {
+                    match read_frame(reader)? {
+                        (kind, input) if kind.eq("INPUT") => Some(input),
+                        (kind, _) => return Err(invalid(format!("Expected INPUT after JOB, got {}", kind)))
+                    }
+                } else { None };
+                Ok(Message::Job(JobRequest {
+                    name: json_string(&header, "name")?,
+                    executable: json_string(&header, "executable")?,
+                    args,
+                    timeout: Duration::from_millis(header["timeout_ms"].as_u64().ok_or(invalid(String::from("Missing timeout_ms in message")))?),
+                    attempt: header["attempt"].as_usize().unwrap_or(1),
+                    input
+                }))
+            },
+            "OUTPUT" => Ok(Message::Output(payload)),
+            "DONE" => {
+                let obj = json_payload(&payload)?;
+                Ok(Message::Done {
+                    exit_code: obj["exit_code"].as_i32().ok_or(invalid(String::from("Missing exit_code in message")))?,
+                    stderr: json_string(&obj, "stderr")?
+                })
+            },
+            "FAILED" => {
+                let obj = json_payload(&payload)?;
+                Ok(Message::Failed { error: json_string(&obj, "error")?, stderr: json_string(&obj, "stderr")? })
+            },
+            "SHUTDOWN" => Ok(Message::Shutdown),
+            _ => Err(invalid(format!("Unknown message: {}", kind)))
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{self, Child, Command, Stdio};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::events;
+use crate::learn_net::protocol::{JobRequest, Message};
+
+// Only the end of a failing job's stderr is sent back
+const STDERR_TAIL: usize = 4096;
+const OUTPUT_CHUNK: usize = 64 * 1024;
+
+// Connects to the coordinator and runs jobs until it is told to shut down,
+// returns the number of jobs run
+pub fn run_worker(address: &str, name: &str) -> io::Result<usize> {
+    let stream = TcpStream::connect(address)?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    Message::Hello(String::from(name)).send(&mut writer)?;
+    events::message(&format!("learn net worker {}: connected to {}", name, address));
+
+    let mut jobs_run = 0;
+    loop {
+        match Message::receive(&mut reader)? {
+            Message::Job(job) => {
+                events::message(&format!("learn net worker {}: running {} (attempt {})", name, job.name, job.attempt));
+                run_job(&job, name, &mut writer)?;
+                jobs_run += 1;
+            },
+            Message::Shutdown => return Ok(jobs_run),
+            other => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected message from coordinator: {:?}", other)))
+        }
+    }
+}
+
+fn tail(bytes: &Arc<Mutex<Vec<u8>>>) -> String {
+    let bytes = bytes.lock().expect("stderr lock poisoned");
+    let start = bytes.len().saturating_sub(STDERR_TAIL);
+    String::from_utf8_lossy(&bytes[start..]).to_string()
+}
+
+// Runs the job and streams its stdout to the coordinator, then sends DONE or FAILED.
+// Only errors writing to the coordinator are returned, job errors are reported to it
+fn run_job(job: &JobRequest, worker_name: &str, writer: &mut impl Write) -> io::Result<()> {
+    // a job with {input} in its args reads the input from a file instead of stdin
+    let file_name = format!("learn_net_{}_{}_{}_{}.input", process::id(), worker_name, job.name, job.attempt)
+        .replace(|c: char| !c.is_ascii_alphanumeric() && c != '.', "_");
+    let input_file = env::temp_dir().join(file_name);
+    let input_as_file = job.args.iter().any(|arg| arg.contains("{input}"));
+    if input_as_file {
+        if let Err(e) = fs::write(&input_file, job.input.as_deref().unwrap_or(&[])) {
+            let error = format!("Error writing input file {}: {}", input_file.display(), e);
+            return Message::Failed { error, stderr: String::new() }.send(writer);
+        }
+    }
+    let args = job.args
+        .iter()
+        .map(|arg| arg.replace("{input}", &input_file.to_string_lossy()))
+        .collect::<Vec<String>>();
+
+    let spawned = Command::new(&job.executable)
+        .args(&args)
+        .stdin(if job.input.is_some() && !input_as_file { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let result = match spawned {
+        Ok(child) => stream_child(child, job, writer),
+        Err(e) => Message::Failed { error: format!("Error starting {}: {}", job.executable, e), stderr: String::new() }.send(writer)
+    };
+    if input_as_file {
+        let _ = fs::remove_file(&input_file);
+    }
+    result
+}
+
+fn stream_child(mut child: Child, job: &JobRequest, writer: &mut impl Write) -> io::Result<()> {
+    let deadline = Instant::now() + job.timeout;
+
+    // the pipes are served by detached threads, a grandchild that keeps a pipe
+    // open after the job is killed must not block the worker
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), job.input.clone()) {
+        thread::spawn(move || {
+            // a job that does not read all of its input closes the pipe early
+            let _ = stdin.write_all(&input);
+        });
+    }
+    let stderr = Arc::new(Mutex::new(Vec::new()));
+    if let Some(mut child_stderr) = child.stderr.take() {
+        let stderr = stderr.clone();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok(n) = child_stderr.read(&mut buf) {
+                if n == 0 { break; }
+                let mut stderr = stderr.lock().expect("stderr lock poisoned");
+                stderr.extend_from_slice(&buf[..n]);
+                let excess = stderr.len().saturating_sub(STDERR_TAIL);
+                stderr.drain(..excess);
+            }
+        });
+    }
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    let mut child_stdout = child.stdout.take().expect("child stdout is piped");
+    thread::spawn(move || {
+        let mut buf = vec![0; OUTPUT_CHUNK];
+        while let Ok(n) = child_stdout.read(&mut buf) {
+            if n == 0 || sender.send(buf[..n].to_vec()).is_err() { break; }
+        }
+    });
+
+    let timed_out = |child: &mut Child| -> Message {
+        let _ = child.kill();
+        let _ = child.wait();
+        Message::Failed { error: format!("Timed out after {:?}", job.timeout), stderr: tail(&stderr) }
+    };
+
+    // stdout until it closes
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(chunk) => {
+                if let Err(e) = Message::Output(chunk).send(writer) {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(e);
+                }
+            },
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => return timed_out(&mut child).send(writer)
+        }
+    }
+    // then the exit status
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                let message = Message::Done { exit_code: status.code().unwrap_or(-1), stderr: tail(&stderr) };
+                return message.send(writer);
+            },
+            Ok(None) if Instant::now() >= deadline => return timed_out(&mut child).send(writer),
+            Ok(None) => thread::sleep(Duration::from_millis(10)),
+            Err(e) => return Message::Failed { error: format!("Error waiting for the job: {}", e), stderr: tail(&stderr) }.send(writer)
+        }
+    }
+}
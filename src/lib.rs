@@ -1,4 +1,14 @@
 pub mod n_gram;
 pub mod markov_chain;
 pub mod util;
-pub mod hidden_markov_model;
\ No newline at end of file
+pub mod parallel;
+pub mod random;
+pub mod events;
+pub mod text;
+pub mod vocabulary;
+pub mod hidden_markov_model;
+pub mod metrics;
+pub mod experiment;
+pub mod learn_net;
+pub mod vectorizer;
+pub mod cli;
\ No newline at end of file
@@ -1,29 +1,80 @@
-use std::{fs::File, io::{Write, Read, BufReader, BufRead}};
+use std::{fs::File, io::{Write, BufReader, BufRead}};
 
+use crate::events;
 use crate::markov_chain::*;
 
 impl MarkovChain {
-    // from_word|"to_word"prob,"to_word"prob...\n
-    pub fn save(&self, file_name: &str) {
-        println!("Saving markov chain to file: {}", file_name);
-        let mut file_data = String::from("");
+    // from_word|"to_word"prob"to_word"prob...\n
+    pub fn write_states<W: Write>(states: &StateMap, writer: &mut W) {
+        MarkovChain::write_lines(states, writer);
+    }
+
+    // from_word|"to_word"value... with any value type, probabilities or counts
+    pub(crate) fn write_lines<T: std::fmt::Display, W: Write>(states: &HashMap<String, HashMap<String, T>>, writer: &mut W) {
         let mut i = 0;
-        let mut file = File::create(file_name).expect("Error creating file object");
-        let num_states = self.states.len();
-        for (from_word, map) in &self.states {
+        let num_states = states.len();
+        for (from_word, map) in states {
             if i % 100  == 0 {
-                println!("Wrote {} lines of {}", i, num_states);
+                events::progress("write states", i, Some(num_states));
             }
-            file_data = format!("{}{}|", file_data, from_word);
+            let mut line = format!("{}|", from_word);
             for (to_word, prob) in map {
-                file_data = format!("{}\"{}\"{}", file_data, to_word, prob.to_string());
+                line = format!("{}\"{}\"{}", line, to_word, prob);
             }
-            file_data = format!("{}\n", file_data);
-            file.write(file_data.as_bytes()).expect("Error writing to file");
+            line.push('\n');
+            writer.write_all(line.as_bytes()).expect("Error writing to file");
             i = i + 1;
         }
     }
 
+    pub fn parse_state_line(line: &str) -> (String, HashMap<String, f32>) {
+        MarkovChain::parse_line(line)
+    }
+
+    // reads the lines of write_lines
+    pub(crate) fn parse_line<T: std::str::FromStr>(line: &str) -> (String, HashMap<String, T>) where T::Err: std::fmt::Debug {
+        let mut from_word = String::new();
+        let mut current_to_word = String::new();
+        let mut current_prob_s = String::new();
+        let mut found_from_state = false;
+        let mut finding_to_word = false;
+        let mut map = HashMap::new();
+        for c in line.chars() {
+            if !found_from_state {
+                if c == '|' {
+                    found_from_state = true;
+                    continue;
+                }
+                from_word = from_word + &c.to_string();
+                continue;
+            }
+            if c == '"' {
+                if !finding_to_word && !current_prob_s.is_empty() {
+                    map.insert(current_to_word.clone(), current_prob_s.clone().parse::<T>().unwrap());
+                    current_to_word = String::new();
+                    current_prob_s = String::new();
+                }
+                finding_to_word = !finding_to_word;
+                continue;
+            }
+            if finding_to_word {
+                current_to_word = current_to_word + &c.to_string();
+            } else {
+                current_prob_s = current_prob_s + &c.to_string();
+            }
+        }
+        // the last to_word on the line is not followed by a quote
+        if !current_prob_s.is_empty() {
+            map.insert(current_to_word, current_prob_s.parse::<T>().unwrap());
+        }
+        (from_word, map)
+    }
+
+    pub fn save(&self, file_name: &str) {
+        let mut file = File::create(file_name).expect("Error creating file object");
+        events::timed("save markov chain", || MarkovChain::write_states(&self.states, &mut file));
+    }
+
     pub fn load(file_name: &str) -> MarkovChain {
         let file = File::open(file_name).expect("Error creating file object");
         let reader = BufReader::new(file);
@@ -32,42 +83,12 @@ impl MarkovChain {
         for ln in reader.lines() {
             let line = ln.expect("Error reading line");
             if i % 100 == 0 {
-                println!("read {} lines", i);
-            }
-            let mut from_word = String::new();
-            let mut current_to_word = String::new();
-            let mut current_prob_s = String::new();
-            let mut found_from_state = false;
-            let mut finding_to_word = false;
-            let mut map = HashMap::new();
-            for c in line.chars() {
-                if !found_from_state {
-                    if c == '|' {
-                        found_from_state = true;
-                        continue;
-                    }
-                    from_word = from_word + &c.to_string();
-                    continue;
-                }
-                if c == '"' {
-                    if !finding_to_word && !current_prob_s.eq("") {
-                        map.insert(current_to_word.clone(), current_prob_s.clone().parse::<f32>().unwrap());
-                        current_to_word = String::new();
-                        current_prob_s = String::new();
-                    }
-                    finding_to_word = !finding_to_word;
-                    continue;
-                }
-                if finding_to_word {
-                    current_to_word = current_to_word + &c.to_string();
-                } else {
-                    current_prob_s = current_prob_s + &c.to_string();
-                }
+                events::progress("read states", i, None);
             }
-            maps.insert(from_word.clone(), map);
-            println!("{}", from_word);
+            let (from_word, map) = MarkovChain::parse_state_line(&line);
+            maps.insert(from_word, map);
             i = i + 1;
         }
         MarkovChain { states: maps }
     }
-}
\ No newline at end of file
+}
@@ -2,10 +2,17 @@ use std::{collections::HashMap, fs};
 use std::time::Instant;
 
 use itertools::Itertools;
+use rand::Rng;
 
-use crate::util::{InputTup, multi_thread_process_list, get_markov_data, get_word_map};
+use crate::text::TextPipeline;
+use crate::events;
+use crate::parallel::process_chunks;
+use crate::random::sample_weighted;
+use crate::util::{InputTup, get_markov_data_with};
+use crate::vocabulary::Vocabulary;
 
 pub mod file;
+pub mod shard;
 
 pub type StateMap = HashMap<String, HashMap<String, f32>>;
 pub type StateTotals = HashMap<String, HashMap<String, i32>>;
@@ -56,21 +63,24 @@ impl MarkovChain {
         sm
     }
 
-    pub fn train_file(text_file: &str, white_list_file: &str) -> StateMap {
-        println!("Getting input data from file");
-        let word_map = get_word_map(white_list_file);
-        let input_data = get_markov_data(text_file)
+    // words outside the vocabulary become <UNK> states
+    pub fn train_file(text_file: &str, vocabulary: &Vocabulary, pipeline: &TextPipeline) -> StateMap {
+        let input_data = events::timed("read input", || get_markov_data_with(text_file, pipeline)
             .into_iter()
-            .filter(|(from, _)| word_map.get(from).is_some())
-            .collect_vec();
-        println!("Training from file data");
+            .map(|(from, to)| (vocabulary.map_token(&from), vocabulary.map_token(&to)))
+            .collect_vec());
         MarkovChain::train(input_data)
     }
 
     pub fn train(input_data: Vec<InputTup>) -> StateMap {
-        let mut totals = StateTotals::new();
+        let totals = MarkovChain::count(&input_data);
+        events::timed("calculate", || MarkovChain::states_from_totals(&totals))
+    }
 
-        let f_thread = |_, chunk: &Vec<InputTup>| -> Vec<StateTotals> {
+    // Transition counts of the input with every to state kept,
+    // counts of separate inputs can be summed with merge_totals
+    pub fn count(input_data: &[InputTup]) -> StateTotals {
+        let f_thread = |chunk: &[InputTup]| -> Vec<StateTotals> {
             let mut totals = StateTotals::new();
             for (from_state, to_state) in chunk {
                 MarkovChain::feed(&mut totals, from_state, to_state);
@@ -80,53 +90,46 @@ impl MarkovChain {
             ret_val
         };
 
-        let start1 = Instant::now();
-        let results = multi_thread_process_list(&input_data, 0, 16, f_thread, None);
-        println!("Feed: {:?}", start1.elapsed());
+        let f_progress = |done, total| events::progress("feed", done, Some(total));
+        let results = events::timed("feed", || process_chunks(input_data, f_thread, Some(&f_progress)));
 
-        let start2 = Instant::now();
-        let groups = results
-            .into_iter()
-            .flat_map(|hm| hm.into_iter())
-            .sorted_by(|(from1, _), (from2, _)| from1.cmp(&from2))
-            .group_by(|(from, _)| from.to_owned());
-        
-        for (from, from_list) in groups.into_iter() {
-            let to_list_groups = from_list.into_iter()
-                .flat_map(|(_, to_hm)| {
-                    to_hm.into_iter().collect_vec()
-                })
-                .sorted_by(|(to1, _), (to2, _)| to1.cmp(&to2))
-                .group_by(|(to, _)| to.to_owned());
-
-            let to_list = to_list_groups
-                .into_iter()
-                .map(|(to, total_groups)| {
-                    let total: i32 = total_groups
-                        .into_iter()
-                        .map(|(_, total)| total)
-                        .sum();
-                    (to, total)
-                })
-                .filter(|(wd, _)| !wd.eq(""))
+        let start = Instant::now();
+        let mut totals = StateTotals::new();
+        for partial in results {
+            MarkovChain::merge_totals(&mut totals, partial);
+        }
+        events::phase_finished("group", start);
+        totals
+    }
+
+    pub fn merge_totals(totals: &mut StateTotals, other: StateTotals) {
+        for (from, to_hm) in other {
+            let from_totals = totals.entry(from).or_default();
+            for (to, total) in to_hm {
+                *from_totals.entry(to).or_insert(0) += total;
+            }
+        }
+    }
+
+    // Probabilities of the 100 most common to states of every state,
+    // states without a to state are dropped
+    pub fn states_from_totals(totals: &StateTotals) -> StateMap {
+        let mut kept = StateTotals::new();
+        for (from, to_hm) in totals {
+            let to_list = to_hm
+                .iter()
+                .filter(|(wd, _)| !wd.is_empty())
+                .sorted_by(|(to1, _), (to2, _)| to1.cmp(to2))
                 .sorted_by(|(_, tot1), (_, tot2)| tot1.cmp(tot2))
                 .rev()
-                .collect_vec();
-            
-            let mut to_hm: HashMap<String, i32> = HashMap::new();
-            for (to, total) in to_list.into_iter().take(100) {
-                to_hm.insert(to, total);
-            }
-            if to_hm.clone().into_iter().len() > 0 {
-                totals.insert(from, to_hm);
+                .take(100)
+                .map(|(to, total)| (to.clone(), *total))
+                .collect::<HashMap<String, i32>>();
+            if !to_list.is_empty() {
+                kept.insert(from.clone(), to_list);
             }
         }
-        println!("Group: {:?}", start2.elapsed());
-
-        let start3 = Instant::now();
-        let states = MarkovChain::calculate_states(totals);
-        println!("Calculate: {:?}", start3.elapsed());
-        states
+        MarkovChain::calculate_states(kept)
     }
 
     pub fn predict(sm: StateMap, state: String) -> String {
@@ -141,4 +144,43 @@ impl MarkovChain {
             .max_by(|(_, prob1), (_, prob2)| prob1.total_cmp(prob2))
             .expect("max err").0
     }
-}
\ No newline at end of file
+
+    // Most likely next state, None when the state has no transitions
+    pub fn next_state(&self, state: &String) -> Option<String> {
+        self.states
+            .get(state)?
+            .iter()
+            .max_by(|(to1, prob1), (to2, prob2)| prob1.total_cmp(prob2).then(to2.cmp(to1)))
+            .map(|(to, _)| to.clone())
+    }
+
+    // Follow the most likely transition from the start state up to length states
+    // stops early at a state without transitions
+    pub fn generate(&self, start: &str, length: usize) -> Vec<String> {
+        let mut ret_val = vec![start.to_owned()];
+        while ret_val.len() < length {
+            match self.next_state(ret_val.last().expect("empty chain")) {
+                Some(next) => ret_val.push(next),
+                None => break
+            }
+        }
+        ret_val
+    }
+
+    // Random next state weighted by the transition probabilities
+    pub fn sample_next<R: Rng + ?Sized>(&self, state: &String, rng: &mut R) -> Option<String> {
+        sample_weighted(self.states.get(state)?, rng)
+    }
+
+    // Like generate but every transition is drawn from the rng
+    pub fn sample<R: Rng + ?Sized>(&self, start: &str, length: usize, rng: &mut R) -> Vec<String> {
+        let mut ret_val = vec![start.to_owned()];
+        while ret_val.len() < length {
+            match self.sample_next(ret_val.last().expect("empty chain"), rng) {
+                Some(next) => ret_val.push(next),
+                None => break
+            }
+        }
+        ret_val
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::events;
+use crate::markov_chain::*;
+
+/*
+Sharded training: every shard of a corpus is counted on its own into a count file,
+merging the count files of all shards gives the same states as training on the whole corpus.
+Count files hold every transition count, the 100 to states limit is applied after the merge.
+Checkpoints of a long training run use the same format with the position in the input.
+*/
+
+// First line of a count file, tells count files and model files apart
+const COUNTS_HEADER: &str = "<<MARKOV COUNTS>>";
+// First line of a checkpoint followed by transitions counted/transitions in the input
+const CHECKPOINT_HEADER: &str = "<<MARKOV CHECKPOINT>>";
+
+impl MarkovChain {
+    // Writes to file_name.partial first so a shard that fails part way leaves no count file
+    fn write_count_file(totals: &StateTotals, file_name: &str, header: &str) {
+        let partial_file = format!("{}.partial", file_name);
+        let mut file = File::create(&partial_file).expect("Error creating file object");
+        file.write_all(format!("{}\n", header).as_bytes()).expect("Error writing to file");
+        MarkovChain::write_lines(totals, &mut file);
+        drop(file);
+        fs::rename(&partial_file, file_name).expect("Error renaming count file");
+    }
+
+    // (header line, counts)
+    fn read_count_file(file_name: &str) -> (String, StateTotals) {
+        let file = File::open(file_name).unwrap_or_else(|_| panic!("Error opening count file: {}", file_name));
+        let mut lines = BufReader::new(file).lines();
+        let header = lines.next().map(|ln| ln.expect("Error reading line")).unwrap_or_default();
+        let mut totals = StateTotals::new();
+        for ln in lines {
+            let line = ln.expect("Error reading line");
+            if line.is_empty() { continue; }
+            let (from_word, map) = MarkovChain::parse_line::<i32>(&line);
+            totals.insert(from_word, map);
+        }
+        (header, totals)
+    }
+
+    pub fn save_counts(totals: &StateTotals, file_name: &str) {
+        events::timed("save markov counts", || MarkovChain::write_count_file(totals, file_name, COUNTS_HEADER));
+    }
+
+    pub fn load_counts(file_name: &str) -> StateTotals {
+        let (header, totals) = MarkovChain::read_count_file(file_name);
+        if !header.eq(COUNTS_HEADER) { panic!("Not a markov count file: {}", file_name) }
+        totals
+    }
+
+    // Sums the count files of every shard, in any order
+    pub fn merge_count_files(file_names: &[String]) -> StateTotals {
+        let mut totals = StateTotals::new();
+        for (i, file_name) in file_names.iter().enumerate() {
+            MarkovChain::merge_totals(&mut totals, MarkovChain::load_counts(file_name));
+            events::progress("merge counts", i + 1, Some(file_names.len()));
+        }
+        totals
+    }
+
+    // Counts the input in blocks of checkpoint_every transitions and saves the counts so far
+    // with the position after every block. A run with an existing checkpoint skips the input
+    // it already counted, the states are the same as MarkovChain::train on the whole input
+    pub fn train_checkpointed(input_data: &[InputTup], checkpoint_file: &str, checkpoint_every: usize) -> StateMap {
+        if checkpoint_every == 0 { panic!("checkpoint_every must be at least 1") }
+        let (mut totals, mut position) = if Path::new(checkpoint_file).exists() {
+            let (header, totals) = MarkovChain::read_count_file(checkpoint_file);
+            let (position, total) = header
+                .strip_prefix(CHECKPOINT_HEADER)
+                .and_then(|progress| progress.split_once('/'))
+                .and_then(|(position, total)| Some((position.parse::<usize>().ok()?, total.parse::<usize>().ok()?)))
+                .unwrap_or_else(|| panic!("Not a markov checkpoint: {}", checkpoint_file));
+            if total != input_data.len() {
+                panic!("Checkpoint {} was made from {} transitions, the input has {}, delete it to start over", checkpoint_file, total, input_data.len());
+            }
+            events::message(&format!("Resuming markov training from {} at transition {} of {}", checkpoint_file, position, total));
+            (totals, position)
+        } else {
+            (StateTotals::new(), 0)
+        };
+
+        while position < input_data.len() {
+            let end = usize::min(position + checkpoint_every, input_data.len());
+            MarkovChain::merge_totals(&mut totals, MarkovChain::count(&input_data[position..end]));
+            position = end;
+            let header = format!("{}{}/{}", CHECKPOINT_HEADER, position, input_data.len());
+            events::timed("save markov checkpoint", || MarkovChain::write_count_file(&totals, checkpoint_file, &header));
+            events::progress("markov checkpoint", position, Some(input_data.len()));
+        }
+        events::timed("calculate", || MarkovChain::states_from_totals(&totals))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::shard;
+    use std::{env, process};
+
+    #[test]
+    fn merged_shards_equal_training_on_everything() {
+        let input = ["a b", "b c", "a b", "b a", "c a", "a c", "b c"]
+            .iter()
+            .map(|pair| pair.split_once(' ').map(|(from, to)| (String::from(from), String::from(to))).unwrap())
+            .collect::<Vec<InputTup>>();
+        let dir = env::temp_dir().join(format!("markov_shard_test_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = (0..3)
+            .map(|i| {
+                let file = dir.join(format!("{}.cnt", i)).to_string_lossy().to_string();
+                MarkovChain::save_counts(&MarkovChain::count(shard(&input, i, 3)), &file);
+                file
+            })
+            .collect::<Vec<String>>();
+        let merged = MarkovChain::merge_count_files(&files);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(MarkovChain::states_from_totals(&merged), MarkovChain::train(input));
+    }
+
+    #[test]
+    fn resumed_training_equals_training_on_everything() {
+        let input = ["a b", "b c", "a b", "b a", "c a", "a c", "b c", "c b"]
+            .iter()
+            .map(|pair| pair.split_once(' ').map(|(from, to)| (String::from(from), String::from(to))).unwrap())
+            .collect::<Vec<InputTup>>();
+        let dir = env::temp_dir().join(format!("markov_checkpoint_test_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let checkpoint = dir.join("markov.ckpt").to_string_lossy().to_string();
+
+        // without a checkpoint every block is counted and saved
+        assert_eq!(MarkovChain::train_checkpointed(&input, &checkpoint, 3), MarkovChain::train(input.clone()));
+        fs::remove_file(&checkpoint).unwrap();
+
+        // the state a run interrupted after its first block of 3 leaves behind
+        let header = format!("{}3/{}", CHECKPOINT_HEADER, input.len());
+        MarkovChain::write_count_file(&MarkovChain::count(&input[..3]), &checkpoint, &header);
+        assert_eq!(MarkovChain::train_checkpointed(&input, &checkpoint, 3), MarkovChain::train(input.clone()));
+
+        // a finished checkpoint is not counted again
+        let header = format!("{}{}/{}", CHECKPOINT_HEADER, input.len(), input.len());
+        MarkovChain::write_count_file(&MarkovChain::count(&input[..3]), &checkpoint, &header);
+        let resumed = MarkovChain::train_checkpointed(&input, &checkpoint, 3);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(resumed, MarkovChain::train(input[..3].to_vec()));
+    }
+}
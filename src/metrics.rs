@@ -0,0 +1,385 @@
+use itertools::Itertools;
+use json::{JsonValue, object};
+use std::fmt;
+use std::ops::Index;
+
+// (actual label, predicted label), None when the model was inconclusive
+pub type Prediction = (String, Option<String>);
+
+#[derive(Clone, Debug)]
+pub struct ClassMetrics {
+    pub label: String,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    // number of inputs with this actual label
+    pub support: usize
+}
+
+#[derive(Clone, Debug)]
+pub struct AverageMetrics {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64
+}
+
+#[derive(Clone, Debug)]
+pub struct ClassificationReport {
+    // every label seen as an actual or predicted value, sorted
+    pub labels: Vec<String>,
+    // confusion_matrix[actual][predicted] indexed by position in labels
+    pub confusion_matrix: Vec<Vec<usize>>,
+    // inputs the model could not classify
+    pub inconclusive: usize,
+    pub total: usize,
+    pub accuracy: f64,
+    pub per_class: Vec<ClassMetrics>,
+    pub macro_avg: AverageMetrics,
+    pub micro_avg: AverageMetrics
+}
+
+fn safe_div(num: f64, den: f64) -> f64 {
+    if den == 0.0 { 0.0 } else { num / den }
+}
+
+fn f1_score(precision: f64, recall: f64) -> f64 {
+    safe_div(2.0 * precision * recall, precision + recall)
+}
+
+impl ClassificationReport {
+    pub fn from_predictions(predictions: &Vec<Prediction>) -> ClassificationReport {
+        let labels = predictions
+            .iter()
+            .flat_map(|(actual, o_predicted)| vec![Some(actual.clone()), o_predicted.clone()])
+            .flatten()
+            .sorted()
+            .dedup()
+            .collect_vec();
+        let label_index = |label: &String| labels.binary_search(label).expect("label missing from report");
+
+        let mut confusion_matrix = vec![vec![0_usize; labels.len()]; labels.len()];
+        let mut inconclusive = 0;
+        for (actual, o_predicted) in predictions {
+            match o_predicted {
+                Some(predicted) => confusion_matrix[label_index(actual)][label_index(predicted)] += 1,
+                None => inconclusive += 1
+            }
+        }
+
+        let total = predictions.len();
+        let mut total_correct = 0;
+        let mut total_predicted = 0;
+        let mut per_class = Vec::new();
+        for (i, label) in labels.iter().enumerate() {
+            let true_positives = confusion_matrix.index(i)[i];
+            let predicted: usize = confusion_matrix.iter().map(|row| row[i]).sum();
+            let support = predictions.iter().filter(|(actual, _)| actual == label).count();
+            total_correct += true_positives;
+            total_predicted += predicted;
+
+            let precision = safe_div(true_positives as f64, predicted as f64);
+            let recall = safe_div(true_positives as f64, support as f64);
+            per_class.push(ClassMetrics {
+                label: label.clone(),
+                precision,
+                recall,
+                f1: f1_score(precision, recall),
+                support
+            });
+        }
+
+        let num_labels = per_class.len() as f64;
+        let macro_avg = AverageMetrics {
+            precision: safe_div(per_class.iter().map(|m| m.precision).sum(), num_labels),
+            recall: safe_div(per_class.iter().map(|m| m.recall).sum(), num_labels),
+            f1: safe_div(per_class.iter().map(|m| m.f1).sum(), num_labels)
+        };
+
+        // inconclusive inputs count against recall but not precision
+        let micro_precision = safe_div(total_correct as f64, total_predicted as f64);
+        let micro_recall = safe_div(total_correct as f64, total as f64);
+        let micro_avg = AverageMetrics {
+            precision: micro_precision,
+            recall: micro_recall,
+            f1: f1_score(micro_precision, micro_recall)
+        };
+
+        ClassificationReport {
+            labels,
+            confusion_matrix,
+            inconclusive,
+            total,
+            accuracy: safe_div(total_correct as f64, total as f64),
+            per_class,
+            macro_avg,
+            micro_avg
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let per_class = self.per_class
+            .iter()
+            .map(|m| object!{
+                label: m.label.clone(),
+                precision: m.precision,
+                recall: m.recall,
+                f1: m.f1,
+                support: m.support
+            })
+            .collect_vec();
+        object!{
+            labels: self.labels.clone(),
+            confusion_matrix: self.confusion_matrix.clone(),
+            inconclusive: self.inconclusive,
+            total: self.total,
+            accuracy: self.accuracy,
+            per_class: per_class,
+            macro_avg: object!{
+                precision: self.macro_avg.precision,
+                recall: self.macro_avg.recall,
+                f1: self.macro_avg.f1
+            },
+            micro_avg: object!{
+                precision: self.micro_avg.precision,
+                recall: self.micro_avg.recall,
+                f1: self.micro_avg.f1
+            }
+        }
+    }
+}
+
+impl fmt::Display for ClassificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.labels.iter().map(|l| l.len()).max().unwrap_or(0).max(9);
+
+        writeln!(f, "Confusion matrix (rows: actual, columns: predicted)")?;
+        write!(f, "{:width$}", "", width = width)?;
+        for label in &self.labels {
+            write!(f, " {:>width$}", label, width = width)?;
+        }
+        writeln!(f)?;
+        for (label, row) in self.labels.iter().zip(&self.confusion_matrix) {
+            write!(f, "{:width$}", label, width = width)?;
+            for count in row {
+                write!(f, " {:>width$}", count, width = width)?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{:width$} {:>9} {:>9} {:>9} {:>9}", "", "precision", "recall", "f1", "support", width = width)?;
+        for m in &self.per_class {
+            writeln!(f, "{:width$} {:>9.4} {:>9.4} {:>9.4} {:>9}", m.label, m.precision, m.recall, m.f1, m.support, width = width)?;
+        }
+        for (name, avg) in [("macro avg", &self.macro_avg), ("micro avg", &self.micro_avg)] {
+            writeln!(f, "{:width$} {:>9.4} {:>9.4} {:>9.4} {:>9}", name, avg.precision, avg.recall, avg.f1, self.total, width = width)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Accuracy: {:.4}", self.accuracy)?;
+        write!(f, "Inconclusive: {} of {}", self.inconclusive, self.total)
+    }
+}
+
+// (actual labels, predicted labels) for one input of a multi label model
+pub type MultiLabelPrediction = (Vec<String>, Vec<String>);
+
+#[derive(Clone, Debug)]
+pub struct MultiLabelReport {
+    // every label seen as an actual or predicted value, sorted
+    pub labels: Vec<String>,
+    pub total: usize,
+    // share of (input, label) decisions that were wrong
+    pub hamming_loss: f64,
+    // share of inputs whose predicted label set is exactly right
+    pub subset_accuracy: f64,
+    pub per_label: Vec<ClassMetrics>,
+    pub macro_avg: AverageMetrics,
+    pub micro_avg: AverageMetrics
+}
+
+impl MultiLabelReport {
+    pub fn from_predictions(predictions: &Vec<MultiLabelPrediction>) -> MultiLabelReport {
+        let labels = predictions
+            .iter()
+            .flat_map(|(actual, predicted)| actual.iter().chain(predicted.iter()))
+            .cloned()
+            .sorted()
+            .dedup()
+            .collect_vec();
+
+        let total = predictions.len();
+        let mut exact = 0;
+        let mut wrong_decisions = 0;
+        for (actual, predicted) in predictions {
+            let missed = actual.iter().filter(|l| !predicted.contains(l)).count();
+            let extra = predicted.iter().filter(|l| !actual.contains(l)).count();
+            if missed + extra == 0 {
+                exact += 1;
+            }
+            wrong_decisions += missed + extra;
+        }
+
+        let (mut all_tp, mut all_predicted, mut all_actual) = (0, 0, 0);
+        let mut per_label = Vec::new();
+        for label in &labels {
+            let true_positives = predictions.iter().filter(|(a, p)| a.contains(label) && p.contains(label)).count();
+            let predicted = predictions.iter().filter(|(_, p)| p.contains(label)).count();
+            let support = predictions.iter().filter(|(a, _)| a.contains(label)).count();
+            all_tp += true_positives;
+            all_predicted += predicted;
+            all_actual += support;
+
+            let precision = safe_div(true_positives as f64, predicted as f64);
+            let recall = safe_div(true_positives as f64, support as f64);
+            per_label.push(ClassMetrics { label: label.clone(), precision, recall, f1: f1_score(precision, recall), support });
+        }
+
+        let num_labels = per_label.len() as f64;
+        let macro_avg = AverageMetrics {
+            precision: safe_div(per_label.iter().map(|m| m.precision).sum(), num_labels),
+            recall: safe_div(per_label.iter().map(|m| m.recall).sum(), num_labels),
+            f1: safe_div(per_label.iter().map(|m| m.f1).sum(), num_labels)
+        };
+        let micro_precision = safe_div(all_tp as f64, all_predicted as f64);
+        let micro_recall = safe_div(all_tp as f64, all_actual as f64);
+        let micro_avg = AverageMetrics {
+            precision: micro_precision,
+            recall: micro_recall,
+            f1: f1_score(micro_precision, micro_recall)
+        };
+
+        MultiLabelReport {
+            total,
+            hamming_loss: safe_div(wrong_decisions as f64, (total * labels.len()) as f64),
+            subset_accuracy: safe_div(exact as f64, total as f64),
+            labels,
+            per_label,
+            macro_avg,
+            micro_avg
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let per_label = self.per_label
+            .iter()
+            .map(|m| object!{
+                label: m.label.clone(),
+                precision: m.precision,
+                recall: m.recall,
+                f1: m.f1,
+                support: m.support
+            })
+            .collect_vec();
+        object!{
+            labels: self.labels.clone(),
+            total: self.total,
+            hamming_loss: self.hamming_loss,
+            subset_accuracy: self.subset_accuracy,
+            per_label: per_label,
+            macro_avg: object!{
+                precision: self.macro_avg.precision,
+                recall: self.macro_avg.recall,
+                f1: self.macro_avg.f1
+            },
+            micro_avg: object!{
+                precision: self.micro_avg.precision,
+                recall: self.micro_avg.recall,
+                f1: self.micro_avg.f1
+            }
+        }
+    }
+}
+
+impl fmt::Display for MultiLabelReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.labels.iter().map(|l| l.len()).max().unwrap_or(0).max(9);
+        writeln!(f, "{:width$} {:>9} {:>9} {:>9} {:>9}", "", "precision", "recall", "f1", "support", width = width)?;
+        for m in &self.per_label {
+            writeln!(f, "{:width$} {:>9.4} {:>9.4} {:>9.4} {:>9}", m.label, m.precision, m.recall, m.f1, m.support, width = width)?;
+        }
+        for (name, avg) in [("macro avg", &self.macro_avg), ("micro avg", &self.micro_avg)] {
+            writeln!(f, "{:width$} {:>9.4} {:>9.4} {:>9.4} {:>9}", name, avg.precision, avg.recall, avg.f1, self.total, width = width)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Hamming loss: {:.4}", self.hamming_loss)?;
+        write!(f, "Subset accuracy: {:.4}", self.subset_accuracy)
+    }
+}
+
+// Mean and sample variance of a metric over repeated runs (e.g. cross validation folds)
+#[derive(Clone, Debug)]
+pub struct MetricSummary {
+    pub mean: f64,
+    pub variance: f64
+}
+
+impl MetricSummary {
+    pub fn from_values(values: &[f64]) -> MetricSummary {
+        let n = values.len() as f64;
+        let mean = safe_div(values.iter().sum(), n);
+        let variance = if values.len() < 2 { 0.0 } else {
+            values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (n - 1.0)
+        };
+        MetricSummary { mean, variance }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object!{
+            mean: self.mean,
+            variance: self.variance
+        }
+    }
+}
+
+impl fmt::Display for MetricSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.4} (variance {:.6})", self.mean, self.variance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn classification_report_from_confusion_matrix() {
+        let predictions = [("a", Some("a")), ("a", Some("a")), ("a", Some("b")), ("b", Some("b")), ("b", Some("a")), ("c", Some("c")), ("c", None)]
+            .iter()
+            .map(|(actual, predicted)| (String::from(*actual), predicted.map(String::from)))
+            .collect_vec();
+        let report = ClassificationReport::from_predictions(&predictions);
+        assert_eq!(report.labels, vec!["a", "b", "c"]);
+        assert_eq!(report.confusion_matrix, vec![vec![2, 1, 0], vec![1, 1, 0], vec![0, 0, 1]]);
+        assert_eq!((report.inconclusive, report.total), (1, 7));
+        assert!(close(report.accuracy, 4.0 / 7.0));
+
+        let expected = [(2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 3), (0.5, 0.5, 0.5, 2), (1.0, 0.5, 2.0 / 3.0, 2)];
+        for (metrics, (precision, recall, f1, support)) in report.per_class.iter().zip(expected) {
+            assert!(close(metrics.precision, precision) && close(metrics.recall, recall) && close(metrics.f1, f1), "{:?}", metrics);
+            assert_eq!(metrics.support, support);
+        }
+        assert!(close(report.macro_avg.precision, 13.0 / 18.0));
+        assert!(close(report.macro_avg.recall, 5.0 / 9.0));
+        // the inconclusive input counts against recall only
+        assert!(close(report.micro_avg.precision, 4.0 / 6.0));
+        assert!(close(report.micro_avg.recall, 4.0 / 7.0));
+        assert!(close(report.micro_avg.f1, 8.0 / 13.0));
+    }
+
+    #[test]
+    fn multi_label_report() {
+        let labels = |s: &str| s.split(',').filter(|l| !l.is_empty()).map(String::from).collect_vec();
+        let predictions = vec![(labels("a,b"), labels("a,b")), (labels("a"), labels("a,b")), (labels("b"), labels(""))];
+        let report = MultiLabelReport::from_predictions(&predictions);
+        assert!(close(report.subset_accuracy, 1.0 / 3.0));
+        // 2 wrong decisions out of 3 inputs * 2 labels
+        assert!(close(report.hamming_loss, 2.0 / 6.0));
+        assert!(close(report.micro_avg.precision, 3.0 / 4.0));
+        assert!(close(report.micro_avg.recall, 3.0 / 4.0));
+    }
+}
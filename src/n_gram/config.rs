@@ -1,13 +1,16 @@
-use std::{str::FromStr, fmt::Debug, fs::File, io::Read};
-use json::{JsonValue, parse};
+use std::{fmt, fs::File, io::{Read, Write}};
+use json::{JsonValue, object, parse};
 
 use crate::n_gram::NGram;
+use crate::n_gram::feature_selection::FeatureSelectionConfig;
+use crate::n_gram::gradient::GradientConfig;
 
 /*
 Config file structure:
 if the config has a key for the pruning type it is automatically selected to be run
 if the "selection" object has a key set to true then it will use the default config
 if the object for a pruning type is missing some elements, then the default config will be used
+unknown keys, values of the wrong type and values out of range are rejected
 {
     probability: {
         starting_probability: 0,
@@ -23,29 +26,140 @@ if the object for a pruning type is missing some elements, then the default conf
         min_count: 0,
         adjust_amount: 0
     },
-    randomizer: {
-        num_params: 0,
-        step_size: 0,
-        iterations: 0
+    gradient: {
+        epochs: 0,
+        learning_rate: 0,
+        l2: 0,
+        patience: 0,
+        validation_fraction: 0,
+        seed: 0,
+        checkpoint: "data/gradient.ckpt",
+        checkpoint_every: 1
+    },
+    features: {
+        method: "chi_squared" | "mutual_information" | "information_gain",
+        top_k: 0,
+        threshold: 0
     },
     selection: {
         probability: true,
         similarity: true,
         count: true,
-        randomizer: true
+        gradient: true,
+        features: true
     }
 }
 */
 
-fn get_json<T>(obj: &JsonValue, c: &str, k: &str, def: T) -> T where T: FromStr, <T as FromStr>::Err: Debug {
-    let err_s = "Error parsing: ".to_owned() + c.clone() + "-" + k.clone();
-    if obj.has_key(k) {
-        obj[k].dump().parse::<T>().expect(&err_s)
-    } else {
-        def
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    Io(String),
+    Parse(String),
+    // (section, key)
+    UnknownKey(String, String),
+    // (section, key, expected type)
+    WrongType(String, String, String),
+    // (section, key, allowed range)
+    OutOfRange(String, String, String)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(message) => write!(f, "Error reading config: {}", message),
+            ConfigError::Parse(message) => write!(f, "Error parsing config: {}", message),
+            ConfigError::UnknownKey(section, key) => write!(f, "Unknown config key: {}-{}", section, key),
+            ConfigError::WrongType(section, key, expected) => write!(f, "Config value {}-{} must be {}", section, key, expected),
+            ConfigError::OutOfRange(section, key, range) => write!(f, "Config value {}-{} must be {}", section, key, range)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub(crate) fn check_keys(obj: &JsonValue, section: &str, allowed: &[&str]) -> Result<(), ConfigError> {
+    if !obj.is_object() {
+        return Err(ConfigError::WrongType(String::from(section), String::from(""), String::from("an object")));
+    }
+    for (k, _) in obj.entries() {
+        if !allowed.contains(&k) {
+            return Err(ConfigError::UnknownKey(String::from(section), String::from(k)));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn out_of_range<T>(section: &str, key: &str, range: &str) -> Result<T, ConfigError> {
+    Err(ConfigError::OutOfRange(String::from(section), String::from(key), String::from(range)))
+}
+
+fn wrong_type<T>(section: &str, key: &str, expected: &str) -> Result<T, ConfigError> {
+    Err(ConfigError::WrongType(String::from(section), String::from(key), String::from(expected)))
+}
+
+pub(crate) fn get_f32(obj: &JsonValue, section: &str, key: &str, def: f32) -> Result<f32, ConfigError> {
+    if !obj.has_key(key) { return Ok(def); }
+    match obj[key].as_f32() {
+        Some(v) if v.is_finite() => Ok(v),
+        _ => wrong_type(section, key, "a number")
+    }
+}
+
+pub(crate) fn get_f64(obj: &JsonValue, section: &str, key: &str, def: f64) -> Result<f64, ConfigError> {
+    if !obj.has_key(key) { return Ok(def); }
+    match obj[key].as_f64() {
+        Some(v) if v.is_finite() => Ok(v),
+        _ => wrong_type(section, key, "a number")
+    }
+}
+
+pub(crate) fn get_i32(obj: &JsonValue, section: &str, key: &str, def: i32) -> Result<i32, ConfigError> {
+    if !obj.has_key(key) { return Ok(def); }
+    match obj[key].as_f64() {
+        Some(v) if v.fract() == 0.0 && v >= i32::MIN as f64 && v <= i32::MAX as f64 => Ok(v as i32),
+        _ => wrong_type(section, key, "an integer")
+    }
+}
+
+pub(crate) fn get_u64(obj: &JsonValue, section: &str, key: &str, def: u64) -> Result<u64, ConfigError> {
+    if !obj.has_key(key) { return Ok(def); }
+    match obj[key].as_u64() {
+        Some(v) => Ok(v),
+        None => wrong_type(section, key, "a non negative integer")
+    }
+}
+
+pub(crate) fn get_bool(obj: &JsonValue, section: &str, key: &str, def: bool) -> Result<bool, ConfigError> {
+    if !obj.has_key(key) { return Ok(def); }
+    match obj[key].as_bool() {
+        Some(v) => Ok(v),
+        None => wrong_type(section, key, "true or false")
+    }
+}
+
+pub(crate) fn get_str<'a>(obj: &'a JsonValue, section: &str, key: &str) -> Result<Option<&'a str>, ConfigError> {
+    if !obj.has_key(key) { return Ok(None); }
+    match obj[key].as_str() {
+        Some(v) => Ok(Some(v)),
+        None => wrong_type(section, key, "a string")
     }
 }
 
+fn check_fraction(section: &str, key: &str, value: f32) -> Result<(), ConfigError> {
+    if !(0.0..=1.0).contains(&value) {
+        return out_of_range(section, key, "between 0 and 1");
+    }
+    Ok(())
+}
+
+fn check_multiplyer(section: &str, value: f32) -> Result<(), ConfigError> {
+    if value <= 1.0 {
+        return out_of_range(section, "probability_multiplyer", "greater than 1");
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct PruneProbabilityConfig {
     // Starts at this probability
     pub starting_probability: f32,
@@ -55,25 +169,49 @@ pub struct PruneProbabilityConfig {
     pub probability_multiplyer: f32,
 }
 
-impl PruneProbabilityConfig {
-    pub fn default() -> PruneProbabilityConfig {
-        PruneProbabilityConfig { 
+impl Default for PruneProbabilityConfig {
+    fn default() -> PruneProbabilityConfig {
+        PruneProbabilityConfig {
             starting_probability: 0.00001,
             max_accuracy_reduction: 0.1,
             probability_multiplyer: 2.0
         }
     }
+}
+
+impl PruneProbabilityConfig {
+    pub fn from_json(obj: &JsonValue) -> Result<PruneProbabilityConfig, ConfigError> {
+        let def = PruneProbabilityConfig::default();
+        let probability_s = "probability";
+        check_keys(obj, probability_s, &["starting_probability", "max_accuracy_reduction", "probability_multiplyer"])?;
+        let config = PruneProbabilityConfig {
+            starting_probability: get_f32(obj, probability_s, "starting_probability", def.starting_probability)?,
+            max_accuracy_reduction: get_f32(obj, probability_s, "max_accuracy_reduction", def.max_accuracy_reduction)?,
+            probability_multiplyer: get_f32(obj, probability_s, "probability_multiplyer", def.probability_multiplyer)?
+        };
+        config.validate()?;
+        Ok(config)
+    }
 
-    pub fn from_json(obj: &JsonValue) -> PruneProbabilityConfig {
-        let mut tmp_config = PruneProbabilityConfig::default();
+    pub fn validate(&self) -> Result<(), ConfigError> {
         let probability_s = "probability";
-        tmp_config.starting_probability = get_json(obj, probability_s, "starting_probability", tmp_config.starting_probability);
-        tmp_config.starting_probability = get_json(obj, probability_s, "max_accuracy_reduction", tmp_config.max_accuracy_reduction);
-        tmp_config.starting_probability = get_json(obj, probability_s, "probability_multiplyer", tmp_config.probability_multiplyer);
-        tmp_config
+        if self.starting_probability <= 0.0 || self.starting_probability > 1.0 {
+            return out_of_range(probability_s, "starting_probability", "greater than 0 and at most 1");
+        }
+        check_fraction(probability_s, "max_accuracy_reduction", self.max_accuracy_reduction)?;
+        check_multiplyer(probability_s, self.probability_multiplyer)
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object!{
+            starting_probability: self.starting_probability,
+            max_accuracy_reduction: self.max_accuracy_reduction,
+            probability_multiplyer: self.probability_multiplyer
+        }
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct PruneSimilarityConfig {
     // Starts at this deviation
     pub starting_deviation: f32,
@@ -83,158 +221,374 @@ pub struct PruneSimilarityConfig {
     pub probability_multiplyer: f32
 }
 
-impl PruneSimilarityConfig {
-    pub fn default() -> PruneSimilarityConfig {
-        PruneSimilarityConfig { 
-            starting_deviation: 0.00000001, 
-            max_accuracy_reduction: 0.1, 
+impl Default for PruneSimilarityConfig {
+    fn default() -> PruneSimilarityConfig {
+        PruneSimilarityConfig {
+            starting_deviation: 0.00000001,
+            max_accuracy_reduction: 0.1,
             probability_multiplyer: 2.0
         }
     }
+}
 
-    pub fn from_json(obj: &JsonValue) -> PruneSimilarityConfig {
-        let mut tmp_config = PruneSimilarityConfig::default();
+impl PruneSimilarityConfig {
+    pub fn from_json(obj: &JsonValue) -> Result<PruneSimilarityConfig, ConfigError> {
+        let def = PruneSimilarityConfig::default();
         let similarity_s = "similarity";
-        tmp_config.starting_deviation = get_json(obj, similarity_s, "starting_deviation", tmp_config.starting_deviation);
-        tmp_config.max_accuracy_reduction = get_json(obj, similarity_s, "max_accuracy_reduction", tmp_config.max_accuracy_reduction);
-        tmp_config.probability_multiplyer = get_json(obj, similarity_s, "probability_multiplyer", tmp_config.probability_multiplyer);
-        tmp_config
+        check_keys(obj, similarity_s, &["starting_deviation", "max_accuracy_reduction", "probability_multiplyer"])?;
+        let config = PruneSimilarityConfig {
+            starting_deviation: get_f32(obj, similarity_s, "starting_deviation", def.starting_deviation)?,
+            max_accuracy_reduction: get_f32(obj, similarity_s, "max_accuracy_reduction", def.max_accuracy_reduction)?,
+            probability_multiplyer: get_f32(obj, similarity_s, "probability_multiplyer", def.probability_multiplyer)?
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let similarity_s = "similarity";
+        if self.starting_deviation <= 0.0 || self.starting_deviation > 1.0 {
+            return out_of_range(similarity_s, "starting_deviation", "greater than 0 and at most 1");
+        }
+        check_fraction(similarity_s, "max_accuracy_reduction", self.max_accuracy_reduction)?;
+        check_multiplyer(similarity_s, self.probability_multiplyer)
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object!{
+            starting_deviation: self.starting_deviation,
+            max_accuracy_reduction: self.max_accuracy_reduction,
+            probability_multiplyer: self.probability_multiplyer
+        }
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct PruneCountConfig {
     pub min_count: i32,
     pub adjust_amount: f32
 }
 
-impl PruneCountConfig {
-    pub fn default() -> PruneCountConfig {
-        PruneCountConfig { 
+impl Default for PruneCountConfig {
+    fn default() -> PruneCountConfig {
+        PruneCountConfig {
             min_count: 2,
             adjust_amount: 0.01
         }
     }
+}
 
-    pub fn from_json(obj: &JsonValue) -> PruneCountConfig {
-        let mut tmp_config = PruneCountConfig::default();
+impl PruneCountConfig {
+    pub fn from_json(obj: &JsonValue) -> Result<PruneCountConfig, ConfigError> {
+        let def = PruneCountConfig::default();
         let count_s = "count";
-        tmp_config.min_count = get_json::<i32>(obj, count_s, "min_count", tmp_config.min_count);
-        tmp_config.adjust_amount = get_json(obj, count_s, "adjust_amount", tmp_config.adjust_amount);
-        tmp_config
+        check_keys(obj, count_s, &["min_count", "adjust_amount"])?;
+        let config = PruneCountConfig {
+            min_count: get_i32(obj, count_s, "min_count", def.min_count)?,
+            adjust_amount: get_f32(obj, count_s, "adjust_amount", def.adjust_amount)?
+        };
+        config.validate()?;
+        Ok(config)
     }
-}
-
-#[derive(Clone)]
-pub struct RandomizerConfig {
-    pub num_params: i32,
-    pub step_size: f32,
-    pub iterations: i32
-}
 
-impl RandomizerConfig {
-    pub fn default() -> RandomizerConfig {
-        RandomizerConfig { 
-            num_params: 10, 
-            step_size: 0.001,
-            iterations: 1000
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let count_s = "count";
+        if self.min_count < 0 {
+            return out_of_range(count_s, "min_count", "at least 0");
         }
+        if self.adjust_amount < 0.0 || self.adjust_amount >= 1.0 {
+            return out_of_range(count_s, "adjust_amount", "at least 0 and less than 1");
+        }
+        Ok(())
     }
 
-    pub fn from_json(obj: &JsonValue) -> RandomizerConfig {
-        let mut tmp_config = RandomizerConfig::default();
-        let rand_s = "randomizer";
-        tmp_config.num_params = get_json(obj, rand_s, "num_params", tmp_config.num_params);
-        tmp_config.step_size = get_json(obj, rand_s, "step_size", tmp_config.step_size);
-        tmp_config.num_params = get_json(obj, rand_s, "iterations", tmp_config.iterations);
-        tmp_config
+    pub fn to_json(&self) -> JsonValue {
+        object!{
+            min_count: self.min_count,
+            adjust_amount: self.adjust_amount
+        }
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Default)]
 pub struct PruneSelectionConfig {
     pub probability: bool,
     pub similarity: bool,
     pub count: bool,
-    pub randomizer: bool
+    pub gradient: bool,
+    pub features: bool
 }
 
 impl PruneSelectionConfig {
-    pub fn default() -> PruneSelectionConfig {
-        PruneSelectionConfig { 
-            probability: false, 
-            similarity: false, 
-            count: false, 
-            randomizer: false 
-        }
+    pub fn from_json(obj: &JsonValue) -> Result<PruneSelectionConfig, ConfigError> {
+        let select_s = "selection";
+        check_keys(obj, select_s, &["probability", "similarity", "count", "gradient", "features"])?;
+        Ok(PruneSelectionConfig {
+            probability: get_bool(obj, select_s, "probability", false)?,
+            similarity: get_bool(obj, select_s, "similarity", false)?,
+            count: get_bool(obj, select_s, "count", false)?,
+            gradient: get_bool(obj, select_s, "gradient", false)?,
+            features: get_bool(obj, select_s, "features", false)?
+        })
     }
 
-    pub fn from_json(obj: &JsonValue) -> PruneSelectionConfig {
-        let mut tmp_config = PruneSelectionConfig::default();
-        let select_s = "selection";
-        tmp_config.probability = get_json(obj, select_s, "probability", false);
-        tmp_config.similarity = get_json(obj, select_s, "similarity", false);
-        tmp_config.count = get_json(obj, select_s, "count", false);
-        tmp_config.randomizer = get_json(obj, select_s, "randomizer", false);
-        tmp_config
+    pub fn to_json(&self) -> JsonValue {
+        object!{
+            probability: self.probability,
+            similarity: self.similarity,
+            count: self.count,
+            gradient: self.gradient,
+            features: self.features
+        }
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Default)]
 pub struct LearnConfig {
     pub prune_selection: PruneSelectionConfig,
     pub prune_probability: Option<PruneProbabilityConfig>,
     pub prune_similarity: Option<PruneSimilarityConfig>,
     pub prune_count: Option<PruneCountConfig>,
-    pub randomizer: Option<RandomizerConfig>,
+    pub gradient: Option<GradientConfig>,
+    pub feature_selection: Option<FeatureSelectionConfig>,
 }
 
-impl NGram {
-    pub fn read_config(file_name: &str) -> LearnConfig {
-        let mut config = LearnConfig { 
-            prune_selection: PruneSelectionConfig { 
-                probability: false, 
-                similarity: false, 
-                count: false, 
-                randomizer: false 
-            },
-            prune_probability: None,
-            prune_similarity: None,
-            prune_count: None,
-            randomizer: None
-        };
-        let mut file = File::open(file_name).expect("Creating file object error");
-        let mut file_contents = String::new();
-        file.read_to_string(&mut file_contents).expect("Reading file error");
-        if file_contents.eq("") { panic!("Loading n-gram model: File empty") }
-        let json_data = parse(&file_contents).unwrap();
+impl LearnConfig {
+    // Nothing selected, every strategy uses its default config when selected
+    pub fn from_json(json_data: &JsonValue) -> Result<LearnConfig, ConfigError> {
+        let mut config = LearnConfig::default();
+        check_keys(json_data, "config", &["probability", "similarity", "count", "gradient", "features", "selection"])?;
 
         let probability_s = "probability";
         if json_data.has_key(probability_s) {
             config.prune_selection.probability = true;
-            config.prune_probability = Some(PruneProbabilityConfig::from_json(&json_data[probability_s]));
+            config.prune_probability = Some(PruneProbabilityConfig::from_json(&json_data[probability_s])?);
         }
 
         let similarity_s = "similarity";
         if json_data.has_key(similarity_s) {
             config.prune_selection.similarity = true;
-            config.prune_similarity = Some(PruneSimilarityConfig::from_json(&json_data[similarity_s]));
+            config.prune_similarity = Some(PruneSimilarityConfig::from_json(&json_data[similarity_s])?);
         }
 
         let count_s = "count";
         if json_data.has_key(count_s) {
             config.prune_selection.count = true;
-            config.prune_count = Some(PruneCountConfig::from_json(&json_data[count_s]));
+            config.prune_count = Some(PruneCountConfig::from_json(&json_data[count_s])?);
+        }
+
+        let gradient_s = "gradient";
+        if json_data.has_key(gradient_s) {
+            config.prune_selection.gradient = true;
+            config.gradient = Some(GradientConfig::from_json(&json_data[gradient_s])?);
         }
 
-        let randomizer_s = "randomizer";
-        if json_data.has_key(randomizer_s) {
-            config.prune_selection.randomizer = true;
-            config.randomizer = Some(RandomizerConfig::from_json(&json_data[randomizer_s]));
+        let features_s = "features";
+        if json_data.has_key(features_s) {
+            config.prune_selection.features = true;
+            config.feature_selection = Some(FeatureSelectionConfig::from_json(&json_data[features_s])?);
         }
 
         let selection_s = "selection";
         if json_data.has_key(selection_s) {
-            config.prune_selection = PruneSelectionConfig::from_json(&json_data[selection_s]);
+            config.prune_selection = PruneSelectionConfig::from_json(&json_data[selection_s])?;
+        }
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(config) = &self.prune_probability { config.validate()?; }
+        if let Some(config) = &self.prune_similarity { config.validate()?; }
+        if let Some(config) = &self.prune_count { config.validate()?; }
+        if let Some(config) = &self.gradient { config.validate()?; }
+        if let Some(config) = &self.feature_selection { config.validate()?; }
+        Ok(())
+    }
+
+    // Every section that is set plus the selection, reading this back gives the same config
+    pub fn to_json(&self) -> JsonValue {
+        let mut obj = JsonValue::new_object();
+        if let Some(config) = &self.prune_probability {
+            obj["probability"] = config.to_json();
+        }
+        if let Some(config) = &self.prune_similarity {
+            obj["similarity"] = config.to_json();
         }
+        if let Some(config) = &self.prune_count {
+            obj["count"] = config.to_json();
+        }
+        if let Some(config) = &self.gradient {
+            obj["gradient"] = config.to_json();
+        }
+        if let Some(config) = &self.feature_selection {
+            obj["features"] = config.to_json();
+        }
+        obj["selection"] = self.prune_selection.to_json();
+        obj
+    }
+}
 
-        config
+impl NGram {
+    pub fn parse_config(file_contents: &str) -> Result<LearnConfig, ConfigError> {
+        let json_data = parse(file_contents).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        LearnConfig::from_json(&json_data)
     }
-}
\ No newline at end of file
+
+    pub fn read_config(file_name: &str) -> Result<LearnConfig, ConfigError> {
+        let mut file = File::open(file_name).map_err(|e| ConfigError::Io(format!("{}: {}", file_name, e)))?;
+        let mut file_contents = String::new();
+        file.read_to_string(&mut file_contents).map_err(|e| ConfigError::Io(format!("{}: {}", file_name, e)))?;
+        if file_contents.is_empty() { return Err(ConfigError::Parse(format!("{}: File empty", file_name))) }
+        NGram::parse_config(&file_contents)
+    }
+
+    pub fn write_config(config: &LearnConfig, file_name: &str) {
+        let mut file = File::create(file_name).expect("Creating config file error");
+        file.write_all(config.to_json().pretty(4).as_bytes()).expect("Writing config file error");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::n_gram::feature_selection::FeatureScore;
+
+    fn parse_err(s: &str) -> ConfigError {
+        NGram::parse_config(s).expect_err("config should be rejected")
+    }
+
+    #[test]
+    fn empty_config_selects_nothing() {
+        assert_eq!(NGram::parse_config("{}").unwrap(), LearnConfig::default());
+    }
+
+    #[test]
+    fn probability_fields() {
+        let config = NGram::parse_config(r#"{"probability": {"starting_probability": 0.5, "max_accuracy_reduction": 0.2, "probability_multiplyer": 3}}"#).unwrap();
+        assert!(config.prune_selection.probability);
+        assert_eq!(config.prune_probability, Some(PruneProbabilityConfig {
+            starting_probability: 0.5,
+            max_accuracy_reduction: 0.2,
+            probability_multiplyer: 3.0
+        }));
+        let config = NGram::parse_config(r#"{"probability": {}}"#).unwrap();
+        assert_eq!(config.prune_probability, Some(PruneProbabilityConfig::default()));
+    }
+
+    #[test]
+    fn probability_ranges() {
+        assert!(matches!(parse_err(r#"{"probability": {"starting_probability": 0}}"#), ConfigError::OutOfRange(_, k, _) if k == "starting_probability"));
+        assert!(matches!(parse_err(r#"{"probability": {"max_accuracy_reduction": 1.5}}"#), ConfigError::OutOfRange(_, k, _) if k == "max_accuracy_reduction"));
+        assert!(matches!(parse_err(r#"{"probability": {"probability_multiplyer": 1}}"#), ConfigError::OutOfRange(_, k, _) if k == "probability_multiplyer"));
+    }
+
+    #[test]
+    fn similarity_fields() {
+        let config = NGram::parse_config(r#"{"similarity": {"starting_deviation": 0.01, "max_accuracy_reduction": 0.05, "probability_multiplyer": 1.5}}"#).unwrap();
+        assert!(config.prune_selection.similarity);
+        assert_eq!(config.prune_similarity, Some(PruneSimilarityConfig {
+            starting_deviation: 0.01,
+            max_accuracy_reduction: 0.05,
+            probability_multiplyer: 1.5
+        }));
+    }
+
+    #[test]
+    fn similarity_ranges() {
+        assert!(matches!(parse_err(r#"{"similarity": {"starting_deviation": -1}}"#), ConfigError::OutOfRange(_, k, _) if k == "starting_deviation"));
+        assert!(matches!(parse_err(r#"{"similarity": {"max_accuracy_reduction": -0.1}}"#), ConfigError::OutOfRange(_, k, _) if k == "max_accuracy_reduction"));
+        assert!(matches!(parse_err(r#"{"similarity": {"probability_multiplyer": 0.5}}"#), ConfigError::OutOfRange(_, k, _) if k == "probability_multiplyer"));
+    }
+
+    #[test]
+    fn count_fields() {
+        let config = NGram::parse_config(r#"{"count": {"min_count": 5, "adjust_amount": 0.5}}"#).unwrap();
+        assert!(config.prune_selection.count);
+        assert_eq!(config.prune_count, Some(PruneCountConfig { min_count: 5, adjust_amount: 0.5 }));
+        assert!(matches!(parse_err(r#"{"count": {"min_count": -1}}"#), ConfigError::OutOfRange(_, k, _) if k == "min_count"));
+        assert!(matches!(parse_err(r#"{"count": {"min_count": 2.5}}"#), ConfigError::WrongType(_, k, _) if k == "min_count"));
+        assert!(matches!(parse_err(r#"{"count": {"adjust_amount": 1}}"#), ConfigError::OutOfRange(_, k, _) if k == "adjust_amount"));
+    }
+
+    #[test]
+    fn gradient_fields() {
+        let config = NGram::parse_config(r#"{"gradient": {"epochs": 5, "learning_rate": 0.5, "l2": 0.01, "patience": 2, "validation_fraction": 0.25, "seed": 7, "checkpoint": "g.ckpt", "checkpoint_every": 2}}"#).unwrap();
+        assert!(config.prune_selection.gradient);
+        assert_eq!(config.gradient, Some(GradientConfig {
+            epochs: 5,
+            learning_rate: 0.5,
+            l2: 0.01,
+            patience: 2,
+            validation_fraction: 0.25,
+            seed: 7,
+            checkpoint: Some(String::from("g.ckpt")),
+            checkpoint_every: 2
+        }));
+        assert!(matches!(parse_err(r#"{"gradient": {"epochs": 0}}"#), ConfigError::OutOfRange(_, k, _) if k == "epochs"));
+        assert!(matches!(parse_err(r#"{"gradient": {"learning_rate": 0}}"#), ConfigError::OutOfRange(_, k, _) if k == "learning_rate"));
+        assert!(matches!(parse_err(r#"{"gradient": {"l2": -1}}"#), ConfigError::OutOfRange(_, k, _) if k == "l2"));
+        assert!(matches!(parse_err(r#"{"gradient": {"patience": 0}}"#), ConfigError::OutOfRange(_, k, _) if k == "patience"));
+        assert!(matches!(parse_err(r#"{"gradient": {"validation_fraction": 1}}"#), ConfigError::OutOfRange(_, k, _) if k == "validation_fraction"));
+        assert!(matches!(parse_err(r#"{"gradient": {"seed": -1}}"#), ConfigError::WrongType(_, k, _) if k == "seed"));
+        assert!(matches!(parse_err(r#"{"gradient": {"checkpoint": 1}}"#), ConfigError::WrongType(_, k, _) if k == "checkpoint"));
+        assert!(matches!(parse_err(r#"{"gradient": {"checkpoint_every": 0}}"#), ConfigError::OutOfRange(_, k, _) if k == "checkpoint_every"));
+    }
+
+    #[test]
+    fn features_fields() {
+        let config = NGram::parse_config(r#"{"features": {"method": "mutual_information", "top_k": 10, "threshold": 0.5}}"#).unwrap();
+        assert!(config.prune_selection.features);
+        assert_eq!(config.feature_selection, Some(FeatureSelectionConfig {
+            method: FeatureScore::MutualInformation,
+            top_k: Some(10),
+            threshold: Some(0.5)
+        }));
+        assert!(matches!(parse_err(r#"{"features": {"method": "entropy"}}"#), ConfigError::OutOfRange(_, k, _) if k == "method"));
+        assert!(matches!(parse_err(r#"{"features": {"method": 1}}"#), ConfigError::WrongType(_, k, _) if k == "method"));
+        assert!(matches!(parse_err(r#"{"features": {"top_k": 0}}"#), ConfigError::OutOfRange(_, k, _) if k == "top_k"));
+
+        let mut config = LearnConfig::default();
+        config.feature_selection = Some(FeatureSelectionConfig { method: FeatureScore::ChiSquared, top_k: Some(0), threshold: None });
+        assert!(matches!(config.validate(), Err(ConfigError::OutOfRange(_, k, _)) if k == "top_k"));
+        config.feature_selection = Some(FeatureSelectionConfig { method: FeatureScore::ChiSquared, top_k: None, threshold: Some(f64::NAN) });
+        assert!(matches!(config.validate(), Err(ConfigError::OutOfRange(_, k, _)) if k == "threshold"));
+    }
+
+    #[test]
+    fn selection_overrides_sections() {
+        let config = NGram::parse_config(r#"{"count": {}, "selection": {"count": false, "probability": true, "similarity": true, "gradient": true, "features": true}}"#).unwrap();
+        assert_eq!(config.prune_selection, PruneSelectionConfig {
+            probability: true,
+            similarity: true,
+            count: false,
+            gradient: true,
+            features: true
+        });
+        assert!(config.prune_count.is_some());
+        assert!(matches!(parse_err(r#"{"selection": {"count": 1}}"#), ConfigError::WrongType(_, k, _) if k == "count"));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert_eq!(parse_err(r#"{"randomizer": {}}"#), ConfigError::UnknownKey(String::from("config"), String::from("randomizer")));
+        assert_eq!(parse_err(r#"{"count": {"min_cnt": 1}}"#), ConfigError::UnknownKey(String::from("count"), String::from("min_cnt")));
+        assert!(matches!(parse_err(r#"{"selection": {"all": true}}"#), ConfigError::UnknownKey(_, _)));
+        assert!(matches!(parse_err("[1, 2]"), ConfigError::WrongType(_, _, _)));
+        assert!(matches!(parse_err("{"), ConfigError::Parse(_)));
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut config = LearnConfig::default();
+        config.prune_probability = Some(PruneProbabilityConfig::default());
+        config.prune_similarity = Some(PruneSimilarityConfig::default());
+        config.prune_count = Some(PruneCountConfig { min_count: 3, adjust_amount: 0.25 });
+        config.gradient = Some(GradientConfig::default());
+        config.feature_selection = Some(FeatureSelectionConfig { method: FeatureScore::InformationGain, top_k: None, threshold: Some(0.1) });
+        config.prune_selection.count = true;
+        config.prune_selection.features = true;
+        let parsed = NGram::parse_config(&config.to_json().dump()).unwrap();
+        assert_eq!(parsed, config);
+    }
+}
@@ -0,0 +1,242 @@
+use itertools::Itertools;
+use json::{JsonValue, object};
+use rand::seq::SliceRandom;
+use std::fmt;
+
+use crate::metrics::{ClassMetrics, ClassificationReport, MetricSummary};
+use crate::n_gram::{NGram, ScoringMode};
+use crate::n_gram::config::{ConfigError, out_of_range};
+use crate::random::seeded_rng;
+use crate::util::InputTup;
+
+#[derive(Clone, Debug)]
+pub struct CrossValidationConfig {
+    pub folds: usize,
+    // seed for the shuffle before the inputs are dealt into folds
+    pub seed: u64,
+    pub max_grams: i8,
+    pub scoring: ScoringMode
+}
+
+impl Default for CrossValidationConfig {
+    fn default() -> CrossValidationConfig {
+        CrossValidationConfig {
+            folds: 5,
+            seed: 0,
+            max_grams: 3,
+            scoring: ScoringMode::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClassMetricSummary {
+    pub label: String,
+    pub precision: MetricSummary,
+    pub recall: MetricSummary,
+    pub f1: MetricSummary
+}
+
+#[derive(Clone, Debug)]
+pub struct CrossValidationReport {
+    // report for each held out fold
+    pub folds: Vec<ClassificationReport>,
+    pub accuracy: MetricSummary,
+    pub macro_precision: MetricSummary,
+    pub macro_recall: MetricSummary,
+    pub macro_f1: MetricSummary,
+    pub micro_f1: MetricSummary,
+    // inconclusive inputs / inputs in the fold
+    pub inconclusive_rate: MetricSummary,
+    pub per_class: Vec<ClassMetricSummary>
+}
+
+fn summarize(reports: &[ClassificationReport], f: fn(&ClassificationReport) -> f64) -> MetricSummary {
+    MetricSummary::from_values(&reports.iter().map(f).collect_vec())
+}
+
+impl CrossValidationReport {
+    pub fn from_folds(folds: Vec<ClassificationReport>) -> CrossValidationReport {
+        let labels = folds
+            .iter()
+            .flat_map(|r| r.labels.clone())
+            .sorted()
+            .dedup()
+            .collect_vec();
+
+        // a label missing from a fold scores 0 for that fold
+        let mut per_class = Vec::new();
+        for label in labels {
+            let fold_metrics = folds
+                .iter()
+                .map(|r| r.per_class.iter().find(|m| m.label == label))
+                .collect_vec();
+            let values = |f: fn(&ClassMetrics) -> f64| -> Vec<f64> {
+                fold_metrics.iter().map(|o_m| o_m.map(f).unwrap_or(0.0)).collect_vec()
+            };
+            per_class.push(ClassMetricSummary {
+                precision: MetricSummary::from_values(&values(|m| m.precision)),
+                recall: MetricSummary::from_values(&values(|m| m.recall)),
+                f1: MetricSummary::from_values(&values(|m| m.f1)),
+                label
+            });
+        }
+
+        CrossValidationReport {
+            accuracy: summarize(&folds, |r| r.accuracy),
+            macro_precision: summarize(&folds, |r| r.macro_avg.precision),
+            macro_recall: summarize(&folds, |r| r.macro_avg.recall),
+            macro_f1: summarize(&folds, |r| r.macro_avg.f1),
+            micro_f1: summarize(&folds, |r| r.micro_avg.f1),
+            inconclusive_rate: summarize(&folds, |r| if r.total == 0 { 0.0 } else { r.inconclusive as f64 / r.total as f64 }),
+            per_class,
+            folds
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let per_class = self.per_class
+            .iter()
+            .map(|m| object!{
+                label: m.label.clone(),
+                precision: m.precision.to_json(),
+                recall: m.recall.to_json(),
+                f1: m.f1.to_json()
+            })
+            .collect_vec();
+        object!{
+            folds: self.folds.iter().map(|r| r.to_json()).collect_vec(),
+            accuracy: self.accuracy.to_json(),
+            macro_precision: self.macro_precision.to_json(),
+            macro_recall: self.macro_recall.to_json(),
+            macro_f1: self.macro_f1.to_json(),
+            micro_f1: self.micro_f1.to_json(),
+            inconclusive_rate: self.inconclusive_rate.to_json(),
+            per_class: per_class
+        }
+    }
+}
+
+impl fmt::Display for CrossValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}-fold cross validation", self.folds.len())?;
+        writeln!(f, "Accuracy:          {}", self.accuracy)?;
+        writeln!(f, "Macro precision:   {}", self.macro_precision)?;
+        writeln!(f, "Macro recall:      {}", self.macro_recall)?;
+        writeln!(f, "Macro f1:          {}", self.macro_f1)?;
+        writeln!(f, "Micro f1:          {}", self.micro_f1)?;
+        write!(f, "Inconclusive rate: {}", self.inconclusive_rate)?;
+        for m in &self.per_class {
+            write!(f, "\n{}: precision {}, recall {}, f1 {}", m.label, m.precision, m.recall, m.f1)?;
+        }
+        Ok(())
+    }
+}
+
+impl NGram {
+    // Shuffle the inputs of each type and deal them round robin into k folds
+    // so every fold keeps roughly the same type distribution as the whole set
+    pub fn stratified_folds(input: &[InputTup], k: usize, seed: u64) -> Result<Vec<Vec<InputTup>>, ConfigError> {
+        if k < 2 {
+            return out_of_range("cross_validation", "folds", "at least 2");
+        }
+        let mut rng = seeded_rng(seed);
+        let type_groups = input
+            .iter()
+            .sorted_by(|tup1, tup2| tup1.0.cmp(&tup2.0))
+            .group_by(|tup| tup.0.to_owned());
+
+        let mut folds: Vec<Vec<InputTup>> = vec![Vec::new(); k];
+        let mut next_fold = 0;
+        for (_, group) in type_groups.into_iter() {
+            let mut type_inputs = group.cloned().collect_vec();
+            type_inputs.shuffle(&mut rng);
+            for tup in type_inputs {
+                folds[next_fold].push(tup);
+                next_fold = (next_fold + 1) % k;
+            }
+        }
+        Ok(folds)
+    }
+
+    // Train on k - 1 folds, evaluate on the held out fold, for every fold
+    pub fn cross_validate(input: &[InputTup], config: &CrossValidationConfig) -> Result<CrossValidationReport, ConfigError> {
+        NGram::cross_validate_with(input, config.folds, config.seed, &|training_data| {
+            let mut ngram = NGram::new(training_data, config.max_grams);
+            ngram.scoring = config.scoring;
+            ngram
+        })
+    }
+
+    // Same as cross_validate but f_train builds the model for each fold,
+    // use this to run learn() or other settings inside the folds
+    pub fn cross_validate_with(input: &[InputTup], k: usize, seed: u64, f_train: &dyn Fn(&Vec<InputTup>) -> NGram) -> Result<CrossValidationReport, ConfigError> {
+        let folds = NGram::stratified_folds(input, k, seed)?;
+        let mut reports = Vec::new();
+        for i in 0..folds.len() {
+            let training_data = folds
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .flat_map(|(_, fold)| fold.iter().cloned())
+                .collect_vec();
+            let ngram = f_train(&training_data);
+            reports.push(NGram::evaluate(&ngram.ngram_maps, &folds[i], &ngram.scoring));
+        }
+        Ok(CrossValidationReport::from_folds(reports))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> Vec<InputTup> {
+        let mut input = Vec::new();
+        for i in 0..12 {
+            input.push((String::from("a"), format!("a{}", i)));
+        }
+        for i in 0..6 {
+            input.push((String::from("b"), format!("b{}", i)));
+        }
+        for i in 0..2 {
+            input.push((String::from("c"), format!("c{}", i)));
+        }
+        input
+    }
+
+    fn count(fold: &[InputTup], label: &str) -> usize {
+        fold.iter().filter(|(l, _)| l == label).count()
+    }
+
+    #[test]
+    fn folds_are_stratified_disjoint_and_cover_the_input() {
+        let input = input();
+        let folds = NGram::stratified_folds(&input, 3, 5).unwrap();
+        assert_eq!(folds.len(), 3);
+        for fold in &folds {
+            assert_eq!(count(fold, "a"), 4);
+            assert_eq!(count(fold, "b"), 2);
+            assert!(count(fold, "c") <= 1);
+        }
+        // every input in exactly one fold
+        let all = folds.iter().flatten().cloned().sorted().collect_vec();
+        assert_eq!(all, input.iter().cloned().sorted().collect_vec());
+    }
+
+    #[test]
+    fn same_seed_same_folds() {
+        let input = input();
+        let folds = NGram::stratified_folds(&input, 4, 1).unwrap();
+        assert_eq!(NGram::stratified_folds(&input, 4, 1).unwrap(), folds);
+        assert_ne!(NGram::stratified_folds(&input, 4, 2).unwrap(), folds);
+    }
+
+    #[test]
+    fn needs_two_folds() {
+        for k in [0, 1] {
+            assert!(matches!(NGram::stratified_folds(&input(), k, 0), Err(ConfigError::OutOfRange(_, key, _)) if key == "folds"));
+            assert!(NGram::cross_validate(&input(), &CrossValidationConfig { folds: k, ..CrossValidationConfig::default() }).is_err());
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use itertools::Itertools;
+use json::{JsonValue, object};
+use std::collections::{HashMap, HashSet};
+
+use crate::n_gram::{NGram, NgramMap};
+use crate::n_gram::config::{ConfigError, check_keys, get_f64, get_i32, get_str, out_of_range};
+use crate::util::InputTup;
+
+/*
+Keeps the grams that best separate the types instead of pruning by raw counts.
+Each gram is treated as a binary feature (sentence contains it or not) and
+scored against the type label with a 2x2 contingency table per type:
+    a = inputs of the type with the gram
+    b = inputs of other types with the gram
+    c = inputs of the type without the gram
+    d = inputs of other types without the gram
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeatureScore {
+    ChiSquared,
+    MutualInformation,
+    // not type specific, every type ranks grams by the same score
+    InformationGain
+}
+
+impl FeatureScore {
+    pub fn parse(s: &str) -> Option<FeatureScore> {
+        match s {
+            "chi_squared" => Some(FeatureScore::ChiSquared),
+            "mutual_information" => Some(FeatureScore::MutualInformation),
+            "information_gain" => Some(FeatureScore::InformationGain),
+            _ => None
+        }
+    }
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            FeatureScore::ChiSquared => "chi_squared",
+            FeatureScore::MutualInformation => "mutual_information",
+            FeatureScore::InformationGain => "information_gain"
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureSelectionConfig {
+    pub method: FeatureScore,
+    // keep the best top_k grams of each type in every gram order
+    pub top_k: Option<usize>,
+    // keep every gram scoring at least this much for some type
+    pub threshold: Option<f64>
+}
+
+impl Default for FeatureSelectionConfig {
+    fn default() -> FeatureSelectionConfig {
+        FeatureSelectionConfig {
+            method: FeatureScore::ChiSquared,
+            top_k: Some(1000),
+            threshold: None
+        }
+    }
+}
+
+impl FeatureSelectionConfig {
+    pub fn from_json(obj: &JsonValue) -> Result<FeatureSelectionConfig, ConfigError> {
+        let mut config = FeatureSelectionConfig::default();
+        let features_s = "features";
+        check_keys(obj, features_s, &["method", "top_k", "threshold"])?;
+        if let Some(method_s) = get_str(obj, features_s, "method")? {
+            config.method = match FeatureScore::parse(method_s) {
+                Some(method) => method,
+                None => return out_of_range(features_s, "method", "chi_squared, mutual_information or information_gain")
+            };
+        }
+        // null turns the limit off
+        if obj.has_key("top_k") {
+            config.top_k = if obj["top_k"].is_null() { None } else {
+                let top_k = get_i32(obj, features_s, "top_k", 0)?;
+                if top_k < 1 {
+                    return out_of_range(features_s, "top_k", "at least 1");
+                }
+                Some(top_k as usize)
+            };
+        }
+        if obj.has_key("threshold") {
+            config.threshold = if obj["threshold"].is_null() { None } else {
+                Some(get_f64(obj, features_s, "threshold", 0.0)?)
+            };
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let features_s = "features";
+        if self.top_k == Some(0) {
+            return out_of_range(features_s, "top_k", "at least 1");
+        }
+        if let Some(threshold) = self.threshold {
+            if !threshold.is_finite() {
+                return out_of_range(features_s, "threshold", "a finite number");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object!{
+            method: self.method.to_str(),
+            top_k: self.top_k,
+            threshold: self.threshold
+        }
+    }
+}
+
+struct Contingency {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64
+}
+
+impl Contingency {
+    fn n(&self) -> f64 {
+        self.a + self.b + self.c + self.d
+    }
+
+    fn chi_squared(&self) -> f64 {
+        let den = (self.a + self.c) * (self.b + self.d) * (self.a + self.b) * (self.c + self.d);
+        if den == 0.0 { return 0.0; }
+        self.n() * (self.a * self.d - self.c * self.b).powi(2) / den
+    }
+
+    fn mutual_information(&self) -> f64 {
+        let n = self.n();
+        // (joint count, gram marginal, type marginal)
+        let cells = [
+            (self.a, self.a + self.b, self.a + self.c),
+            (self.b, self.a + self.b, self.b + self.d),
+            (self.c, self.c + self.d, self.a + self.c),
+            (self.d, self.c + self.d, self.b + self.d)
+        ];
+        cells
+            .iter()
+            .filter(|(joint, gram, type_total)| *joint > 0.0 && *gram > 0.0 && *type_total > 0.0)
+            .map(|(joint, gram, type_total)| joint / n * f64::log2(n * joint / (gram * type_total)))
+            .sum()
+    }
+}
+
+fn entropy(counts: &[f64]) -> f64 {
+    let total: f64 = counts.iter().sum();
+    if total == 0.0 { return 0.0; }
+    counts
+        .iter()
+        .filter(|c| **c > 0.0)
+        .map(|c| -(c / total) * f64::log2(c / total))
+        .sum()
+}
+
+// Inputs of each type containing each gram of order n: type -> gram -> count,
+// and the number of inputs of each type.
+// Counted from the input because the bags only hold occurrences, a gram
+// repeated in one sentence would count as several inputs
+fn document_counts(input: &[InputTup], n: usize) -> (HashMap<String, HashMap<String, f64>>, HashMap<String, f64>) {
+    let mut counts: HashMap<String, HashMap<String, f64>> = HashMap::new();
+    let mut type_totals: HashMap<String, f64> = HashMap::new();
+    for (type_name, sentence) in input {
+        *type_totals.entry(type_name.clone()).or_insert(0.0) += 1.0;
+        let type_counts = counts.entry(type_name.clone()).or_default();
+        for gram in NGram::create_grams(sentence, n).into_iter().unique() {
+            *type_counts.entry(gram).or_insert(0.0) += 1.0;
+        }
+    }
+    (counts, type_totals)
+}
+
+impl NGram {
+    // Score of every gram for every type in the map of order n: type -> gram -> score,
+    // input is the training input of the map
+    pub fn score_features(g_map: &NgramMap, n: usize, input: &[InputTup], method: FeatureScore) -> HashMap<String, HashMap<String, f64>> {
+        let grams = g_map
+            .values()
+            .flat_map(|(_, bag)| bag.keys())
+            .collect::<HashSet<&String>>();
+        let type_names = g_map.keys().sorted().collect_vec();
+        let (counts, input_totals) = document_counts(input, n);
+        let gram_count = |type_name: &String, gram: &String| counts.get(type_name).and_then(|c| c.get(gram)).copied().unwrap_or(0.0);
+        let type_totals = type_names.iter().map(|t| *input_totals.get(*t).unwrap_or(&0.0)).collect_vec();
+        let n: f64 = type_totals.iter().sum();
+        let type_entropy = entropy(&type_totals);
+
+        let mut scores: HashMap<String, HashMap<String, f64>> = HashMap::new();
+        for gram in grams {
+            let with_gram = type_names.iter().map(|t| gram_count(t, gram)).collect_vec();
+            let total_with: f64 = with_gram.iter().sum();
+
+            let information_gain = if method == FeatureScore::InformationGain {
+                let without_gram = type_totals.iter().zip(&with_gram).map(|(total, with)| total - with).collect_vec();
+                let p_gram = total_with / n;
+                type_entropy - p_gram * entropy(&with_gram) - (1.0 - p_gram) * entropy(&without_gram)
+            } else { 0.0 };
+
+            for (i, type_name) in type_names.iter().enumerate() {
+                let table = Contingency {
+                    a: with_gram[i],
+                    b: total_with - with_gram[i],
+                    c: type_totals[i] - with_gram[i],
+                    d: (n - type_totals[i]) - (total_with - with_gram[i])
+                };
+                let score = match method {
+                    FeatureScore::ChiSquared => table.chi_squared(),
+                    FeatureScore::MutualInformation => table.mutual_information(),
+                    FeatureScore::InformationGain => information_gain
+                };
+                scores
+                    .entry((*type_name).clone())
+                    .or_default()
+                    .insert(gram.clone(), score);
+            }
+        }
+        scores
+    }
+
+    // Keep the grams selected for at least one type, in every type's bag
+    // a type only selects from grams that appear in its own bag
+    pub fn select_features(maps: &[NgramMap], input: &[InputTup], config: &FeatureSelectionConfig) -> Vec<NgramMap> {
+        let mut ret_vec = Vec::new();
+        for (i, g_map) in maps.iter().enumerate() {
+            let scores = NGram::score_features(g_map, i + 1, input, config.method);
+            let mut keep: HashSet<String> = HashSet::new();
+            for (type_name, (_, bag)) in g_map {
+                // a type with no grams in this order has no scores
+                let type_scores = match scores.get(type_name) {
+                    Some(type_scores) => type_scores,
+                    None => continue
+                };
+                let ranked = bag
+                    .keys()
+                    .map(|gram| (gram, type_scores[gram]))
+                    .filter(|(_, score)| config.threshold.is_none_or(|t| *score >= t))
+                    .sorted_by(|(gram1, score1), (gram2, score2)| score2.total_cmp(score1).then(gram1.cmp(gram2)))
+                    .take(config.top_k.unwrap_or(usize::MAX))
+                    .map(|(gram, _)| gram.clone());
+                keep.extend(ranked);
+            }
+
+            let mut ret_map = NgramMap::new();
+            for (type_name, (total, bag)) in g_map {
+                let selected_bag = bag
+                    .iter()
+                    .filter(|(gram, _)| keep.contains(*gram))
+                    .map(|(gram, prob)| (gram.clone(), *prob))
+                    .collect();
+                ret_map.insert(type_name.clone(), (*total, selected_bag));
+            }
+            ret_vec.push(ret_map);
+        }
+        ret_vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tups(pairs: &[(&str, &str)]) -> Vec<InputTup> {
+        pairs.iter().map(|(label, text)| (String::from(*label), String::from(*text))).collect_vec()
+    }
+
+    #[test]
+    fn scores_count_inputs_not_occurrences() {
+        // "red" is in 1 of the 3 inputs of a and 1 of the 2 inputs of b,
+        // a = 1, b = 1, c = 2, d = 1 for type a
+        let input = tups(&[("a", "red red apple"), ("a", "pear"), ("a", "pear"), ("b", "red sky"), ("b", "blue sky")]);
+        let ngram = NGram::new(&input, 1);
+        let chi_squared = NGram::score_features(&ngram.ngram_maps[0], 1, &input, FeatureScore::ChiSquared);
+        assert!((chi_squared["a"]["red"] - 5.0 / 36.0).abs() < 1e-9, "{}", chi_squared["a"]["red"]);
+        let mutual_information = NGram::score_features(&ngram.ngram_maps[0], 1, &input, FeatureScore::MutualInformation);
+        let expected = 0.2 * f64::log2(5.0 / 6.0) + 0.2 * f64::log2(5.0 / 4.0) + 0.4 * f64::log2(10.0 / 9.0) + 0.2 * f64::log2(5.0 / 6.0);
+        assert!((mutual_information["a"]["red"] - expected).abs() < 1e-9, "{}", mutual_information["a"]["red"]);
+        // only in a, and in 2 of its 3 inputs
+        assert!((chi_squared["a"]["pear"] - 5.0 * 16.0 / 36.0).abs() < 1e-9, "{}", chi_squared["a"]["pear"]);
+    }
+
+    #[test]
+    fn orders_without_grams_are_kept_empty() {
+        let input = vec![
+            (String::from("a"), String::from("red apple")),
+            (String::from("b"), String::from("blue"))
+        ];
+        let ngram = NGram::new(&input, 3);
+        let selected = NGram::select_features(&ngram.ngram_maps, &input, &FeatureSelectionConfig::default());
+        assert_eq!(selected.len(), 3);
+        assert!(selected[0]["a"].1.contains_key("red"));
+        assert!(selected[2].values().all(|(_, bag)| bag.is_empty()));
+    }
+}
@@ -1,21 +1,79 @@
 use itertools::Itertools;
 use std::fs::File;
+use std::str::FromStr;
 
 use crate::n_gram::*;
 
+// Files written before scoring modes existed have no header and load as voting
+const SCORING_HEADER: &str = "<<SCORING>>";
+
+// Positive,123|"foo"0.123"bar"0.234 with probabilities, or counts in count files
+pub(crate) fn bag_line<T: ToString>(type_name: &str, total: usize, g_map: HashMap<String, T>) -> String {
+    let ret_vec = g_map.into_iter().collect_vec();
+    let map_string = reduce::<(String, T), String>(&ret_vec, &String::from(""), |(word, value), acc| {
+        acc + "\"" + word + "\"" + &value.to_string()
+    });
+    format!("{},{}|{}", type_name, total, map_string)
+}
+
+// reads the lines of bag_line
+pub(crate) fn parse_bag_line<T: FromStr>(g_map: &str) -> (String, usize, HashMap<String, T>) where T::Err: fmt::Debug {
+    let mut type_name = String::new();
+    let mut map_total_s = String::new();
+
+    let mut words: HashMap<String, T> = HashMap::new();
+    let mut current_word = String::new();
+    let mut current_prob = String::new();
+
+    let mut found_type_name = false;
+    let mut found_type_total = false;
+    let mut finding_word = false;
+    for c in g_map.chars() {
+        if !found_type_name {
+            if c == ',' {
+                found_type_name = true;
+                continue;
+            }
+            type_name = type_name + &c.to_string();
+            continue;
+        }
+        if !found_type_total {
+            if c == '|' {
+                found_type_total = true;
+                continue;
+            }
+            map_total_s = map_total_s + &c.to_string();
+            continue;
+        }
+        if c == '\"' {
+            if !finding_word && !current_prob.is_empty() {
+                words.insert(current_word.clone(), current_prob.clone().parse::<T>().unwrap());
+                current_word = String::new();
+                current_prob = String::new();
+            }
+            finding_word = !finding_word;
+            continue;
+        }
+        if finding_word {
+            current_word = current_word + &c.to_string();
+        } else {
+            current_prob = current_prob + &c.to_string();
+        }
+    }
+    // the last gram on the line is not followed by a quote
+    if !current_prob.is_empty() {
+        words.insert(current_word.clone(), current_prob.parse::<T>().unwrap());
+    }
+    (type_name, map_total_s.parse::<usize>().unwrap(), words)
+}
+
 impl NGram {
     pub fn save(&self, file_name: &str) {
-        let mut file_data = String::from("");
+        // <<SCORING>>naive_bayes(1)
+        let mut file_data = String::from(SCORING_HEADER) + &self.scoring.to_string() + "\n";
         for i in 0..self.max_grams {
             for (type_name, (total, g_map)) in self.ngram_maps.index(i as usize).clone() {
-                // Positive,123|
-                file_data = file_data + &type_name + "," + &total.to_string() + "|";
-                let ret_vec = g_map.into_iter().collect_vec();
-                let map_string = reduce::<(String, f32), String>(&ret_vec, &String::from(""), |(word, prob), acc| {
-                    // "foo"0.123"bar"0.234
-                    acc + "\"" + word + "\"" + &prob.to_string()
-                });
-                file_data = file_data + &map_string + "\n"
+                file_data = file_data + &bag_line(&type_name, total, g_map) + "\n"
             }
             file_data = file_data + "<<GRAM>>\n"; // at the end of each gram map
         }
@@ -31,62 +89,25 @@ impl NGram {
         let lines = file_contents.split("\n");
         let mut gram_maps: Vec<NgramMap> = Vec::new();
         let mut max_grams = 0;
+        let mut scoring = ScoringMode::default();
 
         let mut bm: NgramMap = NgramMap::new();
         for g_map in lines {
             if g_map.eq("") { continue; }
+            if let Some(scoring_s) = g_map.strip_prefix(SCORING_HEADER) {
+                scoring = ScoringMode::parse(scoring_s).expect("Loading n-gram model: Unknown scoring mode");
+                continue;
+            }
             if g_map.eq("<<GRAM>>") {
                 gram_maps.push(bm.clone());
                 bm.clear();
                 max_grams = max_grams + 1;
                 continue;
             }
-            let mut type_name = String::new();
-            let mut map_total_s = String::new();
-            
-            let mut words: HashMap<String, f32> = HashMap::new();
-            let mut current_word = String::new();
-            let mut current_prob = String::new();
-
-            let mut found_type_name = false;
-            let mut found_type_total = false;
-            let mut finding_word = false;
-            for c in g_map.chars() {
-                if !found_type_name {
-                    if c == ',' {
-                        found_type_name = true;
-                        continue;
-                    }
-                    type_name = type_name + &c.to_string();
-                    continue;
-                }
-                if !found_type_total {
-                    if c == '|' {
-                        found_type_total = true;
-                        continue;
-                    }
-                    map_total_s = map_total_s + &c.to_string();
-                    continue;
-                }
-                if c == '\"' {
-                    if !finding_word && !current_prob.eq("") {
-                        words.insert(current_word.clone(), current_prob.clone().parse::<f32>().unwrap());
-                        current_word = String::new();
-                        current_prob = String::new();
-                    }
-                    finding_word = !finding_word;
-                    continue;
-                }
-                if finding_word {
-                    current_word = current_word + &c.to_string();
-                } else {
-                    current_prob = current_prob + &c.to_string();
-                }
-            }
-            let type_total = map_total_s.parse::<usize>().unwrap();
+            let (type_name, type_total, words) = parse_bag_line::<f32>(g_map);
             bm.insert(type_name, (type_total, words));
         }
-        NGram { ngram_maps: gram_maps, max_grams }
+        NGram { ngram_maps: gram_maps, max_grams, scoring }
     }
 
     pub fn parse(ngram_file_path: &str, input: Vec<String>, output_file_path: &str) {
@@ -0,0 +1,529 @@
+use itertools::Itertools;
+use json::{JsonValue, object};
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Index;
+use std::path::Path;
+
+use crate::n_gram::{NGram, NgramMap, ScoringMode};
+use crate::n_gram::config::{ConfigError, check_keys, get_f32, get_i32, get_str, get_u64, out_of_range};
+use crate::random::{derive_seed, seeded_rng};
+use crate::events;
+use crate::util::InputTup;
+
+// Gram key that holds the per type bias in the 1 gram map of a linear model,
+// a reserved token (text::is_reserved) so it can never be a real gram
+pub const BIAS_GRAM: &str = "<<BIAS>>";
+
+/*
+Multinomial logistic regression over the grams already in the n-gram maps.
+Each gram of every order is a feature counted once per occurrence in the sentence.
+Trained with stochastic gradient descent and L2 regularization, the weights
+with the best held out accuracy are written back into the n-gram maps in place of
+the probabilities and the model switches to ScoringMode::Linear.
+*/
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradientConfig {
+    // maximum passes over the training data
+    pub epochs: i32,
+    pub learning_rate: f32,
+    // L2 regularization strength
+    pub l2: f32,
+    // stop after this many epochs without a better held out accuracy
+    pub patience: i32,
+    // fraction of the learn input held out for early stopping
+    pub validation_fraction: f32,
+    // picks the held out inputs and the shuffle order of every epoch
+    pub seed: u64,
+    // training state is saved to this file and a run resumes from it when it exists
+    pub checkpoint: Option<String>,
+    // epochs between checkpoints
+    pub checkpoint_every: i32
+}
+
+impl Default for GradientConfig {
+    fn default() -> GradientConfig {
+        GradientConfig {
+            epochs: 20,
+            learning_rate: 0.1,
+            l2: 0.0001,
+            patience: 3,
+            validation_fraction: 0.2,
+            seed: 0,
+            checkpoint: None,
+            checkpoint_every: 1
+        }
+    }
+}
+
+impl GradientConfig {
+    pub fn from_json(obj: &JsonValue) -> Result<GradientConfig, ConfigError> {
+        let def = GradientConfig::default();
+        let gradient_s = "gradient";
+        check_keys(obj, gradient_s, &["epochs", "learning_rate", "l2", "patience", "validation_fraction", "seed", "checkpoint", "checkpoint_every"])?;
+        let config = GradientConfig {
+            epochs: get_i32(obj, gradient_s, "epochs", def.epochs)?,
+            learning_rate: get_f32(obj, gradient_s, "learning_rate", def.learning_rate)?,
+            l2: get_f32(obj, gradient_s, "l2", def.l2)?,
+            patience: get_i32(obj, gradient_s, "patience", def.patience)?,
+            validation_fraction: get_f32(obj, gradient_s, "validation_fraction", def.validation_fraction)?,
+            seed: get_u64(obj, gradient_s, "seed", def.seed)?,
+            checkpoint: if obj["checkpoint"].is_null() { None } else { get_str(obj, gradient_s, "checkpoint")?.map(String::from) },
+            checkpoint_every: get_i32(obj, gradient_s, "checkpoint_every", def.checkpoint_every)?
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let gradient_s = "gradient";
+        if self.epochs < 1 {
+            return out_of_range(gradient_s, "epochs", "at least 1");
+        }
+        if self.learning_rate <= 0.0 {
+            return out_of_range(gradient_s, "learning_rate", "greater than 0");
+        }
+        if self.l2 < 0.0 {
+            return out_of_range(gradient_s, "l2", "at least 0");
+        }
+        if self.patience < 1 {
+            return out_of_range(gradient_s, "patience", "at least 1");
+        }
+        if self.validation_fraction <= 0.0 || self.validation_fraction >= 1.0 {
+            return out_of_range(gradient_s, "validation_fraction", "between 0 and 1 (exclusive)");
+        }
+        if self.checkpoint_every < 1 {
+            return out_of_range(gradient_s, "checkpoint_every", "at least 1");
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        object!{
+            epochs: self.epochs,
+            learning_rate: self.learning_rate,
+            l2: self.l2,
+            patience: self.patience,
+            validation_fraction: self.validation_fraction,
+            seed: self.seed,
+            checkpoint: self.checkpoint.clone(),
+            checkpoint_every: self.checkpoint_every
+        }
+    }
+}
+
+// (type index, feature indexes of every gram in the sentence)
+type Example = (usize, Vec<usize>);
+
+struct FeatureIndex {
+    type_names: Vec<String>,
+    // (gram order - 1, gram) for every feature index
+    features: Vec<(usize, String)>,
+    lookup: HashMap<(usize, String), usize>
+}
+
+impl FeatureIndex {
+    fn new(bow: &[NgramMap]) -> FeatureIndex {
+        let type_names = bow.first().map(|g_map| g_map.keys().cloned().sorted().collect_vec()).unwrap_or_default();
+        let mut features = Vec::new();
+        let mut lookup = HashMap::new();
+        for (i, g_map) in bow.iter().enumerate() {
+            let grams = g_map
+                .values()
+                .flat_map(|(_, bag)| bag.keys())
+                .filter(|gram| gram.as_str() != BIAS_GRAM)
+                .sorted()
+                .dedup();
+            for gram in grams {
+                lookup.insert((i, gram.clone()), features.len());
+                features.push((i, gram.clone()));
+            }
+        }
+        FeatureIndex { type_names, features, lookup }
+    }
+
+    fn example(&self, input: &InputTup, max_grams: usize) -> Option<Example> {
+        let type_idx = self.type_names.binary_search(&input.0).ok()?;
+        Some((type_idx, self.sentence_features(&input.1, max_grams)))
+    }
+
+    fn sentence_features(&self, sentence: &str, max_grams: usize) -> Vec<usize> {
+        let mut ret_val = Vec::new();
+        for i in 1..(max_grams+1) {
+            for gram in NGram::create_grams(sentence, i) {
+                if let Some(idx) = self.lookup.get(&(i-1, gram)) {
+                    ret_val.push(*idx);
+                }
+            }
+        }
+        ret_val
+    }
+}
+
+#[derive(Clone)]
+struct LinearWeights {
+    // weights[type][feature]
+    weights: Vec<Vec<f32>>,
+    bias: Vec<f32>
+}
+
+impl LinearWeights {
+    fn probabilities(&self, features: &[usize]) -> Vec<f32> {
+        let scores = self.weights
+            .iter()
+            .zip(&self.bias)
+            .map(|(w, b)| b + features.iter().map(|f| w[*f]).sum::<f32>())
+            .collect_vec();
+        let max_score = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let exp_scores = scores.iter().map(|s| f32::exp(s - max_score)).collect_vec();
+        let normalizer: f32 = exp_scores.iter().sum();
+        exp_scores.into_iter().map(|s| s / normalizer).collect_vec()
+    }
+
+    fn predict(&self, features: &[usize]) -> usize {
+        self.probabilities(features)
+            .into_iter()
+            .enumerate()
+            .max_by(|(_, p1), (_, p2)| p1.total_cmp(p2))
+            .expect("no types to predict").0
+    }
+
+    fn accuracy(&self, examples: &[Example]) -> f32 {
+        if examples.is_empty() { return 0.0; }
+        let correct = examples
+            .iter()
+            .filter(|(type_idx, features)| self.predict(features) == *type_idx)
+            .count();
+        correct as f32 / examples.len() as f32
+    }
+
+    fn step(&mut self, example: &Example, config: &GradientConfig) {
+        let (type_idx, features) = example;
+        let probabilities = self.probabilities(features);
+        for (k, p) in probabilities.into_iter().enumerate() {
+            let target = if k == *type_idx { 1.0 } else { 0.0 };
+            let gradient = p - target;
+            let w = &mut self.weights[k];
+            for f in features {
+                w[*f] -= config.learning_rate * (gradient + config.l2 * w[*f]);
+            }
+            self.bias[k] -= config.learning_rate * gradient;
+        }
+    }
+}
+
+// Training state after a finished epoch, enough to continue with the next one
+struct GradientCheckpoint {
+    // epochs finished
+    epoch: i32,
+    // early stopping ended the run
+    stopped: bool,
+    best_accuracy: f32,
+    epochs_without_improvement: i32,
+    current: LinearWeights,
+    best: LinearWeights
+}
+
+impl GradientCheckpoint {
+    // What a checkpoint must have been trained with to be resumed, values are stored as
+    // strings so floats compare exactly. epochs and patience may change between runs
+    fn fingerprint(config: &GradientConfig, index: &FeatureIndex, training_examples: usize, validation_examples: usize) -> JsonValue {
+        object!{
+            seed: config.seed.to_string(),
+            learning_rate: config.learning_rate.to_string(),
+            l2: config.l2.to_string(),
+            validation_fraction: config.validation_fraction.to_string(),
+            types: index.type_names.clone(),
+            features: index.features.len(),
+            training_examples: training_examples,
+            validation_examples: validation_examples
+        }
+    }
+
+    // First line: the fingerprint and the counters as json
+    // then current<TAB>type<TAB>bias<TAB>weights separated by spaces for every type, the same for best
+    // floats are written in their shortest exact form so a resumed run continues bit for bit
+    fn save(&self, file_name: &str, fingerprint: &JsonValue) {
+        let header = object!{
+            fingerprint: fingerprint.clone(),
+            epoch: self.epoch,
+            stopped: self.stopped,
+            best_accuracy: self.best_accuracy.to_string(),
+            epochs_without_improvement: self.epochs_without_improvement
+        };
+        let mut file_data = header.dump() + "\n";
+        for (name, weights) in [("current", &self.current), ("best", &self.best)] {
+            for (k, w) in weights.weights.iter().enumerate() {
+                file_data = file_data + &format!("{}\t{}\t{}\t{}\n", name, k, weights.bias[k], w.iter().map(|v| v.to_string()).join(" "));
+            }
+        }
+        // written next to the checkpoint and renamed so a crash never leaves half a checkpoint
+        let partial_file = format!("{}.partial", file_name);
+        fs::write(&partial_file, file_data).expect("Error writing checkpoint");
+        fs::rename(&partial_file, file_name).expect("Error renaming checkpoint");
+    }
+
+    fn load(file_name: &str, fingerprint: &JsonValue) -> GradientCheckpoint {
+        let err = format!("Error reading checkpoint: {}", file_name);
+        let contents = fs::read_to_string(file_name).expect(&err);
+        let mut lines = contents.lines();
+        let header = json::parse(lines.next().expect(&err)).expect(&err);
+        if header["fingerprint"] != *fingerprint {
+            panic!("Checkpoint {} was made with other data or gradient settings, delete it to start over", file_name);
+        }
+        let parse_f32 = |s: &str| s.parse::<f32>().expect(&err);
+
+        let num_types = fingerprint["types"].len();
+        let empty = LinearWeights { weights: vec![Vec::new(); num_types], bias: vec![0.0; num_types] };
+        let (mut current, mut best) = (empty.clone(), empty);
+        for line in lines.filter(|line| !line.is_empty()) {
+            let fields = line.split('\t').collect_vec();
+            if fields.len() != 4 { panic!("{}: bad weights line", err) }
+            let weights = match fields[0] {
+                "current" => &mut current,
+                "best" => &mut best,
+                _ => panic!("{}: bad weights line", err)
+            };
+            let k = fields[1].parse::<usize>().expect(&err);
+            if k >= num_types { panic!("{}: bad type index {}", err, k) }
+            weights.bias[k] = parse_f32(fields[2]);
+            weights.weights[k] = fields[3].split(' ').filter(|v| !v.is_empty()).map(&parse_f32).collect_vec();
+        }
+        let num_features = fingerprint["features"].as_usize().unwrap_or(0);
+        if current.weights.iter().chain(best.weights.iter()).any(|w| w.len() != num_features) {
+            panic!("{}: expected {} weights for every type", err, num_features);
+        }
+
+        GradientCheckpoint {
+            epoch: header["epoch"].as_i32().expect(&err),
+            stopped: header["stopped"].as_bool().expect(&err),
+            best_accuracy: parse_f32(header["best_accuracy"].as_str().expect(&err)),
+            epochs_without_improvement: header["epochs_without_improvement"].as_i32().expect(&err),
+            current,
+            best
+        }
+    }
+}
+
+impl NGram {
+    // Train linear weights over the grams in the n-gram maps with early stopping on validation_data
+    // with config.checkpoint set the state is saved every checkpoint_every epochs and a run
+    // with an existing checkpoint continues from it, giving the same weights as an uninterrupted run
+    // the best weights replace the probabilities and scoring switches to Linear
+    // returns the held out accuracy of the best weights
+    pub fn train_gradient(&mut self, training_data: &[InputTup], validation_data: &[InputTup], config: &GradientConfig) -> f32 {
+        // a model without grams has nothing to weigh and keeps its scoring
+        if self.ngram_maps.is_empty() {
+            return 0.0;
+        }
+        let index = FeatureIndex::new(&self.ngram_maps);
+        let max_grams = self.ngram_maps.len();
+        let mut training_examples = training_data.iter().filter_map(|tup| index.example(tup, max_grams)).collect_vec();
+        let validation_examples = validation_data.iter().filter_map(|tup| index.example(tup, max_grams)).collect_vec();
+        events::metric("features", index.features.len() as f64);
+
+        let num_types = index.type_names.len();
+        let mut state = GradientCheckpoint {
+            epoch: 0,
+            stopped: false,
+            best_accuracy: 0.0,
+            epochs_without_improvement: 0,
+            current: LinearWeights {
+                weights: vec![vec![0.0; index.features.len()]; num_types],
+                bias: vec![0.0; num_types]
+            },
+            best: LinearWeights { weights: Vec::new(), bias: Vec::new() }
+        };
+        state.best = state.current.clone();
+        state.best_accuracy = state.current.accuracy(&validation_examples);
+
+        let fingerprint = GradientCheckpoint::fingerprint(config, &index, training_examples.len(), validation_examples.len());
+        if let Some(file) = config.checkpoint.as_ref().filter(|file| Path::new(file).exists()) {
+            state = GradientCheckpoint::load(file, &fingerprint);
+            // every epoch shuffles the order left by the one before it
+            for epoch in 0..state.epoch {
+                training_examples.shuffle(&mut seeded_rng(derive_seed(config.seed, epoch as u64)));
+            }
+            events::message(&format!("Resuming gradient training from {} at epoch {}", file, state.epoch + 1));
+        }
+
+        while state.epoch < config.epochs && !state.stopped {
+            let epoch = state.epoch;
+            training_examples.shuffle(&mut seeded_rng(derive_seed(config.seed, epoch as u64)));
+            for example in &training_examples {
+                state.current.step(example, config);
+            }
+
+            let accuracy = state.current.accuracy(&validation_examples);
+            events::accuracy(&format!("gradient epoch {}", epoch + 1), accuracy);
+            if accuracy > state.best_accuracy {
+                state.best_accuracy = accuracy;
+                state.best = state.current.clone();
+                state.epochs_without_improvement = 0;
+            } else {
+                state.epochs_without_improvement += 1;
+                if state.epochs_without_improvement >= config.patience {
+                    events::message(&format!("No improvement for {} epochs, stopping", state.epochs_without_improvement));
+                    state.stopped = true;
+                }
+            }
+            state.epoch = epoch + 1;
+
+            if let Some(file) = &config.checkpoint {
+                if state.stopped || state.epoch % config.checkpoint_every == 0 || state.epoch == config.epochs {
+                    state.save(file, &fingerprint);
+                }
+            }
+        }
+        let best_accuracy = state.best_accuracy;
+        let best_weights = state.best;
+
+        self.ngram_maps = NGram::weights_to_maps(&self.ngram_maps, &index, &best_weights);
+        self.scoring = ScoringMode::Linear;
+        events::accuracy("gradient", best_accuracy);
+        best_accuracy
+    }
+
+    fn weights_to_maps(bow: &Vec<NgramMap>, index: &FeatureIndex, weights: &LinearWeights) -> Vec<NgramMap> {
+        let mut ret_vec: Vec<NgramMap> = Vec::new();
+        for g_map in bow {
+            let mut ret_map = NgramMap::new();
+            for (type_name, (total, _)) in g_map {
+                ret_map.insert(type_name.clone(), (*total, HashMap::new()));
+            }
+            ret_vec.push(ret_map);
+        }
+
+        for (k, type_name) in index.type_names.iter().enumerate() {
+            for (f, (order_idx, gram)) in index.features.iter().enumerate() {
+                let weight = weights.weights[k][f];
+                if weight == 0.0 { continue; }
+                if let Some((_, bag)) = ret_vec[*order_idx].get_mut(type_name) {
+                    bag.insert(gram.clone(), weight);
+                }
+            }
+            if let Some((_, bag)) = ret_vec[0].get_mut(type_name) {
+                bag.insert(String::from(BIAS_GRAM), weights.bias[k]);
+            }
+        }
+        ret_vec
+    }
+
+    // Softmax over bias + the summed weights of every gram in the sentence
+    // None when no gram in the sentence has a weight
+    pub fn score_linear(bow: &Vec<NgramMap>, sentence: &str) -> Option<Vec<(String, f64)>> {
+        if bow.is_empty() { return None; }
+        let mut log_scores: HashMap<String, f64> = HashMap::new();
+        for (type_name, (_, bag)) in bow.index(0) {
+            log_scores.insert(type_name.clone(), *bag.get(BIAS_GRAM).unwrap_or(&0.0) as f64);
+        }
+
+        let mut found_gram = false;
+        for i in 1..(bow.len()+1) {
+            let g_map = bow.index(i-1);
+            for gram in NGram::create_grams(sentence, i) {
+                for (type_name, (_, bag)) in g_map {
+                    if let Some(weight) = bag.get(&gram) {
+                        found_gram = true;
+                        if let Some(log_score) = log_scores.get_mut(type_name) {
+                            *log_score += *weight as f64;
+                        }
+                    }
+                }
+            }
+        }
+        if !found_gram {
+            return None;
+        }
+
+        let max_score = log_scores.values().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let normalizer: f64 = log_scores.values().map(|s| f64::exp(s - max_score)).sum();
+        let scores = log_scores
+            .into_iter()
+            .map(|(type_name, s)| (type_name, f64::exp(s - max_score) / normalizer))
+            .sorted_by(|(type1, score1), (type2, score2)| score2.total_cmp(score1).then(type1.cmp(type2)))
+            .collect_vec();
+        Some(scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn tup(label: &str, text: &str) -> InputTup {
+        (String::from(label), String::from(text))
+    }
+
+    // "good" and "bad" decide the type, the filler words of the a sentences make
+    // "common" and "shared" more probable in b so voting gets short sentences wrong
+    fn separable() -> (Vec<InputTup>, Vec<InputTup>) {
+        let mut training_data = Vec::new();
+        for i in 0..10 {
+            training_data.push(tup("a", &format!("good common shared filler{}x filler{}y filler{}z", i, i, i)));
+            training_data.push(tup("b", "bad common shared"));
+        }
+        let test_data = vec![tup("a", "good common shared"), tup("b", "bad common shared"), tup("a", "shared good")];
+        (training_data, test_data)
+    }
+
+    #[test]
+    fn weights_separate_the_types_and_beat_voting() {
+        let (training_data, test_data) = separable();
+        let mut ngram = NGram::new(&training_data, 1);
+        let voting_accuracy = NGram::validate(&ngram.ngram_maps, &test_data, &ScoringMode::Voting);
+
+        let held_out_accuracy = ngram.train_gradient(&training_data, &training_data, &GradientConfig::default());
+        assert_eq!(ngram.scoring, ScoringMode::Linear);
+        assert_eq!(held_out_accuracy, 1.0);
+        assert_eq!(NGram::validate(&ngram.ngram_maps, &training_data, &ngram.scoring), 1.0);
+        let linear_accuracy = NGram::validate(&ngram.ngram_maps, &test_data, &ngram.scoring);
+        assert_eq!(linear_accuracy, 1.0);
+        assert!(linear_accuracy > voting_accuracy, "voting accuracy {}", voting_accuracy);
+
+        let scores = ngram.classify("good").unwrap();
+        assert_eq!(scores[0].0, "a");
+        assert!(scores[0].1 > 0.5);
+    }
+
+    #[test]
+    fn empty_model_is_left_alone() {
+        let mut ngram = NGram::new(&Vec::new(), 2);
+        ngram.ngram_maps.clear();
+        assert_eq!(ngram.train_gradient(&[tup("a", "good")], &[tup("a", "good")], &GradientConfig::default()), 0.0);
+        assert_eq!(ngram.scoring, ScoringMode::Voting);
+    }
+
+    #[test]
+    fn resumed_training_gives_the_same_weights() {
+        let (mut training_data, _) = separable();
+        // mislabeled inputs keep the weights and the held out accuracy moving between epochs
+        training_data.push(tup("b", "good common"));
+        training_data.push(tup("a", "bad shared filler1x"));
+        let dir = env::temp_dir().join(format!("gradient_checkpoint_test_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let checkpoint = |name: &str| Some(dir.join(name).to_string_lossy().to_string());
+        let config = GradientConfig { epochs: 6, learning_rate: 0.05, patience: 10, seed: 7, ..GradientConfig::default() };
+        let train = |config: &GradientConfig| {
+            let mut ngram = NGram::new(&training_data, 2);
+            let accuracy = ngram.train_gradient(&training_data, &training_data[..12], config);
+            (accuracy, ngram.ngram_maps)
+        };
+
+        let uninterrupted = train(&GradientConfig { checkpoint: checkpoint("full.ckpt"), ..config.clone() });
+        // stopped after 2 epochs, then run again with the same checkpoint
+        let interrupted = GradientConfig { checkpoint: checkpoint("resumed.ckpt"), ..config.clone() };
+        train(&GradientConfig { epochs: 2, ..interrupted.clone() });
+        let resumed = train(&interrupted);
+
+        assert_eq!(resumed, uninterrupted);
+        assert_eq!(resumed, train(&config));
+        // the current weights and counters match too
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("resumed.ckpt"), read("full.ckpt"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
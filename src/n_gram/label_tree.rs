@@ -0,0 +1,452 @@
+use itertools::Itertools;
+use json::{JsonValue, object};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+
+use crate::metrics::{MultiLabelPrediction, MultiLabelReport};
+use crate::n_gram::multi_label::{MultiLabelInput, MultiLabelNGram};
+
+/*
+Label hierarchy learned from which labels appear together.
+LabelCooccurrence counts labels and label pairs over multi label inputs.
+LabelTree is the Chow-Liu tree of the labels: the maximum spanning tree over the
+mutual information of every label pair, rooted at the most frequent label, with
+P(label | parent label) on every edge. Decoding combines the one vs rest scores
+with the tree prior and picks the most likely label set with max-product.
+*/
+
+// Scores are clamped to [SCORE_EPSILON, 1 - SCORE_EPSILON] before taking logs
+const SCORE_EPSILON: f64 = 1e-6;
+
+#[derive(Clone, Debug)]
+pub struct LabelCooccurrence {
+    pub labels: Vec<String>,
+    // number of inputs
+    pub total: usize,
+    // inputs with labels[i]
+    pub counts: Vec<usize>,
+    // inputs with both labels[i] and labels[j], pair_counts[i][i] == counts[i]
+    pub pair_counts: Vec<Vec<usize>>
+}
+
+impl LabelCooccurrence {
+    // labels sets the order of the statistics, labels of the input missing from it are ignored
+    pub fn from_input(input: &Vec<MultiLabelInput>, labels: &[String]) -> LabelCooccurrence {
+        let mut counts = vec![0; labels.len()];
+        let mut pair_counts = vec![vec![0; labels.len()]; labels.len()];
+        for (input_labels, _) in input {
+            let present = labels
+                .iter()
+                .enumerate()
+                .filter(|(_, label)| input_labels.contains(label))
+                .map(|(i, _)| i)
+                .collect_vec();
+            for i in &present {
+                counts[*i] += 1;
+                for j in &present {
+                    pair_counts[*i][*j] += 1;
+                }
+            }
+        }
+        LabelCooccurrence { labels: labels.to_owned(), total: input.len(), counts, pair_counts }
+    }
+
+    // P(labels[i]) with laplace smoothing
+    pub fn probability(&self, i: usize, smoothing: f64) -> f64 {
+        (self.counts[i] as f64 + smoothing) / (self.total as f64 + 2.0 * smoothing)
+    }
+
+    // P(labels[j] = value_j | labels[i] = value_i) with laplace smoothing
+    pub fn conditional(&self, j: usize, value_j: bool, i: usize, value_i: bool, smoothing: f64) -> f64 {
+        let both = self.joint_count(i, value_i, j, value_j) as f64;
+        let given = if value_i { self.counts[i] } else { self.total - self.counts[i] } as f64;
+        (both + smoothing) / (given + 2.0 * smoothing)
+    }
+
+    // inputs where labels[i] is present == value_i and labels[j] is present == value_j
+    fn joint_count(&self, i: usize, value_i: bool, j: usize, value_j: bool) -> usize {
+        let both = self.pair_counts[i][j];
+        match (value_i, value_j) {
+            (true, true) => both,
+            (true, false) => self.counts[i] - both,
+            (false, true) => self.counts[j] - both,
+            (false, false) => self.total + both - self.counts[i] - self.counts[j]
+        }
+    }
+
+    // Mutual information in nats between the presence of labels[i] and labels[j]
+    pub fn mutual_information(&self, i: usize, j: usize) -> f64 {
+        if self.total == 0 { return 0.0 }
+        let total = self.total as f64;
+        let marginal = |k: usize, value: bool| if value { self.counts[k] } else { self.total - self.counts[k] } as f64 / total;
+        let mut mi = 0.0;
+        for value_i in [false, true] {
+            for value_j in [false, true] {
+                let p_joint = self.joint_count(i, value_i, j, value_j) as f64 / total;
+                if p_joint > 0.0 {
+                    mi += p_joint * (p_joint / (marginal(i, value_i) * marginal(j, value_j))).ln();
+                }
+            }
+        }
+        mi
+    }
+
+    // (label, other label, P(other | label)) for every pair seen together, highest first
+    pub fn implications(&self) -> Vec<(String, String, f64)> {
+        let n = self.labels.len();
+        (0..n)
+            .flat_map(|i| (0..n).filter(move |j| *j != i).map(move |j| (i, j)))
+            .filter(|(i, j)| self.pair_counts[*i][*j] > 0)
+            .map(|(i, j)| (self.labels[i].clone(), self.labels[j].clone(), self.pair_counts[i][j] as f64 / self.counts[i] as f64))
+            .sorted_by(|(a1, b1, p1), (a2, b2, p2)| p2.total_cmp(p1).then(a1.cmp(a2)).then(b1.cmp(b2)))
+            .collect_vec()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelTree {
+    // same order as the MultiLabelNGram models
+    pub labels: Vec<String>,
+    // index of the parent label, None for the root
+    pub parents: Vec<Option<usize>>,
+    // probability[i][v] = P(labels[i] | parent present == v),
+    // both values are the prior P(labels[i]) for the root
+    pub probability: Vec<[f64; 2]>,
+    // mutual information with the parent, 0 for the root
+    pub mutual_information: Vec<f64>
+}
+
+impl LabelTree {
+    pub fn train(input: &Vec<MultiLabelInput>, labels: &[String], smoothing: f64) -> LabelTree {
+        LabelTree::chow_liu(&LabelCooccurrence::from_input(input, labels), smoothing)
+    }
+
+    // Prim's algorithm on the mutual information of the label pairs, starting
+    // at the most frequent label (the first one on ties)
+    pub fn chow_liu(stats: &LabelCooccurrence, smoothing: f64) -> LabelTree {
+        let n = stats.labels.len();
+        let mut parents: Vec<Option<usize>> = vec![None; n];
+        let mut mutual_information = vec![0.0; n];
+        if n > 0 {
+            let root = (0..n).max_by(|i, j| stats.counts[*i].cmp(&stats.counts[*j]).then(j.cmp(i))).unwrap();
+            let mut in_tree = vec![false; n];
+            in_tree[root] = true;
+            // best (mutual information, tree label) to attach each label outside the tree
+            let mut best: Vec<(f64, usize)> = (0..n).map(|i| (stats.mutual_information(root, i), root)).collect_vec();
+            for _ in 1..n {
+                let next = (0..n)
+                    .filter(|i| !in_tree[*i])
+                    .max_by(|i, j| best[*i].0.total_cmp(&best[*j].0).then(j.cmp(i)))
+                    .unwrap();
+                in_tree[next] = true;
+                parents[next] = Some(best[next].1);
+                mutual_information[next] = best[next].0;
+                for i in (0..n).filter(|i| !in_tree[*i]) {
+                    let mi = stats.mutual_information(next, i);
+                    if mi > best[i].0 {
+                        best[i] = (mi, next);
+                    }
+                }
+            }
+        }
+
+        let probability = (0..n)
+            .map(|i| match parents[i] {
+                Some(p) => [stats.conditional(i, true, p, false, smoothing), stats.conditional(i, true, p, true, smoothing)],
+                None => {
+                    let prior = stats.probability(i, smoothing);
+                    [prior, prior]
+                }
+            })
+            .collect_vec();
+        LabelTree { labels: stats.labels.clone(), parents, probability, mutual_information }
+    }
+
+    fn children(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.labels.len()];
+        for (i, parent) in self.parents.iter().enumerate() {
+            if let Some(p) = parent {
+                children[*p].push(i);
+            }
+        }
+        children
+    }
+
+    // Labels ordered so every parent comes before its children
+    fn top_down(&self) -> Vec<usize> {
+        let children = self.children();
+        let mut order = (0..self.labels.len()).filter(|i| self.parents[*i].is_none()).collect_vec();
+        let mut next = 0;
+        while next < order.len() {
+            order.extend(children[order[next]].iter().cloned());
+            next += 1;
+        }
+        order
+    }
+
+    // Marginal P(label present) of every label under the tree
+    pub fn marginals(&self) -> Vec<f64> {
+        let mut marginals = vec![0.0; self.labels.len()];
+        for i in self.top_down() {
+            marginals[i] = match self.parents[i] {
+                Some(p) => marginals[p] * self.probability[i][1] + (1.0 - marginals[p]) * self.probability[i][0],
+                None => self.probability[i][1]
+            };
+        }
+        marginals
+    }
+
+    // Most likely label set given the one vs rest score of every label (same order as labels).
+    // Each score is read as P(label | sentence), dividing by the label marginal turns it into
+    // a likelihood ratio so the tree supplies the label prior:
+    //   P(set | sentence) ~ P_tree(set) * prod score_i / marginal_i (over present and absent labels)
+    // prior_weight scales the tree term in log space, 0 keeps only the scores: every label
+    // with a score of at least 0.5 like the default threshold, ties keep the label
+    pub fn decode(&self, scores: &[f64], prior_weight: f64) -> Vec<bool> {
+        let n = self.labels.len();
+        if scores.len() != n { panic!("Expected {} label scores, got {}", n, scores.len()) }
+        let marginals = self.marginals();
+        let unary = (0..n)
+            .map(|i| {
+                let score = scores[i].clamp(SCORE_EPSILON, 1.0 - SCORE_EPSILON);
+                let marginal = marginals[i].clamp(SCORE_EPSILON, 1.0 - SCORE_EPSILON);
+                [
+                    (1.0 - score).ln() - prior_weight * (1.0 - marginal).ln(),
+                    score.ln() - prior_weight * marginal.ln()
+                ]
+            })
+            .collect_vec();
+        let edge = |i: usize, value: usize, parent_value: usize| -> f64 {
+            let p = self.probability[i][parent_value].clamp(SCORE_EPSILON, 1.0 - SCORE_EPSILON);
+            prior_weight * if value == 1 { p.ln() } else { (1.0 - p).ln() }
+        };
+
+        // upward pass: belief[i][v] is the best log score of the subtree of i with i = v,
+        // choice[i][pv] the value of i that achieves the best message to a parent with value pv
+        let order = self.top_down();
+        let children = self.children();
+        let mut belief = vec![[0.0; 2]; n];
+        let mut choice = vec![[0; 2]; n];
+        for i in order.iter().rev() {
+            let i = *i;
+            for value in 0..2 {
+                belief[i][value] = unary[i][value] + children[i]
+                    .iter()
+                    .map(|c| (0..2).map(|cv| belief[*c][cv] + edge(*c, cv, value)).fold(f64::MIN, f64::max))
+                    .sum::<f64>();
+            }
+            if self.parents[i].is_some() {
+                for (parent_value, slot) in choice[i].iter_mut().enumerate() {
+                    *slot = if belief[i][1] + edge(i, 1, parent_value) >= belief[i][0] + edge(i, 0, parent_value) { 1 } else { 0 };
+                }
+            }
+        }
+
+        // downward pass: fix the roots, then every child from its parent
+        let mut values = vec![0; n];
+        for i in order {
+            values[i] = match self.parents[i] {
+                Some(p) => choice[i][values[p]],
+                None => {
+                    let root_score = |v: usize| belief[i][v] + edge(i, v, 0);
+                    if root_score(1) >= root_score(0) { 1 } else { 0 }
+                }
+            };
+        }
+        values.into_iter().map(|v| v == 1).collect_vec()
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let labels = (0..self.labels.len())
+            .map(|i| object!{
+                label: self.labels[i].clone(),
+                parent: self.parents[i].map(|p| self.labels[p].clone()),
+                probability_without_parent: self.probability[i][0],
+                probability_with_parent: self.probability[i][1],
+                mutual_information: self.mutual_information[i]
+            })
+            .collect_vec();
+        object!{ labels: labels }
+    }
+
+    // label<TAB>parent label or -<TAB>P(label | no parent)<TAB>P(label | parent)<TAB>mutual information
+    pub fn save(&self, file_name: &str) {
+        let mut file = File::create(file_name).expect("Error creating file object");
+        for i in 0..self.labels.len() {
+            let parent = self.parents[i].map(|p| self.labels[p].clone()).unwrap_or(String::from("-"));
+            let line = format!("{}\t{}\t{}\t{}\t{}\n", self.labels[i], parent, self.probability[i][0], self.probability[i][1], self.mutual_information[i]);
+            file.write_all(line.as_bytes()).expect("Error writing to file");
+        }
+    }
+
+    pub fn load(file_name: &str) -> LabelTree {
+        let err = format!("Error reading label tree: {}", file_name);
+        let rows = fs::read_to_string(file_name)
+            .expect(&err)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let fields = line.split('\t').map(String::from).collect_vec();
+                if fields.len() != 5 { panic!("Expected label, parent, two probabilities and mutual information, got: {}", line) }
+                fields
+            })
+            .collect_vec();
+        let labels = rows.iter().map(|fields| fields[0].clone()).collect_vec();
+        let number = |s: &String| s.parse::<f64>().unwrap_or_else(|_| panic!("Bad number in label tree: {}", s));
+        LabelTree {
+            parents: rows
+                .iter()
+                .map(|fields| match fields[1].as_str() {
+                    "-" => None,
+                    parent => Some(labels.iter().position(|l| l == parent).unwrap_or_else(|| panic!("Unknown parent label: {}", parent)))
+                })
+                .collect_vec(),
+            probability: rows.iter().map(|fields| [number(&fields[2]), number(&fields[3])]).collect_vec(),
+            mutual_information: rows.iter().map(|fields| number(&fields[4])).collect_vec(),
+            labels
+        }
+    }
+}
+
+impl fmt::Display for LabelTree {
+    // the tree indented by depth, with P(label | parent) and P(label | no parent)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let children = self.children();
+        let mut stack = (0..self.labels.len()).filter(|i| self.parents[*i].is_none()).rev().map(|i| (i, 0)).collect_vec();
+        let mut first = true;
+        while let Some((i, depth)) = stack.pop() {
+            if !first { writeln!(f)?; }
+            first = false;
+            match self.parents[i] {
+                Some(_) => write!(f, "{}{}  p|parent={:.4} p|no parent={:.4} mi={:.4}", "  ".repeat(depth), self.labels[i], self.probability[i][1], self.probability[i][0], self.mutual_information[i])?,
+                None => write!(f, "{}  p={:.4}", self.labels[i], self.probability[i][1])?
+            }
+            stack.extend(children[i].iter().rev().map(|c| (*c, depth + 1)));
+        }
+        Ok(())
+    }
+}
+
+impl MultiLabelNGram {
+    fn check_tree(&self, tree: &LabelTree) {
+        if tree.labels != self.labels() { panic!("The label tree labels do not match the model labels") }
+    }
+
+    fn decode_labels(&self, tree: &LabelTree, scores: &[f64], prior_weight: f64) -> Vec<String> {
+        tree.decode(scores, prior_weight)
+            .into_iter()
+            .zip(&tree.labels)
+            .filter(|(present, _)| *present)
+            .map(|(_, label)| label.clone())
+            .collect_vec()
+    }
+
+    // Labels of the most likely label set under the tree instead of the per label thresholds
+    pub fn predict_with_tree(&self, tree: &LabelTree, sentence: &str, prior_weight: f64) -> Vec<String> {
+        self.check_tree(tree);
+        let scores = self.scores(sentence).into_iter().map(|(_, score)| score).collect_vec();
+        self.decode_labels(tree, &scores, prior_weight)
+    }
+
+    pub fn predict_all_with_tree(&self, tree: &LabelTree, input: &[MultiLabelInput], prior_weight: f64) -> Vec<MultiLabelPrediction> {
+        self.check_tree(tree);
+        self.score_all(input)
+            .into_iter()
+            .map(|(actual, scores)| (actual, self.decode_labels(tree, &scores, prior_weight)))
+            .collect_vec()
+    }
+
+    pub fn evaluate_with_tree(&self, tree: &LabelTree, input: &[MultiLabelInput], prior_weight: f64) -> MultiLabelReport {
+        MultiLabelReport::from_predictions(&self.predict_all_with_tree(tree, input, prior_weight))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::seeded_rng;
+    use rand::Rng;
+    use std::{env, process};
+
+    fn labels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| String::from(*name)).collect_vec()
+    }
+
+    // a implies b and b implies c
+    fn chain_input() -> Vec<MultiLabelInput> {
+        [&["a", "b", "c"][..], &["b", "c"], &["c"], &[]]
+            .iter()
+            .flat_map(|set| vec![(labels(set), String::from("text")); 2])
+            .collect_vec()
+    }
+
+    // The log score decode maximizes, summed for one label set
+    fn log_score(tree: &LabelTree, scores: &[f64], prior_weight: f64, values: &[bool]) -> f64 {
+        let marginals = tree.marginals();
+        let clamp = |p: f64| p.clamp(SCORE_EPSILON, 1.0 - SCORE_EPSILON);
+        let log_p = |p: f64, present: bool| if present { clamp(p).ln() } else { (1.0 - clamp(p)).ln() };
+        (0..tree.labels.len())
+            .map(|i| {
+                let parent_value = tree.parents[i].map(|p| values[p] as usize).unwrap_or(0);
+                log_p(scores[i], values[i])
+                    - prior_weight * log_p(marginals[i], values[i])
+                    + prior_weight * log_p(tree.probability[i][parent_value], values[i])
+            })
+            .sum()
+    }
+
+    #[test]
+    fn chow_liu_recovers_a_chain() {
+        let tree = LabelTree::train(&chain_input(), &labels(&["a", "b", "c"]), 1.0);
+        // c is the most frequent label and the root
+        assert_eq!(tree.parents, vec![Some(1), Some(2), None]);
+        assert!(tree.mutual_information[0] > 0.0 && tree.mutual_information[1] > 0.0);
+        // P(a | b) = (2 + 1) / (4 + 2), P(a | no b) = (0 + 1) / (4 + 2)
+        assert_eq!(tree.probability[0], [1.0 / 6.0, 0.5]);
+        assert_eq!(tree.probability[2], [0.7, 0.7]);
+    }
+
+    #[test]
+    fn decode_finds_the_best_label_set() {
+        let mut rng = seeded_rng(3);
+        for n in 1..6 {
+            for _ in 0..20 {
+                let tree = LabelTree {
+                    labels: (0..n).map(|i| i.to_string()).collect_vec(),
+                    parents: (0..n).map(|i| if i == 0 { None } else { Some(rng.gen_range(0..i)) }).collect_vec(),
+                    probability: (0..n).map(|_| [rng.gen_range(0.01..0.99), rng.gen_range(0.01..0.99)]).collect_vec(),
+                    mutual_information: vec![0.0; n]
+                };
+                let scores = (0..n).map(|_| rng.gen_range(0.0..1.0)).collect_vec();
+                let prior_weight = rng.gen_range(0.0..2.0);
+                let best = (0..(1 << n))
+                    .map(|set: usize| (0..n).map(|i| set & (1 << i) != 0).collect_vec())
+                    .max_by(|v1, v2| log_score(&tree, &scores, prior_weight, v1).total_cmp(&log_score(&tree, &scores, prior_weight, v2)))
+                    .unwrap();
+                assert_eq!(tree.decode(&scores, prior_weight), best, "tree {:?} scores {:?}", tree, scores);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_prior_weight_keeps_only_the_scores() {
+        let tree = LabelTree::train(&chain_input(), &labels(&["a", "b", "c"]), 1.0);
+        let scores = [0.5, 0.49, 0.9];
+        assert_eq!(tree.decode(&scores, 0.0), scores.iter().map(|score| *score >= 0.5).collect_vec());
+        let scores = [0.9, 0.4, 0.4];
+        assert_eq!(tree.decode(&scores, 0.0), vec![true, false, false]);
+        // the prior pulls in the labels a implies
+        assert_eq!(tree.decode(&scores, 1.0), vec![true, true, true]);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let tree = LabelTree::train(&chain_input(), &labels(&["a", "b", "c"]), 0.5);
+        let file = env::temp_dir().join(format!("label_tree_test_{}.tsv", process::id())).to_string_lossy().to_string();
+        tree.save(&file);
+        let loaded = LabelTree::load(&file);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(loaded, tree);
+    }
+}
@@ -3,30 +3,90 @@ use std::collections::HashMap;
 use std::io::{Write, Read};
 use std::ops::Index;
 use std::collections::VecDeque;
+use std::fmt;
 
 pub mod config;
 pub mod learn;
 pub mod file;
+pub mod naive_bayes;
+pub mod cross_validation;
+pub mod search;
+pub mod feature_selection;
+pub mod gradient;
+pub mod shard;
+pub mod multi_label;
+pub mod label_tree;
 
 use crate::util::InputTup;
-use crate::util::{multi_thread_process_list, reduce};
+use crate::util::reduce;
+use crate::parallel::process_chunks;
+use crate::n_gram::naive_bayes::NaiveBayesStats;
+use crate::metrics::{ClassificationReport, Prediction};
+use crate::vocabulary::Vocabulary;
 
 // (total num words, word -> probability)
 // probability = num times word appears / total num words for type
 pub type NgramBag = (usize, HashMap<String, f32>);
 pub type NgramMap = HashMap<String, NgramBag>;
 
+// Label returned by test_sentence when no gram in the sentence is known
+pub const INCONCLUSIVE: &str = "Inconclusive";
+
+// How a sentence is scored against the n-gram maps
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum ScoringMode {
+    // every gram votes for the type with the highest probability
+    #[default]
+    Voting,
+    // multinomial naive bayes with laplace smoothing (the value is the smoothing amount)
+    NaiveBayes(f64),
+    // the maps hold learned weights instead of probabilities, see gradient.rs
+    Linear
+}
+
+impl ScoringMode {
+    // Parses the Display format: voting, naive_bayes(1), linear
+    pub fn parse(s: &str) -> Option<ScoringMode> {
+        match s {
+            "voting" => Some(ScoringMode::Voting),
+            "linear" => Some(ScoringMode::Linear),
+            _ => {
+                let smoothing = s.strip_prefix("naive_bayes(")?.strip_suffix(")")?;
+                smoothing.parse::<f64>().ok().map(ScoringMode::NaiveBayes)
+            }
+        }
+    }
+}
+
+// Scoring state computed once for a set of maps and reused for many sentences
+enum PreparedScoring {
+    Voting,
+    NaiveBayes(NaiveBayesStats),
+    Linear
+}
+
+impl fmt::Display for ScoringMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScoringMode::Voting => write!(f, "voting"),
+            ScoringMode::NaiveBayes(smoothing) => write!(f, "naive_bayes({})", smoothing),
+            ScoringMode::Linear => write!(f, "linear")
+        }
+    }
+}
+
 pub struct NGram {
     pub ngram_maps: Vec<NgramMap>,
-    pub max_grams: i8
+    pub max_grams: i8,
+    pub scoring: ScoringMode
 }
 
 impl NGram {
-    fn create_grams(s: &String, n: usize) -> Vec<String> {
+    pub(crate) fn create_grams(s: &str, n: usize) -> Vec<String> {
         let mut ret_val = Vec::new();
         let mut last_words: VecDeque<String> = VecDeque::new();
-        for wd in s.split(" ") {
-            if wd.eq("") { continue; }
+        // sentences are TextPipeline output, tokens separated by spaces
+        for wd in s.split_whitespace() {
             last_words.push_back(String::from(wd));
             if last_words.len() < n {
                 continue;
@@ -87,11 +147,17 @@ impl NGram {
 
 
     pub fn new(input_data: &Vec<InputTup>, max_grams: i8) -> NGram {
-        let mut bow = NGram { ngram_maps: Vec::new(), max_grams };
+        let mut bow = NGram { ngram_maps: Vec::new(), max_grams, scoring: ScoringMode::default() };
         bow.train(input_data);
         bow
     }
     
+    // words outside the vocabulary are trained as <UNK>,
+    // classify sentences mapped with the same vocabulary
+    pub fn new_with_vocabulary(input_data: &[InputTup], max_grams: i8, vocabulary: &Vocabulary) -> NGram {
+        NGram::new(&vocabulary.map_input(input_data), max_grams)
+    }
+
     pub fn train(&mut self, input_data: &Vec<InputTup>) {
         let input_groups = input_data.iter()
             .filter(|tup| tup.0 != "")
@@ -128,7 +194,7 @@ impl NGram {
         String::from(best_prob.0)
     }
 
-    pub fn test_sentence_static(bow: &Vec<NgramMap>, sentence: &String) -> String {
+    fn count_votes(bow: &Vec<NgramMap>, sentence: &str) -> HashMap<String, i32> {
         let mut totals_hm: HashMap<String, i32> = HashMap::new();
         let mut found_words: Vec<String> = Vec::new();
         for i in (1..(bow.len()+1)).rev() {
@@ -148,38 +214,96 @@ impl NGram {
                 totals_hm.insert(best_type, total);
             }
         }
-    
-        let mut best_type = (String::from(""), 0);
-        for (type_name, total) in totals_hm.into_iter() {
-            if total > best_type.1 {
-                best_type = (type_name, total)
-            }
+        totals_hm
+    }
+
+    fn classify_votes(bow: &Vec<NgramMap>, sentence: &str) -> Option<Vec<(String, f64)>> {
+        let totals_hm = NGram::count_votes(bow, sentence);
+        let total_votes: i32 = totals_hm.values().sum();
+        if total_votes == 0 {
+            return None;
+        }
+
+        let scores = totals_hm
+            .into_iter()
+            .map(|(type_name, total)| (type_name, total as f64 / total_votes as f64))
+            .sorted_by(|(type1, score1), (type2, score2)| score2.total_cmp(score1).then(type1.cmp(type2)))
+            .collect_vec();
+        Some(scores)
+    }
+
+    // Naive bayes needs totals over the whole map, prepare them once when scoring many sentences
+    fn classify_prepared(bow: &Vec<NgramMap>, sentence: &str, prepared: &PreparedScoring) -> Option<Vec<(String, f64)>> {
+        match prepared {
+            PreparedScoring::Voting => NGram::classify_votes(bow, sentence),
+            PreparedScoring::NaiveBayes(stats) => stats.score(bow, sentence),
+            PreparedScoring::Linear => NGram::score_linear(bow, sentence)
         }
+    }
 
-        if best_type.0.eq("") {
-            best_type.0 = String::from("Inconclusive");
+    fn prepare_scoring(bow: &Vec<NgramMap>, scoring: &ScoringMode) -> PreparedScoring {
+        match scoring {
+            ScoringMode::Voting => PreparedScoring::Voting,
+            ScoringMode::NaiveBayes(smoothing) => PreparedScoring::NaiveBayes(NaiveBayesStats::new(bow, *smoothing)),
+            ScoringMode::Linear => PreparedScoring::Linear
+        }
+    }
+
+    // Every type with its normalized score, best first
+    // Voting: share of the gram votes, NaiveBayes and Linear: posterior probability
+    // None when no gram in the sentence was found in any bag
+    pub fn classify_static(bow: &Vec<NgramMap>, sentence: &str, scoring: &ScoringMode) -> Option<Vec<(String, f64)>> {
+        NGram::classify_prepared(bow, sentence, &NGram::prepare_scoring(bow, scoring))
+    }
+
+    pub fn classify(&self, sentence: &str) -> Option<Vec<(String, f64)>> {
+        NGram::classify_static(&self.ngram_maps, sentence, &self.scoring)
+    }
+
+    fn best_type(o_scores: Option<Vec<(String, f64)>>) -> String {
+        match o_scores {
+            Some(scores) => scores.index(0).0.clone(),
+            None => String::from(INCONCLUSIVE)
         }
-    
-        best_type.0
+    }
+
+    pub fn test_sentence_static(bow: &Vec<NgramMap>, sentence: &str, scoring: &ScoringMode) -> String {
+        NGram::best_type(NGram::classify_static(bow, sentence, scoring))
     }
 
     pub fn test_sentence(&self, sentence: &String) -> String {
-        NGram::test_sentence_static(&self.ngram_maps, sentence)
+        NGram::test_sentence_static(&self.ngram_maps, sentence, &self.scoring)
     }
 
-    pub fn validate(gram_maps: &Vec<NgramMap>, input: &Vec<InputTup>) -> f32 {
-        let num_inputs = input.len();
-        let f_thread = |c_maps: Vec<NgramMap>, chunk: &Vec<(String, String)>| -> Vec<bool> {
-            let mut correct_vec = Vec::new();
+    // (actual type, predicted type) for every input, None when inconclusive
+    pub fn predict_all(gram_maps: &Vec<NgramMap>, input: &[InputTup], scoring: &ScoringMode) -> Vec<Prediction> {
+        let stats = NGram::prepare_scoring(gram_maps, scoring);
+        let f_thread = |chunk: &[(String, String)]| -> Vec<Prediction> {
+            let mut predictions = Vec::new();
             for (tweet_type, sentence) in chunk {
-                correct_vec.push(&NGram::test_sentence_static(&c_maps, sentence) == tweet_type)
+                let o_predicted = NGram::classify_prepared(gram_maps, sentence, &stats)
+                    .map(|scores| scores.index(0).0.clone());
+                predictions.push((tweet_type.clone(), o_predicted));
             }
-            correct_vec
+            predictions
         };
-        
-        let results = multi_thread_process_list(input, gram_maps.clone(), 16, f_thread, None);
-        let num_correct = results.into_iter().filter(|b| *b).collect_vec().len() as i32;
+
+        process_chunks(input, f_thread, None)
+    }
+
+    pub fn validate(gram_maps: &Vec<NgramMap>, input: &[InputTup], scoring: &ScoringMode) -> f32 {
+        let num_inputs = input.len();
+        let results = NGram::predict_all(gram_maps, input, scoring);
+        let num_correct = results
+            .into_iter()
+            .filter(|(tweet_type, o_predicted)| o_predicted.as_ref() == Some(tweet_type))
+            .collect_vec()
+            .len() as i32;
         
         num_correct as f32 / num_inputs as f32
     }
+
+    pub fn evaluate(gram_maps: &Vec<NgramMap>, input: &[InputTup], scoring: &ScoringMode) -> ClassificationReport {
+        ClassificationReport::from_predictions(&NGram::predict_all(gram_maps, input, scoring))
+    }
 }
\ No newline at end of file
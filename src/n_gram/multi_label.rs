@@ -0,0 +1,303 @@
+use itertools::Itertools;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use crate::metrics::{MultiLabelPrediction, MultiLabelReport};
+use crate::n_gram::{NGram, PreparedScoring, ScoringMode};
+use crate::parallel::process_chunks;
+use crate::text::is_reserved;
+use crate::util::InputTup;
+
+/*
+One vs rest multi label classification.
+Every label gets a two type n-gram model: inputs with the label train the label type,
+all other inputs train REST_TYPE. A sentence gets every label whose score for the
+label type reaches that label's threshold, so the result is a set of labels.
+*/
+
+// (labels, cleaned text)
+pub type MultiLabelInput = (Vec<String>, String);
+
+// Type name for the inputs without the label, train rejects reserved (text::is_reserved) labels
+pub const REST_TYPE: &str = "<<REST>>";
+
+const DEFAULT_THRESHOLD: f64 = 0.5;
+
+pub struct LabelModel {
+    pub label: String,
+    pub ngram: NGram,
+    // lowest score that still predicts the label
+    pub threshold: f64
+}
+
+pub struct MultiLabelNGram {
+    // sorted by label
+    pub models: Vec<LabelModel>
+}
+
+impl MultiLabelNGram {
+    pub fn train(input: &[MultiLabelInput], max_grams: i8, scoring: ScoringMode) -> MultiLabelNGram {
+        let labels = input
+            .iter()
+            .flat_map(|(labels, _)| labels.iter().cloned())
+            .sorted()
+            .dedup()
+            .collect_vec();
+        if let Some(label) = labels.iter().find(|label| is_reserved(label)) {
+            panic!("Multi-label training: label {} is reserved", label);
+        }
+
+        let f_thread = |chunk: &[String]| -> Vec<LabelModel> {
+            chunk
+                .iter()
+                .map(|label| {
+                    let binary_input = input
+                        .iter()
+                        .map(|(labels, text)| {
+                            let type_name = if labels.contains(label) { label.as_str() } else { REST_TYPE };
+                            (String::from(type_name), text.clone())
+                        })
+                        .collect_vec();
+                    let mut ngram = NGram::new(&binary_input, max_grams);
+                    ngram.scoring = scoring;
+                    LabelModel { label: label.clone(), ngram, threshold: DEFAULT_THRESHOLD }
+                })
+                .collect_vec()
+        };
+        MultiLabelNGram { models: process_chunks(&labels, f_thread, None) }
+    }
+
+    pub fn labels(&self) -> Vec<String> {
+        self.models.iter().map(|m| m.label.clone()).collect_vec()
+    }
+
+    fn prepare(&self) -> Vec<PreparedScoring> {
+        self.models
+            .iter()
+            .map(|m| NGram::prepare_scoring(&m.ngram.ngram_maps, &m.ngram.scoring))
+            .collect_vec()
+    }
+
+    // score of the label type in each label model, 0 when the model was inconclusive
+    fn scores_prepared(&self, prepared: &Vec<PreparedScoring>, sentence: &str) -> Vec<f64> {
+        self.models
+            .iter()
+            .zip(prepared)
+            .map(|(m, stats)| {
+                NGram::classify_prepared(&m.ngram.ngram_maps, sentence, stats)
+                    .and_then(|scores| scores.into_iter().find(|(type_name, _)| type_name == &m.label))
+                    .map(|(_, score)| score)
+                    .unwrap_or(0.0)
+            })
+            .collect_vec()
+    }
+
+    fn select(&self, scores: &Vec<f64>) -> Vec<String> {
+        self.models
+            .iter()
+            .zip(scores)
+            .filter(|(m, score)| **score >= m.threshold)
+            .map(|(m, _)| m.label.clone())
+            .collect_vec()
+    }
+
+    // (label, score) for every label
+    pub fn scores(&self, sentence: &str) -> Vec<(String, f64)> {
+        self.labels().into_iter().zip(self.scores_prepared(&self.prepare(), sentence)).collect_vec()
+    }
+
+    // every label whose score reaches its threshold, sorted
+    pub fn predict(&self, sentence: &str) -> Vec<String> {
+        self.select(&self.scores_prepared(&self.prepare(), sentence))
+    }
+
+    // (actual labels, label scores) for every input
+    pub(crate) fn score_all(&self, input: &[MultiLabelInput]) -> Vec<(Vec<String>, Vec<f64>)> {
+        let prepared = self.prepare();
+        let f_thread = |chunk: &[MultiLabelInput]| -> Vec<(Vec<String>, Vec<f64>)> {
+            chunk
+                .iter()
+                .map(|(labels, sentence)| (labels.clone(), self.scores_prepared(&prepared, sentence)))
+                .collect_vec()
+        };
+        process_chunks(input, f_thread, None)
+    }
+
+    pub fn predict_all(&self, input: &[MultiLabelInput]) -> Vec<MultiLabelPrediction> {
+        self.score_all(input)
+            .into_iter()
+            .map(|(actual, scores)| (actual, self.select(&scores)))
+            .collect_vec()
+    }
+
+    pub fn evaluate(&self, input: &[MultiLabelInput]) -> MultiLabelReport {
+        MultiLabelReport::from_predictions(&self.predict_all(input))
+    }
+
+    // Pick the threshold with the best f1 of each label on the input out of
+    // 0.05, 0.10 ... 0.95, ties go to the threshold closest to 0.5
+    pub fn tune_thresholds(&mut self, input: &[MultiLabelInput]) {
+        let scored = self.score_all(input);
+        let candidates = (1..20).map(|i| i as f64 * 0.05).collect_vec();
+        for (i, model) in self.models.iter_mut().enumerate() {
+            let f1_at = |threshold: f64| {
+                let (mut tp, mut predicted, mut actual) = (0, 0, 0);
+                for (labels, scores) in &scored {
+                    let is_label = labels.contains(&model.label);
+                    let is_predicted = scores[i] >= threshold;
+                    if is_label && is_predicted { tp += 1; }
+                    if is_predicted { predicted += 1; }
+                    if is_label { actual += 1; }
+                }
+                if predicted + actual == 0 { 0.0 } else { 2.0 * tp as f64 / (predicted + actual) as f64 }
+            };
+            model.threshold = candidates
+                .iter()
+                .map(|t| (*t, f1_at(*t)))
+                .max_by(|(t1, f1), (t2, f2)| f1.total_cmp(f2).then((t2 - 0.5).abs().total_cmp(&(t1 - 0.5).abs())))
+                .map(|(t, _)| t)
+                .unwrap_or(DEFAULT_THRESHOLD);
+        }
+    }
+
+    // file_name holds label<TAB>threshold<TAB>model file per line,
+    // each label model is saved next to it as file_name.<index>
+    // and listed by file name only so the files can be moved together
+    pub fn save(&self, file_name: &str) {
+        let mut index = File::create(file_name).expect("Error creating file object");
+        for (i, model) in self.models.iter().enumerate() {
+            let model_file = format!("{}.{}", file_name, i);
+            model.ngram.save(&model_file);
+            let model_name = Path::new(&model_file).file_name().expect("model file has no name").to_string_lossy();
+            index.write_all(format!("{}\t{}\t{}\n", model.label, model.threshold, model_name).as_bytes()).expect("Error writing to file");
+        }
+    }
+
+    pub fn load(file_name: &str) -> MultiLabelNGram {
+        let err = format!("Error reading multi label model: {}", file_name);
+        let dir = Path::new(file_name).parent().unwrap_or(Path::new(""));
+        let models = fs::read_to_string(file_name)
+            .expect(&err)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let fields = line.split('\t').collect_vec();
+                if fields.len() != 3 { panic!("Expected label, threshold and model file, got: {}", line) }
+                LabelModel {
+                    label: String::from(fields[0]),
+                    threshold: fields[1].parse::<f64>().unwrap_or_else(|_| panic!("Bad threshold: {}", fields[1])),
+                    ngram: NGram::load(&dir.join(fields[2]).to_string_lossy())
+                }
+            })
+            .collect_vec();
+        MultiLabelNGram { models }
+    }
+}
+
+// Splits the label field of (labels, text) input on the separator, empty labels are dropped
+pub fn split_labels(input: Vec<InputTup>, separator: &str) -> Vec<MultiLabelInput> {
+    input
+        .into_iter()
+        .map(|(labels, text)| {
+            let labels = labels
+                .split(separator)
+                .map(|l| l.trim())
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .sorted()
+                .dedup()
+                .collect_vec();
+            (labels, text)
+        })
+        .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn input(rows: &[(&[&str], &str)]) -> Vec<MultiLabelInput> {
+        rows.iter()
+            .map(|(labels, text)| (labels.iter().map(|label| String::from(*label)).collect_vec(), String::from(*text)))
+            .collect_vec()
+    }
+
+    fn news() -> MultiLabelNGram {
+        let training_data = input(&[
+            (&["sports"], "ball game team"),
+            (&["sports"], "team score"),
+            (&["politics"], "vote election law"),
+            (&["politics"], "law vote"),
+            (&["politics", "sports"], "ball vote"),
+            (&[], "rain weather")
+        ]);
+        MultiLabelNGram::train(&training_data, 1, ScoringMode::Voting)
+    }
+
+    #[test]
+    fn one_model_per_label() {
+        let model = news();
+        assert_eq!(model.labels(), vec!["politics", "sports"]);
+        for m in &model.models {
+            let types = m.ngram.ngram_maps[0].keys().cloned().sorted().collect_vec();
+            assert_eq!(types, vec![m.label.clone(), String::from(REST_TYPE)].into_iter().sorted().collect_vec());
+            assert_eq!(m.threshold, DEFAULT_THRESHOLD);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "reserved")]
+    fn reserved_labels_are_rejected() {
+        MultiLabelNGram::train(&input(&[(&["<<REST>>"], "text")]), 1, ScoringMode::Voting);
+    }
+
+    #[test]
+    fn predicts_label_sets() {
+        let model = news();
+        assert_eq!(model.predict("team game"), vec!["sports"]);
+        assert_eq!(model.predict("election law"), vec!["politics"]);
+        assert_eq!(model.predict("team election"), vec!["politics", "sports"]);
+        assert!(model.predict("rain").is_empty());
+        // unknown words leave every model inconclusive, a score of 0
+        assert!(model.predict("unknown").is_empty());
+        assert_eq!(model.scores("unknown"), vec![(String::from("politics"), 0.0), (String::from("sports"), 0.0)]);
+    }
+
+    #[test]
+    fn tuned_thresholds() {
+        // "a b c" gets a third of the votes for x, "b" none
+        let mut model = MultiLabelNGram::train(&input(&[(&["x"], "a"), (&[], "b"), (&[], "c")]), 1, ScoringMode::Voting);
+        model.tune_thresholds(&input(&[(&["x"], "a b c"), (&[], "b")]));
+        // every threshold up to 1/3 separates them, 0.30 is the closest to 0.5
+        assert!((model.models[0].threshold - 0.3).abs() < 1e-9, "threshold {}", model.models[0].threshold);
+        assert_eq!(model.predict("a b c"), vec!["x"]);
+
+        // scores of 1 and 0: every candidate has the same f1, the tie goes to 0.5
+        model.tune_thresholds(&input(&[(&["x"], "a"), (&[], "b")]));
+        assert!((model.models[0].threshold - 0.5).abs() < 1e-9, "threshold {}", model.models[0].threshold);
+    }
+
+    #[test]
+    fn save_and_load_from_a_moved_directory() {
+        let mut model = news();
+        model.models[1].threshold = 0.35;
+        // relative to the working directory, like a model path on the command line
+        let dir = format!("target/multi_label_test_{}", process::id());
+        let moved_dir = format!("{}_moved", dir);
+        fs::create_dir_all(&dir).unwrap();
+        model.save(&format!("{}/news.idx", dir));
+        let index = fs::read_to_string(format!("{}/news.idx", dir)).unwrap();
+        assert_eq!(index, "politics\t0.5\tnews.idx.0\nsports\t0.35\tnews.idx.1\n");
+
+        fs::rename(&dir, &moved_dir).unwrap();
+        let loaded = MultiLabelNGram::load(&format!("{}/news.idx", moved_dir));
+        fs::remove_dir_all(&moved_dir).unwrap();
+        assert_eq!(loaded.labels(), model.labels());
+        assert_eq!(loaded.models.iter().map(|m| m.threshold).collect_vec(), vec![0.5, 0.35]);
+        for sentence in ["team game", "election law", "team election", "rain"] {
+            assert_eq!(loaded.scores(sentence), model.scores(sentence));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+use std::ops::Index;
+
+use crate::n_gram::{NGram, NgramMap};
+
+// Multinomial naive bayes over the gram counts stored in the n-gram maps
+// log P(type) + sum over every gram order of log P(gram | type)
+pub struct NaiveBayesStats {
+    // log(total inputs for type / total inputs)
+    log_priors: HashMap<String, f64>,
+    // per gram order: type -> log(total gram count for type + smoothing * vocabulary size)
+    log_denominators: Vec<HashMap<String, f64>>,
+    smoothing: f64
+}
+
+impl NaiveBayesStats {
+    pub fn new(bow: &Vec<NgramMap>, smoothing: f64) -> NaiveBayesStats {
+        let mut log_priors = HashMap::new();
+        if !bow.is_empty() {
+            let all_inputs: usize = bow.index(0).values().map(|(total, _)| total).sum();
+            for (type_name, (total, _)) in bow.index(0) {
+                log_priors.insert(type_name.clone(), f64::ln(*total as f64 / all_inputs as f64));
+            }
+        }
+
+        let mut log_denominators = Vec::new();
+        for g_map in bow {
+            let vocabulary_size = g_map
+                .values()
+                .flat_map(|(_, bag)| bag.keys())
+                .collect::<HashSet<&String>>()
+                .len();
+            let mut denominators = HashMap::new();
+            for (type_name, (total, bag)) in g_map {
+                let gram_count: f64 = bag.values().map(|prob| NaiveBayesStats::gram_count(prob, total)).sum();
+                denominators.insert(type_name.clone(), f64::ln(gram_count + smoothing * vocabulary_size as f64));
+            }
+            log_denominators.push(denominators);
+        }
+
+        NaiveBayesStats { log_priors, log_denominators, smoothing }
+    }
+
+    // The bags store count / total inputs, recover the raw count
+    fn gram_count(prob: &f32, total: &usize) -> f64 {
+        f64::round(*prob as f64 * *total as f64)
+    }
+
+    // Posterior probability of every type, best first
+    // None when no gram in the sentence was seen during training
+    pub fn score(&self, bow: &Vec<NgramMap>, sentence: &str) -> Option<Vec<(String, f64)>> {
+        let mut log_scores = self.log_priors.clone();
+        let mut found_gram = false;
+        for i in 1..(bow.len()+1) {
+            let g_map = bow.index(i-1);
+            let denominators = self.log_denominators.index(i-1);
+            for gram in NGram::create_grams(sentence, i) {
+                if !g_map.values().any(|(_, bag)| bag.contains_key(&gram)) {
+                    continue;
+                }
+                found_gram = true;
+                for (type_name, (total, bag)) in g_map {
+                    let count = match bag.get(&gram) {
+                        Some(prob) => NaiveBayesStats::gram_count(prob, total),
+                        None => 0.0
+                    };
+                    let log_likelihood = f64::ln(count + self.smoothing) - denominators.index(type_name);
+                    if let Some(log_score) = log_scores.get_mut(type_name) {
+                        *log_score += log_likelihood;
+                    }
+                }
+            }
+        }
+        if !found_gram {
+            return None;
+        }
+
+        // softmax in log space to turn the joint scores into posteriors
+        let max_score = log_scores.values().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let normalizer: f64 = log_scores.values().map(|s| f64::exp(s - max_score)).sum();
+        let scores = log_scores
+            .into_iter()
+            .map(|(type_name, s)| (type_name, f64::exp(s - max_score) / normalizer))
+            .sorted_by(|(type1, score1), (type2, score2)| score2.total_cmp(score1).then(type1.cmp(type2)))
+            .collect_vec();
+        Some(scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn posterior_matches_hand_computation() {
+        let input = vec![
+            (String::from("a"), String::from("x y")),
+            (String::from("a"), String::from("x x")),
+            (String::from("b"), String::from("y z"))
+        ];
+        let ngram = NGram::new(&input, 1);
+        let stats = NaiveBayesStats::new(&ngram.ngram_maps, 1.0);
+        // vocabulary {x, y, z}, a has 4 grams (x 3 times), b has 2 (no x)
+        // P(a) P(x|a) = 2/3 * (3+1)/(4+3), P(b) P(x|b) = 1/3 * (0+1)/(2+3)
+        let scores = stats.score(&ngram.ngram_maps, &String::from("x")).unwrap();
+        assert_eq!(scores[0].0, "a");
+        assert!((scores[0].1 - 40.0 / 47.0).abs() < 1e-9);
+        assert!((scores[1].1 - 7.0 / 47.0).abs() < 1e-9);
+        assert!(stats.score(&ngram.ngram_maps, &String::from("q")).is_none());
+    }
+}
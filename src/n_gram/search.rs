@@ -0,0 +1,319 @@
+use itertools::Itertools;
+use json::{JsonValue, object};
+use rand::Rng;
+
+use crate::random::seeded_rng;
+use std::fmt;
+
+use crate::metrics::ClassificationReport;
+use crate::n_gram::{NGram, ScoringMode};
+use crate::n_gram::config::{ConfigError, LearnConfig, PruneCountConfig, out_of_range};
+use crate::parallel::process_chunks;
+use crate::util::InputTup;
+
+// Settings that are varied between trials
+#[derive(Clone, Debug)]
+pub struct TrialParams {
+    pub max_grams: i8,
+    pub scoring: ScoringMode,
+    // None trains without count pruning
+    pub prune_count: Option<PruneCountConfig>
+}
+
+impl fmt::Display for TrialParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "max_grams={} scoring={}", self.max_grams, self.scoring)?;
+        match &self.prune_count {
+            Some(config) => write!(f, " min_count={} adjust_amount={}", config.min_count, config.adjust_amount),
+            None => write!(f, " min_count=none")
+        }
+    }
+}
+
+// Every combination of these values is a candidate trial
+#[derive(Clone, Debug)]
+pub struct SearchSpace {
+    pub max_grams: Vec<i8>,
+    // smoothing is searched through ScoringMode::NaiveBayes values
+    pub scoring: Vec<ScoringMode>,
+    // None in the list means a trial without count pruning
+    pub min_count: Vec<Option<i32>>,
+    pub adjust_amount: f32
+}
+
+impl Default for SearchSpace {
+    fn default() -> SearchSpace {
+        SearchSpace {
+            max_grams: vec![1, 2, 3],
+            scoring: vec![
+                ScoringMode::Voting,
+                ScoringMode::NaiveBayes(0.1),
+                ScoringMode::NaiveBayes(1.0)
+            ],
+            min_count: vec![None, Some(1), Some(2)],
+            adjust_amount: PruneCountConfig::default().adjust_amount
+        }
+    }
+}
+
+impl SearchSpace {
+    pub fn grid(&self) -> Vec<TrialParams> {
+        let mut trials = Vec::new();
+        for max_grams in &self.max_grams {
+            for scoring in &self.scoring {
+                for min_count in &self.min_count {
+                    trials.push(TrialParams {
+                        max_grams: *max_grams,
+                        scoring: *scoring,
+                        prune_count: min_count.map(|min_count| PruneCountConfig { min_count, adjust_amount: self.adjust_amount })
+                    });
+                }
+            }
+        }
+        trials
+    }
+
+    // num_trials distinct combinations drawn from the grid
+    pub fn sample(&self, num_trials: usize, seed: u64) -> Vec<TrialParams> {
+        let mut remaining = self.grid();
+        let mut rng = seeded_rng(seed);
+        let mut trials = Vec::new();
+        while trials.len() < num_trials && !remaining.is_empty() {
+            let idx = rng.gen_range(0..remaining.len());
+            trials.push(remaining.swap_remove(idx));
+        }
+        trials
+    }
+}
+
+// What each trial is scored against
+#[derive(Clone, Debug)]
+pub enum SearchEvaluation {
+    // train on the training data, evaluate on this set
+    Validation(Vec<InputTup>),
+    // cross validate on the training data (folds, seed)
+    CrossValidation(usize, u64)
+}
+
+// The metric trials are ranked by
+#[derive(Clone, Copy, Debug)]
+pub enum SearchMetric {
+    Accuracy,
+    MacroF1,
+    MicroF1
+}
+
+impl SearchMetric {
+    fn value(&self, report: &ClassificationReport) -> f64 {
+        match self {
+            SearchMetric::Accuracy => report.accuracy,
+            SearchMetric::MacroF1 => report.macro_avg.f1,
+            SearchMetric::MicroF1 => report.micro_avg.f1
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrialResult {
+    pub params: TrialParams,
+    // value of the search metric, mean over folds when cross validating
+    pub score: f64,
+    pub accuracy: f64,
+    pub macro_f1: f64,
+    pub micro_f1: f64
+}
+
+pub struct SearchResult {
+    // best first
+    pub trials: Vec<TrialResult>,
+    // the best params trained on all of the training data
+    pub best_model: NGram
+}
+
+impl SearchResult {
+    pub fn to_json(&self) -> JsonValue {
+        let trials = self.trials
+            .iter()
+            .map(|t| object!{
+                max_grams: t.params.max_grams,
+                scoring: t.params.scoring.to_string(),
+                min_count: t.params.prune_count.as_ref().map(|c| c.min_count),
+                score: t.score,
+                accuracy: t.accuracy,
+                macro_f1: t.macro_f1,
+                micro_f1: t.micro_f1
+            })
+            .collect_vec();
+        object!{ trials: trials }
+    }
+}
+
+impl fmt::Display for SearchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>4} {:>8} {:>8} {:>8} {:>8}  params", "rank", "score", "accuracy", "macro f1", "micro f1")?;
+        for (i, t) in self.trials.iter().enumerate() {
+            write!(f, "\n{:>4} {:>8.4} {:>8.4} {:>8.4} {:>8.4}  {}", i + 1, t.score, t.accuracy, t.macro_f1, t.micro_f1, t.params)?;
+        }
+        Ok(())
+    }
+}
+
+impl NGram {
+    fn train_trial(training_data: &Vec<InputTup>, params: &TrialParams) -> NGram {
+        let mut ngram = NGram::new(training_data, params.max_grams);
+        ngram.scoring = params.scoring;
+        if params.prune_count.is_some() {
+            let mut learn_config = LearnConfig::default();
+            learn_config.prune_selection.count = true;
+            learn_config.prune_count = params.prune_count.clone();
+            ngram.learn(training_data, Some(learn_config));
+        }
+        ngram
+    }
+
+    fn run_trial(training_data: &Vec<InputTup>, evaluation: &SearchEvaluation, metric: &SearchMetric, params: &TrialParams) -> TrialResult {
+        match evaluation {
+            SearchEvaluation::Validation(validation_data) => {
+                let ngram = NGram::train_trial(training_data, params);
+                let report = NGram::evaluate(&ngram.ngram_maps, validation_data, &ngram.scoring);
+                TrialResult {
+                    params: params.clone(),
+                    score: metric.value(&report),
+                    accuracy: report.accuracy,
+                    macro_f1: report.macro_avg.f1,
+                    micro_f1: report.micro_avg.f1
+                }
+            },
+            SearchEvaluation::CrossValidation(folds, seed) => {
+                let cv = NGram::cross_validate_with(training_data, *folds, *seed, &|fold_data| NGram::train_trial(fold_data, params))
+                    .expect("Search folds are checked before the trials run");
+                let scores = cv.folds.iter().map(|r| metric.value(r)).collect_vec();
+                TrialResult {
+                    params: params.clone(),
+                    score: scores.iter().sum::<f64>() / scores.len() as f64,
+                    accuracy: cv.accuracy.mean,
+                    macro_f1: cv.macro_f1.mean,
+                    micro_f1: cv.micro_f1.mean
+                }
+            }
+        }
+    }
+
+    fn check_search(trials: &[TrialParams], evaluation: &SearchEvaluation) -> Result<(), ConfigError> {
+        let search_s = "search";
+        if trials.is_empty() {
+            return out_of_range(search_s, "trials", "at least one trial");
+        }
+        for trial in trials {
+            if trial.max_grams < 1 {
+                return out_of_range(search_s, "max_grams", "at least 1");
+            }
+            if let ScoringMode::NaiveBayes(smoothing) = trial.scoring {
+                if smoothing <= 0.0 {
+                    return out_of_range(search_s, "smoothing", "greater than 0");
+                }
+            }
+        }
+        if let SearchEvaluation::CrossValidation(folds, _) = evaluation {
+            if *folds < 2 {
+                return out_of_range(search_s, "folds", "at least 2");
+            }
+        }
+        Ok(())
+    }
+
+    // Run every trial in parallel and rank them by the metric,
+    // the learn and evaluate calls of a trial run on the trial's thread
+    pub fn search(training_data: &Vec<InputTup>, trials: &[TrialParams], evaluation: &SearchEvaluation, metric: SearchMetric) -> Result<SearchResult, ConfigError> {
+        NGram::check_search(trials, evaluation)?;
+        let f_thread = |chunk: &[TrialParams]| -> Vec<TrialResult> {
+            chunk
+                .iter()
+                .map(|params| NGram::run_trial(training_data, evaluation, &metric, params))
+                .collect_vec()
+        };
+
+        let results = process_chunks(trials, f_thread, None)
+            .into_iter()
+            .sorted_by(|t1, t2| t2.score.total_cmp(&t1.score))
+            .collect_vec();
+
+        let best_model = NGram::train_trial(training_data, &results[0].params);
+        Ok(SearchResult { trials: results, best_model })
+    }
+
+    pub fn grid_search(training_data: &Vec<InputTup>, space: &SearchSpace, evaluation: &SearchEvaluation, metric: SearchMetric) -> Result<SearchResult, ConfigError> {
+        NGram::search(training_data, &space.grid(), evaluation, metric)
+    }
+
+    pub fn random_search(training_data: &Vec<InputTup>, space: &SearchSpace, num_trials: usize, seed: u64, evaluation: &SearchEvaluation, metric: SearchMetric) -> Result<SearchResult, ConfigError> {
+        NGram::search(training_data, &space.sample(num_trials, seed), evaluation, metric)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tup(label: &str, text: &str) -> InputTup {
+        (String::from(label), String::from(text))
+    }
+
+    // only the word order tells the types apart, so it takes 2-grams
+    fn word_order() -> Vec<InputTup> {
+        (0..3)
+            .flat_map(|_| [tup("a", "x y w"), tup("b", "y x w"), tup("a", "w x y"), tup("b", "w y x")])
+            .collect_vec()
+    }
+
+    fn space(max_grams: Vec<i8>) -> SearchSpace {
+        SearchSpace { max_grams, scoring: vec![ScoringMode::Voting], min_count: vec![None], adjust_amount: 0.0 }
+    }
+
+    #[test]
+    fn grid_search_ranks_the_better_trial_first() {
+        let data = word_order();
+        for evaluation in [SearchEvaluation::Validation(data.clone()), SearchEvaluation::CrossValidation(3, 0)] {
+            let result = NGram::grid_search(&data, &space(vec![1, 2]), &evaluation, SearchMetric::Accuracy).unwrap();
+            assert_eq!(result.trials.iter().map(|t| t.params.max_grams).collect_vec(), vec![2, 1]);
+            assert_eq!(result.trials[0].score, 1.0);
+            assert!(result.trials[1].score < 1.0);
+            assert_eq!(result.best_model.max_grams, 2);
+        }
+    }
+
+    #[test]
+    fn random_search_is_reproducible() {
+        let data = word_order();
+        let evaluation = SearchEvaluation::Validation(data.clone());
+        let run = |seed: u64| {
+            NGram::random_search(&data, &SearchSpace::default(), 4, seed, &evaluation, SearchMetric::MacroF1)
+                .unwrap()
+                .trials
+                .iter()
+                .map(|t| (t.params.to_string(), t.score))
+                .collect_vec()
+        };
+        let trials = run(11);
+        assert_eq!(trials.len(), 4);
+        assert_eq!(run(11), trials);
+        assert_eq!(SearchSpace::default().sample(4, 11).len(), 4);
+        assert_eq!(SearchSpace::default().sample(100, 11).len(), SearchSpace::default().grid().len());
+    }
+
+    #[test]
+    fn invalid_searches_are_rejected() {
+        let data = word_order();
+        let evaluation = SearchEvaluation::Validation(data.clone());
+        let rejected = |trials: &Vec<TrialParams>, evaluation: &SearchEvaluation| match NGram::search(&data, trials, evaluation, SearchMetric::Accuracy) {
+            Err(ConfigError::OutOfRange(_, key, _)) => key,
+            _ => panic!("search should be rejected")
+        };
+        assert_eq!(rejected(&Vec::new(), &evaluation), "trials");
+        let mut naive_bayes = space(vec![1]);
+        naive_bayes.scoring = vec![ScoringMode::NaiveBayes(0.0)];
+        assert_eq!(rejected(&naive_bayes.grid(), &evaluation), "smoothing");
+        assert_eq!(rejected(&space(vec![0]).grid(), &evaluation), "max_grams");
+        assert_eq!(rejected(&space(vec![1]).grid(), &SearchEvaluation::CrossValidation(1, 0)), "folds");
+    }
+}
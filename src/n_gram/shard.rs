@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+
+use crate::events;
+use crate::n_gram::{NGram, NgramMap, ScoringMode};
+use crate::n_gram::file::{bag_line, parse_bag_line};
+use crate::parallel::process_chunks;
+use crate::util::InputTup;
+
+/*
+Sharded training: every shard of a corpus is counted on its own into a count file,
+merging the count files of all shards gives the same model as NGram::new on the whole corpus.
+Count files keep whole counts instead of probabilities so shards can be summed in any order.
+*/
+
+// First line of a count file followed by max_grams
+const COUNTS_HEADER: &str = "<<NGRAM COUNTS>>";
+
+// type -> (inputs of the type, gram -> times the gram appeared in them)
+pub type NgramCountMap = HashMap<String, (usize, HashMap<String, usize>)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NgramCounts {
+    pub max_grams: i8,
+    // one map for each gram size, like NGram.ngram_maps
+    pub counts: Vec<NgramCountMap>
+}
+
+impl NgramCounts {
+    pub fn new(max_grams: i8) -> NgramCounts {
+        NgramCounts { max_grams, counts: vec![NgramCountMap::new(); max_grams as usize] }
+    }
+
+    pub fn count(input_data: &[InputTup], max_grams: i8) -> NgramCounts {
+        let f_thread = |chunk: &[InputTup]| -> Vec<NgramCounts> {
+            let mut counts = NgramCounts::new(max_grams);
+            for (type_name, text) in chunk {
+                if type_name.is_empty() { continue; }
+                for (i, count_map) in counts.counts.iter_mut().enumerate() {
+                    let (total, grams) = count_map.entry(type_name.clone()).or_insert_with(|| (0, HashMap::new()));
+                    *total += 1;
+                    for gram in NGram::create_grams(text, i + 1) {
+                        *grams.entry(gram).or_insert(0) += 1;
+                    }
+                }
+            }
+            vec![counts]
+        };
+        let f_progress = |done, total| events::progress("count grams", done, Some(total));
+        let mut counts = NgramCounts::new(max_grams);
+        for partial in process_chunks(input_data, f_thread, Some(&f_progress)) {
+            counts.merge(partial);
+        }
+        counts
+    }
+
+    pub fn merge(&mut self, other: NgramCounts) {
+        if other.max_grams != self.max_grams {
+            panic!("Can not merge counts of {} grams into counts of {} grams", other.max_grams, self.max_grams);
+        }
+        for (count_map, other_map) in self.counts.iter_mut().zip(other.counts) {
+            for (type_name, (other_total, other_grams)) in other_map {
+                let (total, grams) = count_map.entry(type_name).or_insert_with(|| (0, HashMap::new()));
+                *total += other_total;
+                for (gram, count) in other_grams {
+                    *grams.entry(gram).or_insert(0) += count;
+                }
+            }
+        }
+    }
+
+    // probability = times the gram appeared / inputs of the type, as in NGram::train
+    pub fn to_ngram(&self) -> NGram {
+        let ngram_maps = self.counts
+            .iter()
+            .map(|count_map| {
+                count_map
+                    .iter()
+                    .map(|(type_name, (total, grams))| {
+                        let probs = grams
+                            .iter()
+                            .map(|(gram, count)| (gram.clone(), *count as f32 / *total as f32))
+                            .collect::<HashMap<String, f32>>();
+                        (type_name.clone(), (*total, probs))
+                    })
+                    .collect::<NgramMap>()
+            })
+            .collect();
+        NGram { ngram_maps, max_grams: self.max_grams, scoring: ScoringMode::default() }
+    }
+
+    // Writes to file_name.partial first so a shard that fails part way leaves no count file
+    pub fn save(&self, file_name: &str) {
+        let partial_file = format!("{}.partial", file_name);
+        let mut file = File::create(&partial_file).expect("Error creating file object");
+        let mut write = |s: String| file.write_all(s.as_bytes()).expect("Error writing to file");
+        write(format!("{}{}\n", COUNTS_HEADER, self.max_grams));
+        for count_map in &self.counts {
+            for (type_name, (total, grams)) in count_map {
+                write(bag_line(type_name, *total, grams.clone()) + "\n");
+            }
+            write(String::from("<<GRAM>>\n"));
+        }
+        drop(file);
+        fs::rename(&partial_file, file_name).expect("Error renaming count file");
+    }
+
+    pub fn load(file_name: &str) -> NgramCounts {
+        let contents = fs::read_to_string(file_name).unwrap_or_else(|_| panic!("Error reading count file: {}", file_name));
+        let mut lines = contents.lines();
+        let max_grams = lines
+            .next()
+            .and_then(|header| header.strip_prefix(COUNTS_HEADER))
+            .and_then(|max_grams| max_grams.parse::<i8>().ok())
+            .unwrap_or_else(|| panic!("Not an n-gram count file: {}", file_name));
+
+        let mut counts = Vec::new();
+        let mut count_map = NgramCountMap::new();
+        for line in lines {
+            if line.is_empty() { continue; }
+            if line.eq("<<GRAM>>") {
+                counts.push(count_map);
+                count_map = NgramCountMap::new();
+                continue;
+            }
+            let (type_name, total, grams) = parse_bag_line::<usize>(line);
+            count_map.insert(type_name, (total, grams));
+        }
+        if counts.len() != max_grams as usize {
+            panic!("Count file {} has {} gram maps, expected {}", file_name, counts.len(), max_grams);
+        }
+        NgramCounts { max_grams, counts }
+    }
+
+    // Sums the count files of every shard, in any order
+    pub fn merge_files(file_names: &[String]) -> NgramCounts {
+        let mut o_counts: Option<NgramCounts> = None;
+        for (i, file_name) in file_names.iter().enumerate() {
+            let shard = NgramCounts::load(file_name);
+            match o_counts.as_mut() {
+                Some(counts) => counts.merge(shard),
+                None => o_counts = Some(shard)
+            }
+            events::progress("merge counts", i + 1, Some(file_names.len()));
+        }
+        o_counts.expect("No count files to merge")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::shard;
+    use std::{env, process};
+
+    #[test]
+    fn merged_shards_equal_training_on_everything() {
+        let input = [("a", "the cat sat"), ("b", "a dog ran"), ("a", "the cat ran"), ("b", "the dog sat down"), ("a", "cat"), ("c", "sat sat sat")]
+            .iter()
+            .map(|(type_name, text)| (String::from(*type_name), String::from(*text)))
+            .collect::<Vec<InputTup>>();
+        let dir = env::temp_dir().join(format!("ngram_shard_test_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = (0..3)
+            .map(|i| {
+                let file = dir.join(format!("{}.cnt", i)).to_string_lossy().to_string();
+                NgramCounts::count(&shard(&input, i, 3).to_vec(), 3).save(&file);
+                file
+            })
+            .collect::<Vec<String>>();
+        let merged = NgramCounts::merge_files(&files);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(merged, NgramCounts::count(&input, 3));
+        assert!(merged.to_ngram().ngram_maps == NGram::new(&input, 3).ngram_maps);
+    }
+}
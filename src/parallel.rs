@@ -0,0 +1,131 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+// 0 means use the available parallelism
+static NUM_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // set on the threads of process_chunks, nested calls run on the calling thread
+    static IN_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+// Chunks per thread, more chunks keep threads busy when chunks take uneven time
+const CHUNKS_PER_THREAD: usize = 4;
+
+// Thread count for every parallel call in the crate, 0 goes back to the default
+pub fn set_num_threads(num_threads: usize) {
+    NUM_THREADS.store(num_threads, Ordering::Relaxed);
+}
+
+pub fn num_threads() -> usize {
+    match NUM_THREADS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        n => n
+    }
+}
+
+// Runs f_thread over contiguous chunks of the list on scoped threads that borrow the list,
+// threads take the next unclaimed chunk until none are left.
+// Results come back in the order of the list.
+// f_progress gets (items done, total items) on the calling thread as chunks finish.
+// Only the outermost call is parallel, a call from inside f_thread runs f_thread over
+// the whole list on its own thread so nesting never starts threads * threads threads
+pub fn process_chunks<T1, T2, F>(list: &[T1], f_thread: F, f_progress: Option<&dyn Fn(usize, usize)>) -> Vec<T2>
+    where
+        T1: Sync,
+        T2: Send,
+        F: Fn(&[T1]) -> Vec<T2> + Sync
+    {
+    let list_size = list.len();
+    if list_size == 0 {
+        return Vec::new();
+    }
+    if IN_WORKER.with(|in_worker| in_worker.get()) {
+        let results = f_thread(list);
+        if let Some(f) = f_progress {
+            f(list_size, list_size);
+        }
+        return results;
+    }
+    let num_threads = num_threads().min(list_size);
+    let chunk_size = list_size.div_ceil(num_threads * CHUNKS_PER_THREAD);
+    let chunks = list.chunks(chunk_size).collect::<Vec<&[T1]>>();
+    let next_chunk = AtomicUsize::new(0);
+    let mut results: Vec<Option<Vec<T2>>> = (0..chunks.len()).map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel::<(usize, Vec<T2>)>();
+        for _ in 0..num_threads {
+            let tx = tx.clone();
+            let (chunks, next_chunk, f_thread) = (&chunks, &next_chunk, &f_thread);
+            scope.spawn(move || {
+                IN_WORKER.with(|in_worker| in_worker.set(true));
+                loop {
+                    let i = next_chunk.fetch_add(1, Ordering::Relaxed);
+                    if i >= chunks.len() { break; }
+                    tx.send((i, f_thread(chunks[i]))).expect("Error sending data from thread");
+                }
+            });
+        }
+        drop(tx);
+
+        let mut done = 0;
+        for (i, result) in rx {
+            done += chunks[i].len();
+            results[i] = Some(result);
+            if let Some(f) = f_progress {
+                f(done, list_size);
+            }
+        }
+    });
+
+    results
+        .into_iter()
+        .flat_map(|r| r.expect("Thread did not return a result"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn results_keep_the_input_order() {
+        let list = (0..1000).collect::<Vec<usize>>();
+        let expected = list.iter().map(|n| n * 2).collect::<Vec<usize>>();
+        for threads in [1, 3, 8, 0] {
+            set_num_threads(threads);
+            let progress = Mutex::new(Vec::new());
+            let record = |done: usize, total: usize| progress.lock().unwrap().push((done, total));
+            let results = process_chunks(&list, |chunk| chunk.iter().map(|n| n * 2).collect(), Some(&record));
+            assert_eq!(results, expected, "{} threads", threads);
+            let progress = progress.into_inner().unwrap();
+            assert!(progress.windows(2).all(|w| w[0].0 < w[1].0));
+            assert_eq!(progress.last(), Some(&(1000, 1000)));
+        }
+        set_num_threads(0);
+        assert!(process_chunks(&Vec::<usize>::new(), |chunk| chunk.to_vec(), None).is_empty());
+    }
+
+    #[test]
+    fn nested_calls_run_on_the_worker_thread() {
+        let outer = (0..40).collect::<Vec<usize>>();
+        let results = process_chunks(&outer, |chunk| {
+            chunk
+                .iter()
+                .map(|n| {
+                    let inner = (0..*n).collect::<Vec<usize>>();
+                    let worker = thread::current().id();
+                    let threads = process_chunks(&inner, |c| c.iter().map(|_| thread::current().id()).collect(), None);
+                    assert!(threads.iter().all(|id| *id == worker));
+                    process_chunks(&inner, |c| c.to_vec(), None).iter().sum::<usize>()
+                })
+                .collect()
+        }, None);
+        assert_eq!(results, outer.iter().map(|n| (0..*n).sum::<usize>()).collect::<Vec<usize>>());
+        assert!(!IN_WORKER.with(|in_worker| in_worker.get()));
+    }
+}
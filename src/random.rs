@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+/*
+Every stochastic operation in the crate takes a seed or an Rng so runs can be reproduced.
+Work split over threads or repeated in a loop derives its own seed from the
+base seed and a stream number, never from the thread that runs it,
+so results are the same for any thread count.
+*/
+
+pub fn seeded_rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+fn split_mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// Independent seed for a numbered sub stream of the base seed, like one per epoch or per trial
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    split_mix(seed ^ split_mix(stream))
+}
+
+// Draw a key with probability proportional to its weight,
+// keys are visited in sorted order so the hash map order does not change the result
+pub fn sample_weighted<R: Rng + ?Sized>(weights: &HashMap<String, f32>, rng: &mut R) -> Option<String> {
+    let total: f32 = weights.values().filter(|w| **w > 0.0).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let sorted = weights.iter().filter(|(_, w)| **w > 0.0).sorted_by(|(k1, _), (k2, _)| k1.cmp(k2)).collect_vec();
+    let mut target = rng.gen::<f32>() * total;
+    for (key, weight) in &sorted {
+        if target < **weight {
+            return Some((*key).clone());
+        }
+        target -= **weight;
+    }
+    // rounding left a bit of the total over
+    sorted.last().map(|(key, _)| (*key).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draws(seed: u64) -> Vec<u64> {
+        let mut rng = seeded_rng(seed);
+        (0..8).map(|_| rng.gen::<u64>()).collect_vec()
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(43));
+        assert_eq!(derive_seed(42, 3), derive_seed(42, 3));
+        assert_eq!(draws(derive_seed(42, 3)), draws(derive_seed(42, 3)));
+    }
+
+    #[test]
+    fn streams_get_different_seeds() {
+        let seeds = (0..100).map(|stream| derive_seed(42, stream)).collect_vec();
+        assert_eq!(seeds.iter().unique().count(), seeds.len());
+        assert!(!seeds.contains(&42));
+        assert_ne!(derive_seed(42, 0), derive_seed(43, 0));
+        // swapping seed and stream is another stream
+        assert_ne!(derive_seed(1, 2), derive_seed(2, 1));
+        assert_ne!(draws(derive_seed(42, 0)), draws(derive_seed(42, 1)));
+    }
+
+    #[test]
+    fn weighted_samples_follow_the_weights() {
+        let weights: HashMap<String, f32> = [("a", 1.0), ("b", 3.0), ("never", 0.0)].iter().map(|(k, w)| (String::from(*k), *w)).collect();
+        let mut rng = seeded_rng(7);
+        let samples = (0..4000).map(|_| sample_weighted(&weights, &mut rng).unwrap()).collect_vec();
+        let b_count = samples.iter().filter(|s| *s == "b").count();
+        assert!(!samples.iter().any(|s| s == "never"));
+        assert!((2800..3200).contains(&b_count), "{}", b_count);
+        let mut rng1 = seeded_rng(7);
+        assert_eq!(sample_weighted(&weights, &mut rng1), Some(samples[0].clone()));
+        assert_eq!(sample_weighted(&HashMap::new(), &mut rng1), None);
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::sync::Arc;
+use json::{JsonValue, object};
+
+use crate::n_gram::config::{ConfigError, check_keys, get_bool, get_str, out_of_range};
+
+pub mod tokenizer;
+pub mod normalizer;
+pub mod stemmer;
+pub mod stop_words;
+
+use normalizer::{Lemmatizer, Normalizer};
+use stemmer::PorterStemmer;
+use stop_words::StopWords;
+use tokenizer::*;
+
+// Field delimiters of the n-gram (type,total|"gram"value) and markov (from|"to"value)
+// files, a token holding one would not load back the same
+pub const DELIMITERS: [char; 3] = ['"', ',', '|'];
+
+// Tokens in << >> are reserved for the keys models keep next to the grams,
+// like gradient::BIAS_GRAM, TextPipeline drops them
+pub fn is_reserved(token: &str) -> bool {
+    token.len() >= 4 && token.starts_with("<<") && token.ends_with(">>")
+}
+
+const TOKENIZERS: &str = "default, whitespace, regex, unicode_word, tweet";
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum TokenizerConfig {
+    // lowercase and replace punctuation and digits with spaces
+    #[default]
+    Default,
+    Whitespace,
+    Regex(String),
+    UnicodeWord,
+    Tweet { strip_handles: bool, reduce_length: bool }
+}
+
+impl TokenizerConfig {
+    // "default", "whitespace", "unicode_word", "tweet" or "regex(pattern)"
+    pub fn parse(s: &str) -> Option<TokenizerConfig> {
+        match s {
+            "default" => Some(TokenizerConfig::Default),
+            "whitespace" => Some(TokenizerConfig::Whitespace),
+            "unicode_word" => Some(TokenizerConfig::UnicodeWord),
+            "tweet" => Some(TokenizerConfig::Tweet { strip_handles: false, reduce_length: false }),
+            _ => {
+                let pattern = s.strip_prefix("regex(")?.strip_suffix(")")?;
+                Some(TokenizerConfig::Regex(String::from(pattern)))
+            }
+        }
+    }
+
+    /*
+    {
+        type: "default" | "whitespace" | "regex" | "unicode_word" | "tweet",
+        pattern: "\\w+",        regex only
+        strip_handles: false,   tweet only
+        reduce_length: false    tweet only
+    }
+    */
+    pub fn from_json(obj: &JsonValue) -> Result<TokenizerConfig, ConfigError> {
+        let tokenizer_s = "tokenizer";
+        let kind = get_str(obj, tokenizer_s, "type")?.unwrap_or("default");
+        let allowed: &[&str] = match kind {
+            "regex" => &["type", "pattern"],
+            "tweet" => &["type", "strip_handles", "reduce_length"],
+            _ => &["type"]
+        };
+        check_keys(obj, tokenizer_s, allowed)?;
+        let config = match kind {
+            "default" => TokenizerConfig::Default,
+            "whitespace" => TokenizerConfig::Whitespace,
+            "unicode_word" => TokenizerConfig::UnicodeWord,
+            "regex" => match get_str(obj, tokenizer_s, "pattern")? {
+                Some(pattern) => TokenizerConfig::Regex(String::from(pattern)),
+                None => return out_of_range(tokenizer_s, "pattern", "set for the regex tokenizer")
+            },
+            "tweet" => TokenizerConfig::Tweet {
+                strip_handles: get_bool(obj, tokenizer_s, "strip_handles", false)?,
+                reduce_length: get_bool(obj, tokenizer_s, "reduce_length", false)?
+            },
+            _ => return out_of_range(tokenizer_s, "type", TOKENIZERS)
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let TokenizerConfig::Regex(pattern) = self {
+            if let Err(e) = RegexTokenizer::new(pattern) {
+                return out_of_range("tokenizer", "pattern", &format!("a valid regex ({})", e));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            TokenizerConfig::Default => object!{ type: "default" },
+            TokenizerConfig::Whitespace => object!{ type: "whitespace" },
+            TokenizerConfig::Regex(pattern) => object!{ type: "regex", pattern: pattern.clone() },
+            TokenizerConfig::UnicodeWord => object!{ type: "unicode_word" },
+            TokenizerConfig::Tweet { strip_handles, reduce_length } =>
+                object!{ type: "tweet", strip_handles: *strip_handles, reduce_length: *reduce_length }
+        }
+    }
+
+    // panics on an invalid regex, call validate first for untrusted patterns
+    pub fn build(&self) -> Arc<dyn Tokenizer> {
+        match self {
+            TokenizerConfig::Default => Arc::new(DefaultTokenizer),
+            TokenizerConfig::Whitespace => Arc::new(WhitespaceTokenizer),
+            TokenizerConfig::Regex(pattern) => Arc::new(RegexTokenizer::new(pattern).expect("Invalid tokenizer pattern")),
+            TokenizerConfig::UnicodeWord => Arc::new(UnicodeWordTokenizer::new()),
+            TokenizerConfig::Tweet { strip_handles, reduce_length } => Arc::new(TweetTokenizer::new(*strip_handles, *reduce_length))
+        }
+    }
+}
+
+impl fmt::Display for TokenizerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizerConfig::Default => write!(f, "default"),
+            TokenizerConfig::Whitespace => write!(f, "whitespace"),
+            TokenizerConfig::Regex(pattern) => write!(f, "regex({})", pattern),
+            TokenizerConfig::UnicodeWord => write!(f, "unicode_word"),
+            TokenizerConfig::Tweet { .. } => write!(f, "tweet")
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NormalizerConfig {
+    PorterStemmer,
+    // lemma file path
+    Lemmatizer(String)
+}
+
+impl NormalizerConfig {
+    // "porter_stemmer" or "lemmatizer(path)"
+    pub fn parse(s: &str) -> Option<NormalizerConfig> {
+        match s {
+            "porter_stemmer" => Some(NormalizerConfig::PorterStemmer),
+            _ => {
+                let path = s.strip_prefix("lemmatizer(")?.strip_suffix(")")?;
+                Some(NormalizerConfig::Lemmatizer(String::from(path)))
+            }
+        }
+    }
+
+    // a list of normalizer names, applied in order
+    pub fn list_from_json(obj: &JsonValue) -> Result<Vec<NormalizerConfig>, ConfigError> {
+        let wrong_type = ConfigError::WrongType(String::from("experiment"), String::from("normalizers"), String::from("a list of normalizer names"));
+        if !obj.is_array() {
+            return Err(wrong_type);
+        }
+        let mut ret_val = Vec::new();
+        for normalizer in obj.members() {
+            let name = normalizer.as_str().ok_or(wrong_type.clone())?;
+            match NormalizerConfig::parse(name) {
+                Some(config) => ret_val.push(config),
+                None => return out_of_range("experiment", "normalizers", "porter_stemmer or lemmatizer(path)")
+            }
+        }
+        Ok(ret_val)
+    }
+
+    pub fn build(&self) -> Arc<dyn Normalizer> {
+        match self {
+            NormalizerConfig::PorterStemmer => Arc::new(PorterStemmer),
+            NormalizerConfig::Lemmatizer(path) => Arc::new(Lemmatizer::load(path))
+        }
+    }
+}
+
+impl fmt::Display for NormalizerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NormalizerConfig::PorterStemmer => write!(f, "porter_stemmer"),
+            NormalizerConfig::Lemmatizer(path) => write!(f, "lemmatizer({})", path)
+        }
+    }
+}
+
+// Turns raw text into the space separated tokens the models train on:
+// tokenize, drop stop words, run each normalizer in order, then remove the DELIMITERS
+// and the reserved tokens
+#[derive(Clone)]
+pub struct TextPipeline {
+    tokenizer: Arc<dyn Tokenizer>,
+    normalizers: Vec<Arc<dyn Normalizer>>,
+    stop_words: StopWords
+}
+
+impl Default for TextPipeline {
+    fn default() -> TextPipeline {
+        TextPipeline::new(&TokenizerConfig::default(), &Vec::new(), StopWords::new())
+    }
+}
+
+impl TextPipeline {
+    // the stop words are split by the tokenizer like the text they are matched against
+    pub fn new(tokenizer: &TokenizerConfig, normalizers: &[NormalizerConfig], stop_words: StopWords) -> TextPipeline {
+        let tokenizer = tokenizer.build();
+        TextPipeline {
+            stop_words: stop_words.tokenized(&*tokenizer),
+            normalizers: normalizers.iter().map(|n| n.build()).collect(),
+            tokenizer
+        }
+    }
+
+    pub fn tokens(&self, input: &str) -> Vec<String> {
+        self.tokenizer
+            .tokenize(input)
+            .into_iter()
+            .filter(|wd| !self.stop_words.contains(wd))
+            .map(|wd| self.normalizers.iter().fold(wd, |token, n| n.normalize(&token)))
+            .map(|wd| wd.replace(&DELIMITERS[..], ""))
+            .filter(|wd| !wd.is_empty() && !is_reserved(wd))
+            .collect()
+    }
+
+    pub fn clean(&self, input: &str) -> String {
+        self.tokens(input).join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_never_hold_file_delimiters() {
+        let pipeline = TextPipeline::new(&TokenizerConfig::Whitespace, &Vec::new(), StopWords::new());
+        assert_eq!(pipeline.clean(r#"Say "hi", a|b , :|"#), "say hi ab :");
+        let tweets = TextPipeline::new(&TokenizerConfig::Tweet { strip_handles: false, reduce_length: false }, &Vec::new(), StopWords::new());
+        assert_eq!(tweets.clean("http://x.y/?a=1,2|3 :|"), "http://x.y/?a=123 :");
+    }
+
+    #[test]
+    fn reserved_tokens_are_dropped() {
+        let pipeline = TextPipeline::new(&TokenizerConfig::Regex(String::from(r"\S+")), &Vec::new(), StopWords::new());
+        assert_eq!(pipeline.clean("a <<BIAS>> <<rest>> <b> <<>> c"), "a <b> c");
+        assert!(!is_reserved("<<>"));
+    }
+}
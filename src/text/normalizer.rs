@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::fs;
+
+// Maps a token to its normalized form, an empty result drops the token
+pub trait Normalizer: Send + Sync {
+    fn normalize(&self, token: &str) -> String;
+}
+
+// Dictionary lookup, tokens missing from the dictionary are kept as they are
+pub struct Lemmatizer {
+    lemmas: HashMap<String, String>
+}
+
+impl Lemmatizer {
+    pub fn new(lemmas: HashMap<String, String>) -> Lemmatizer {
+        Lemmatizer { lemmas }
+    }
+
+    // one "inflected lemma" pair per line separated by whitespace, lines starting with # are skipped
+    pub fn load(file_path: &str) -> Lemmatizer {
+        let err = format!("Error reading lemma file: {}", file_path);
+        let mut lemmas = HashMap::new();
+        for line in fs::read_to_string(file_path).expect(&err).lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some(word), Some(lemma)) => { lemmas.insert(word.to_lowercase(), lemma.to_lowercase()); },
+                _ => panic!("Lemma file line needs a word and its lemma: {}", line)
+            }
+        }
+        Lemmatizer::new(lemmas)
+    }
+}
+
+impl Normalizer for Lemmatizer {
+    fn normalize(&self, token: &str) -> String {
+        match self.lemmas.get(token) {
+            Some(lemma) => lemma.clone(),
+            None => String::from(token)
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::text::normalizer::Normalizer;
+
+// Porter's English suffix stripping algorithm (1980)
+// words with non ascii letters and words of two letters or less are not changed
+pub struct PorterStemmer;
+
+// The word being stemmed, b[0..=k] is the current stem
+// j marks the end of the stem before the suffix found by the last ends() call
+struct Stem {
+    b: Vec<u8>,
+    k: usize,
+    j: isize
+}
+
+impl Stem {
+    fn cons(&self, i: usize) -> bool {
+        match self.b[i] {
+            b'a' | b'e' | b'i' | b'o' | b'u' => false,
+            b'y' => if i == 0 { true } else { !self.cons(i - 1) },
+            _ => true
+        }
+    }
+
+    // number of vowel consonant sequences in b[0..=j]
+    fn m(&self) -> usize {
+        let mut n = 0;
+        let mut i: isize = 0;
+        loop {
+            if i > self.j { return n; }
+            if !self.cons(i as usize) { break; }
+            i += 1;
+        }
+        i += 1;
+        loop {
+            loop {
+                if i > self.j { return n; }
+                if self.cons(i as usize) { break; }
+                i += 1;
+            }
+            i += 1;
+            n += 1;
+            loop {
+                if i > self.j { return n; }
+                if !self.cons(i as usize) { break; }
+                i += 1;
+            }
+            i += 1;
+        }
+    }
+
+    fn vowel_in_stem(&self) -> bool {
+        (0..=self.j).any(|i| !self.cons(i as usize))
+    }
+
+    fn double_c(&self, j: usize) -> bool {
+        j >= 1 && self.b[j] == self.b[j - 1] && self.cons(j)
+    }
+
+    // consonant vowel consonant ending at i, where the last consonant is not w, x or y
+    fn cvc(&self, i: usize) -> bool {
+        if i < 2 || !self.cons(i) || self.cons(i - 1) || !self.cons(i - 2) {
+            return false;
+        }
+        !matches!(self.b[i], b'w' | b'x' | b'y')
+    }
+
+    fn ends(&mut self, s: &str) -> bool {
+        let s = s.as_bytes();
+        if s.len() > self.k + 1 {
+            return false;
+        }
+        let start = self.k + 1 - s.len();
+        if &self.b[start..=self.k] != s {
+            return false;
+        }
+        self.j = start as isize - 1;
+        true
+    }
+
+    fn set_to(&mut self, s: &str) {
+        let start = (self.j + 1) as usize;
+        self.b.truncate(start);
+        self.b.extend_from_slice(s.as_bytes());
+        self.k = (self.j + s.len() as isize) as usize;
+    }
+
+    fn r(&mut self, s: &str) {
+        if self.m() > 0 {
+            self.set_to(s);
+        }
+    }
+
+    // plurals and -ed or -ing
+    fn step1ab(&mut self) {
+        if self.b[self.k] == b's' {
+            if self.ends("sses") {
+                self.k -= 2;
+            } else if self.ends("ies") {
+                self.set_to("i");
+            } else if self.b[self.k - 1] != b's' {
+                self.k -= 1;
+            }
+        }
+        if self.ends("eed") {
+            if self.m() > 0 {
+                self.k -= 1;
+            }
+        } else if (self.ends("ed") || self.ends("ing")) && self.vowel_in_stem() {
+            self.k = self.j as usize;
+            self.b.truncate(self.k + 1);
+            if self.ends("at") {
+                self.set_to("ate");
+            } else if self.ends("bl") {
+                self.set_to("ble");
+            } else if self.ends("iz") {
+                self.set_to("ize");
+            } else if self.double_c(self.k) {
+                if !matches!(self.b[self.k], b'l' | b's' | b'z') {
+                    self.k -= 1;
+                }
+            } else {
+                self.j = self.k as isize;
+                if self.m() == 1 && self.cvc(self.k) {
+                    self.set_to("e");
+                }
+            }
+        }
+        self.b.truncate(self.k + 1);
+    }
+
+    // terminal y to i when there is another vowel in the stem
+    fn step1c(&mut self) {
+        if self.ends("y") && self.vowel_in_stem() {
+            self.b[self.k] = b'i';
+        }
+    }
+
+    fn replace_first(&mut self, suffixes: &[(&str, &str)]) {
+        for (suffix, replacement) in suffixes {
+            if self.ends(suffix) {
+                self.r(replacement);
+                return;
+            }
+        }
+    }
+
+    // double suffixes to single ones
+    fn step2(&mut self) {
+        let suffixes: &[(&str, &str)] = match self.b[self.k - 1] {
+            b'a' => &[("ational", "ate"), ("tional", "tion")],
+            b'c' => &[("enci", "ence"), ("anci", "ance")],
+            b'e' => &[("izer", "ize")],
+            b'l' => &[("bli", "ble"), ("alli", "al"), ("entli", "ent"), ("eli", "e"), ("ousli", "ous")],
+            b'o' => &[("ization", "ize"), ("ation", "ate"), ("ator", "ate")],
+            b's' => &[("alism", "al"), ("iveness", "ive"), ("fulness", "ful"), ("ousness", "ous")],
+            b't' => &[("aliti", "al"), ("iviti", "ive"), ("biliti", "ble")],
+            b'g' => &[("logi", "log")],
+            _ => &[]
+        };
+        self.replace_first(suffixes);
+    }
+
+    // -ic-, -full, -ness etc.
+    fn step3(&mut self) {
+        let suffixes: &[(&str, &str)] = match self.b[self.k] {
+            b'e' => &[("icate", "ic"), ("ative", ""), ("alize", "al")],
+            b'i' => &[("iciti", "ic")],
+            b'l' => &[("ical", "ic"), ("ful", "")],
+            b's' => &[("ness", "")],
+            _ => &[]
+        };
+        self.replace_first(suffixes);
+    }
+
+    // -ant, -ence etc. in context <c>vcvc<v>
+    fn step4(&mut self) {
+        let suffixes: &[&str] = match self.b[self.k - 1] {
+            b'a' => &["al"],
+            b'c' => &["ance", "ence"],
+            b'e' => &["er"],
+            b'i' => &["ic"],
+            b'l' => &["able", "ible"],
+            b'n' => &["ant", "ement", "ment", "ent"],
+            b'o' => &["ion", "ou"],
+            b's' => &["ism"],
+            b't' => &["ate", "iti"],
+            b'u' => &["ous"],
+            b'v' => &["ive"],
+            b'z' => &["ize"],
+            _ => &[]
+        };
+        let mut found = false;
+        for suffix in suffixes {
+            if self.ends(suffix) {
+                // -ion only after s or t
+                found = !suffix.eq(&"ion") || (self.j >= 0 && matches!(self.b[self.j as usize], b's' | b't'));
+                if found { break; }
+            }
+        }
+        if found && self.m() > 1 {
+            self.k = self.j as usize;
+            self.b.truncate(self.k + 1);
+        }
+    }
+
+    // final -e and -ll
+    fn step5(&mut self) {
+        self.j = self.k as isize;
+        if self.b[self.k] == b'e' {
+            let a = self.m();
+            if a > 1 || (a == 1 && !self.cvc(self.k - 1)) {
+                self.k -= 1;
+            }
+        }
+        if self.b[self.k] == b'l' && self.double_c(self.k) && self.m() > 1 {
+            self.k -= 1;
+        }
+        self.b.truncate(self.k + 1);
+    }
+}
+
+impl PorterStemmer {
+    pub fn stem(word: &str) -> String {
+        if word.len() <= 2 || !word.bytes().all(|c| c.is_ascii_lowercase()) {
+            return String::from(word);
+        }
+        let mut stem = Stem { b: word.as_bytes().to_vec(), k: word.len() - 1, j: 0 };
+        stem.step1ab();
+        if stem.k > 0 {
+            stem.step1c();
+            stem.step2();
+            stem.step3();
+            stem.step4();
+            stem.step5();
+        }
+        String::from_utf8(stem.b).expect("Stem is not ascii")
+    }
+}
+
+impl Normalizer for PorterStemmer {
+    fn normalize(&self, token: &str) -> String {
+        PorterStemmer::stem(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn porter_pairs() {
+        let pairs = [
+            ("caresses", "caress"), ("ponies", "poni"), ("ties", "ti"), ("caress", "caress"), ("cats", "cat"),
+            ("feed", "feed"), ("agreed", "agre"), ("plastered", "plaster"), ("motoring", "motor"), ("sing", "sing"),
+            ("conflated", "conflat"), ("hopping", "hop"), ("falling", "fall"), ("filing", "file"), ("happy", "happi"),
+            ("relational", "relat"), ("conditional", "condit"), ("valenci", "valenc"), ("digitizer", "digit"),
+            ("triplicate", "triplic"), ("hopeful", "hope"), ("goodness", "good"), ("revival", "reviv"),
+            ("adjustable", "adjust"), ("effective", "effect"), ("probate", "probat"), ("controll", "control"),
+            ("loving", "love"), ("generalizations", "gener"), ("oscillators", "oscil")
+        ];
+        for (word, stem) in pairs {
+            assert_eq!(PorterStemmer::stem(word), stem, "stem of {}", word);
+        }
+    }
+
+    #[test]
+    fn short_and_non_ascii_words_are_kept() {
+        assert_eq!(PorterStemmer::stem("is"), "is");
+        assert_eq!(PorterStemmer::stem("niños"), "niños");
+        assert_eq!(PorterStemmer::stem("Running"), "Running");
+    }
+}
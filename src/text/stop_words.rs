@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::fs;
+
+use crate::text::tokenizer::Tokenizer;
+
+pub const LANGUAGES: [&str; 6] = ["english", "french", "german", "italian", "portuguese", "spanish"];
+
+fn bundled_list(language: &str) -> Option<&'static str> {
+    match language {
+        "english" => Some(include_str!("stop_words/english.txt")),
+        "french" => Some(include_str!("stop_words/french.txt")),
+        "german" => Some(include_str!("stop_words/german.txt")),
+        "italian" => Some(include_str!("stop_words/italian.txt")),
+        "portuguese" => Some(include_str!("stop_words/portuguese.txt")),
+        "spanish" => Some(include_str!("stop_words/spanish.txt")),
+        _ => None
+    }
+}
+
+// Words matched exactly against tokens after trimming and lowercasing.
+// TextPipeline first runs the entries through its tokenizer, so with the default
+// tokenizer "don't" becomes "don" and "t", the tokens "don't" is split into
+#[derive(Clone, Debug, PartialEq)]
+pub struct StopWords {
+    words: HashSet<String>
+}
+
+impl Default for StopWords {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StopWords {
+    pub fn new() -> StopWords {
+        StopWords { words: HashSet::new() }
+    }
+
+    // one word per line, empty lines are skipped
+    pub fn parse(list: &str) -> StopWords {
+        let mut stop_words = StopWords::new();
+        for line in list.lines() {
+            stop_words.insert(line);
+        }
+        stop_words
+    }
+
+    pub fn load(file_path: &str) -> StopWords {
+        let err = format!("Error reading stop word file: {}", file_path);
+        StopWords::parse(&fs::read_to_string(file_path).expect(&err))
+    }
+
+    // None for a language without a bundled list
+    pub fn bundled(language: &str) -> Option<StopWords> {
+        bundled_list(language).map(StopWords::parse)
+    }
+
+    pub fn insert(&mut self, word: &str) {
+        let word = word.trim().to_lowercase();
+        if !word.is_empty() {
+            self.words.insert(word);
+        }
+    }
+
+    // The tokens of every entry, so the entries match the tokens of text
+    // split by the same tokenizer
+    pub fn tokenized(&self, tokenizer: &dyn Tokenizer) -> StopWords {
+        self.words.iter().flat_map(|word| tokenizer.tokenize(word)).collect()
+    }
+
+    pub fn extend(&mut self, other: &StopWords) {
+        self.words.extend(other.words.iter().cloned());
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.words.contains(token)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+impl FromIterator<String> for StopWords {
+    fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> StopWords {
+        let mut stop_words = StopWords::new();
+        for word in iter {
+            stop_words.insert(&word);
+        }
+        stop_words
+    }
+}
+
+// Bundled lists combined with an optional custom file
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct StopWordsConfig {
+    pub languages: Vec<String>,
+    pub file: Option<String>
+}
+
+impl StopWordsConfig {
+    pub fn load(&self) -> StopWords {
+        let mut stop_words = StopWords::new();
+        for language in &self.languages {
+            let bundled = StopWords::bundled(language).unwrap_or_else(|| panic!("No bundled stop words for language: {}", language));
+            stop_words.extend(&bundled);
+        }
+        if let Some(file) = &self.file {
+            stop_words.extend(&StopWords::load(file));
+        }
+        stop_words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::{TextPipeline, TokenizerConfig};
+
+    fn english_pipeline(tokenizer: &TokenizerConfig) -> TextPipeline {
+        TextPipeline::new(tokenizer, &Vec::new(), StopWords::bundled("english").unwrap())
+    }
+
+    #[test]
+    fn bundled_contractions_match_the_tokenizer() {
+        let english = StopWords::bundled("english").unwrap();
+        assert!(english.contains("don't"));
+        // the default tokenizer splits "don't" into "don" and "t"
+        assert_eq!(english_pipeline(&TokenizerConfig::Default).clean("I don't like it, you'll see"), "like see");
+        // unicode_word keeps "don't" as one token
+        assert_eq!(english_pipeline(&TokenizerConfig::UnicodeWord).clean("I don't like it, you'll see"), "like see");
+        let pipeline = TextPipeline::new(&TokenizerConfig::Default, &Vec::new(), StopWords::parse("won't"));
+        assert_eq!(pipeline.clean("I won't go"), "i go");
+    }
+
+    #[test]
+    fn entries_are_trimmed_and_lowercased() {
+        let stop_words = StopWords::parse(" The \n\nAND\n");
+        assert_eq!(stop_words.len(), 2);
+        assert!(stop_words.contains("the") && stop_words.contains("and"));
+        let config = StopWordsConfig { languages: vec![String::from("english"), String::from("spanish")], file: None };
+        let combined = config.load();
+        assert!(combined.contains("the") && combined.contains("los"));
+    }
+}
@@ -0,0 +1,199 @@
+use regex::Regex;
+
+// Splits text into tokens, never returns empty tokens. Words are lowercased,
+// the tweet tokenizer keeps the case of urls and emoticons. Tokens can hold any
+// character, TextPipeline removes the ones the model files use as delimiters
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, input: &str) -> Vec<String>;
+}
+
+// The original cleaning: punctuation and digits become spaces
+pub struct DefaultTokenizer;
+
+impl Tokenizer for DefaultTokenizer {
+    fn tokenize(&self, input: &str) -> Vec<String> {
+        let special_characters = "!@#$%^&*()_+-=[]{}\\|;':\",./<>?0123456789\n\r";
+        input
+            .to_lowercase()
+            .split(|c: char| c.is_whitespace() || special_characters.contains(c))
+            .filter(|wd| !wd.is_empty())
+            .map(String::from)
+            .collect()
+    }
+}
+
+// Keeps punctuation attached to the words
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, input: &str) -> Vec<String> {
+        input
+            .to_lowercase()
+            .split_whitespace()
+            .map(String::from)
+            .collect()
+    }
+}
+
+// Every match of the pattern is a token
+pub struct RegexTokenizer {
+    pattern: Regex
+}
+
+impl RegexTokenizer {
+    pub fn new(pattern: &str) -> Result<RegexTokenizer, regex::Error> {
+        Ok(RegexTokenizer { pattern: Regex::new(pattern)? })
+    }
+}
+
+impl Tokenizer for RegexTokenizer {
+    fn tokenize(&self, input: &str) -> Vec<String> {
+        let lower = input.to_lowercase();
+        self.pattern
+            .find_iter(&lower)
+            .map(|m| String::from(m.as_str()))
+            .filter(|wd| !wd.is_empty())
+            .collect()
+    }
+}
+
+// Runs of unicode letters, marks and digits, so non latin scripts survive
+// apostrophes inside a word are kept: "don't" is one token
+pub struct UnicodeWordTokenizer {
+    pattern: Regex
+}
+
+impl Default for UnicodeWordTokenizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UnicodeWordTokenizer {
+    pub fn new() -> UnicodeWordTokenizer {
+        UnicodeWordTokenizer { pattern: Regex::new(r"[\w\p{M}]+(?:['’][\w\p{M}]+)*").expect("Invalid word pattern") }
+    }
+}
+
+impl Tokenizer for UnicodeWordTokenizer {
+    fn tokenize(&self, input: &str) -> Vec<String> {
+        let lower = input.to_lowercase();
+        self.pattern
+            .find_iter(&lower)
+            .map(|m| String::from(m.as_str()))
+            .collect()
+    }
+}
+
+// Keeps urls, @mentions, #hashtags, emoticons and emoji as single tokens
+pub struct TweetTokenizer {
+    pattern: Regex,
+    // drop @mentions
+    strip_handles: bool,
+    // cut runs of more than 3 repeated characters to 3: "sooooo" -> "sooo"
+    reduce_length: bool
+}
+
+impl TweetTokenizer {
+    pub fn new(strip_handles: bool, reduce_length: bool) -> TweetTokenizer {
+        // a mouth letter must end the token and 8 eyes must start one,
+        // so "8pm" and "8dB" stay words
+        let mouth = r"(?:[\)\]\(\[/\\:\}\{@\|]|[dDpP]\b)";
+        let emoticons = [
+            format!(r"[<>]?[:;=][\-o\*']?{}", mouth),
+            format!(r"[<>]?\b8[\-o\*']?{}", mouth),
+            String::from(r"<3")
+        ].join("|");
+        let pattern = [
+            // urls
+            String::from(r"(?:https?://|www\.)\S+"),
+            format!("(?P<emoticon>{})", emoticons),
+            // mentions and hashtags
+            String::from(r"[@#][\w_]+"),
+            // emoji, one token each
+            String::from(r"\p{Extended_Pictographic}"),
+            // words with inner apostrophes and hyphens
+            String::from(r"[\w\p{M}]+(?:['’\-][\w\p{M}]+)*")
+        ].join("|");
+        TweetTokenizer { pattern: Regex::new(&pattern).expect("Invalid tweet pattern"), strip_handles, reduce_length }
+    }
+
+    fn reduce(token: &str) -> String {
+        let mut ret_val = String::new();
+        let mut last = None;
+        let mut run = 0;
+        for c in token.chars() {
+            run = if last == Some(c) { run + 1 } else { 1 };
+            last = Some(c);
+            if run <= 3 {
+                ret_val.push(c);
+            }
+        }
+        ret_val
+    }
+}
+
+impl Tokenizer for TweetTokenizer {
+    fn tokenize(&self, input: &str) -> Vec<String> {
+        self.pattern
+            .captures_iter(input)
+            .map(|caps| (caps.get(0).expect("Match without text").as_str(), caps.name("emoticon").is_some()))
+            .filter(|(token, _)| !(self.strip_handles && token.starts_with('@')))
+            .map(|(token, is_emoticon)| {
+                // urls are case sensitive and ":D" is not ":d"
+                let is_url = token.starts_with("http") || token.starts_with("www.");
+                let token = if is_url || is_emoticon { String::from(token) } else { token.to_lowercase() };
+                if self.reduce_length && !is_url { TweetTokenizer::reduce(&token) } else { token }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(tokenizer: &dyn Tokenizer, input: &str) -> Vec<String> {
+        tokenizer.tokenize(input)
+    }
+
+    #[test]
+    fn regex_tokens_are_lowercased_matches() {
+        let tokenizer = RegexTokenizer::new(r"[a-z]+|\d+").unwrap();
+        assert_eq!(tokens(&tokenizer, "Hello World, 42 times!"), vec!["hello", "world", "42", "times"]);
+        assert_eq!(tokens(&RegexTokenizer::new(r"[a-z]*").unwrap(), "a1b"), vec!["a", "b"]);
+        assert!(RegexTokenizer::new("(").is_err());
+    }
+
+    #[test]
+    fn unicode_words_keep_scripts_and_apostrophes() {
+        let tokenizer = UnicodeWordTokenizer::new();
+        assert_eq!(tokens(&tokenizer, "Don't STOP, naïve café!"), vec!["don't", "stop", "naïve", "café"]);
+        assert_eq!(tokens(&tokenizer, "Привет мир 東京 2024"), vec!["привет", "мир", "東京", "2024"]);
+        assert_eq!(tokens(&tokenizer, "rock 'n' roll"), vec!["rock", "n", "roll"]);
+    }
+
+    #[test]
+    fn tweet_tokens() {
+        let tokenizer = TweetTokenizer::new(false, false);
+        assert_eq!(
+            tokens(&tokenizer, "@Bob LOVED it :D <3 #Rust http://Example.com/A 👍"),
+            vec!["@bob", "loved", "it", ":D", "<3", "#rust", "http://Example.com/A", "👍"]
+        );
+        assert_eq!(tokens(&tokenizer, "well-known isn't ;-) :P"), vec!["well-known", "isn't", ";-)", ":P"]);
+    }
+
+    #[test]
+    fn tweet_emoticons_do_not_start_inside_words() {
+        let tokenizer = TweetTokenizer::new(false, false);
+        assert_eq!(tokens(&tokenizer, "meet at 8pm :D 8D"), vec!["meet", "at", "8pm", ":D", "8D"]);
+        assert_eq!(tokens(&tokenizer, "8dB a8) 8)"), vec!["8db", "a8", "8)"]);
+        assert_eq!(tokens(&tokenizer, ":Dog"), vec!["dog"]);
+    }
+
+    #[test]
+    fn tweet_handles_and_lengths() {
+        assert_eq!(tokens(&TweetTokenizer::new(true, false), "@bob hi"), vec!["hi"]);
+        assert_eq!(tokens(&TweetTokenizer::new(false, true), "Sooooo goooood"), vec!["sooo", "goood"]);
+    }
+}
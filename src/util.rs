@@ -1,136 +1,194 @@
 use std::collections::HashMap;
-use std::ops::Index;
-use csv::Reader;
+use csv::{ReaderBuilder, StringRecord};
 use itertools::Itertools;
 use std::fs;
-use std::thread;
-use std::sync::mpsc;
+
+use crate::events;
+use crate::parallel::process_chunks;
+use crate::text::{NormalizerConfig, TextPipeline, TokenizerConfig};
+use crate::text::stop_words::{StopWords, StopWordsConfig};
+use crate::vocabulary::Vocabulary;
 
 pub type InputTup = (String, String);
 
-pub fn multi_thread_process_list<T1, T2, T3> (
-    list: &Vec<T1>, 
-    context: T3,
-    num_threads: i8, 
-    f_thread: fn(T3, &Vec<T1>) -> Vec<T2>, 
-    f_return: Option<fn(Vec<T2>, i32, usize) -> ()>
-) -> Vec<T2> 
-    where 
-        T1: 'static + Send + Clone, 
-        T2: 'static + Send + Clone,
-        T3: 'static + Send + Clone
-    {
-    let (tx, rx) = mpsc::channel::<Vec<T2>>();
-
-    let list_size = list.len();
-    let num_in_chunk = f32::ceil(list_size as f32 / num_threads as f32) as i32;
-    // println!("{} total records, {} in chunk", list_size, num_in_chunk);
-    
-    let mut threads_spawned: i8 = 0;
-    let iter = list.clone().into_iter();
-
-    for i in 0..num_threads {
-        let ctx = tx.clone();
-
-        threads_spawned += 1;
-        
-        let c = context.clone();
-        let list_chunk = iter.clone().skip((i as i32 * num_in_chunk) as usize).take(num_in_chunk as usize).collect_vec();
-        thread::spawn(move || {
-            ctx.send(f_thread(c, &list_chunk)).expect("Error sending data from thread");
-        });
-    }
+// Default tokenizer, stop words removed, tokens joined by single spaces
+pub fn clean_words(input: &str, stop_words: &StopWords) -> String {
+    TextPipeline::new(&TokenizerConfig::Default, &Vec::new(), stop_words.clone()).clean(input)
+}
+
+// Lowercase, punctuation and digits removed, tokens joined by single spaces
+#[deprecated(note = "use TextPipeline::default().clean")]
+pub fn strip_special_characters(input: &str) -> String {
+    TextPipeline::default().clean(input)
+}
+
+// Every word of the word list file mapped to true
+#[deprecated(note = "use Vocabulary::load and Vocabulary::contains")]
+pub fn get_word_map(file_path: &str) -> HashMap<String, bool> {
+    Vocabulary::load(file_path)
+        .unwrap_or_else(|e| panic!("{}", e))
+        .ranked()
+        .into_iter()
+        .map(|(wd, _)| (wd, true))
+        .collect()
+}
 
-    let mut ret_val: Vec<T2> = Vec::new();
-    for i in 1..threads_spawned+1 {
-        let mut rec = rx.recv().expect("Recieved from thread error");
-        ret_val.append(&mut rec);
-        if f_return.is_some() {
-            f_return.expect("ERR F RETURN")(ret_val.clone(), i as i32 * num_in_chunk, list_size);
+pub fn get_stop_words(file_path: &str) -> StopWords {
+    StopWords::load(file_path)
+}
+
+pub fn get_input_data_csv(csv_file: &str, stop_word_file: &str) -> Vec<InputTup> {
+    let mut config = CsvInputConfig::default();
+    config.stop_words.file = Some(String::from(stop_word_file));
+    get_input_data_csv_with(csv_file, &config).unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnSelector {
+    Index(usize),
+    // needs a header row
+    Name(String)
+}
+
+impl ColumnSelector {
+    // numbers select by index, anything else by header name
+    pub fn parse(s: &str) -> ColumnSelector {
+        match s.parse::<usize>() {
+            Ok(idx) => ColumnSelector::Index(idx),
+            Err(_) => ColumnSelector::Name(String::from(s))
         }
     }
-    ret_val
-}
 
-pub fn strip_special_characters(input: &String) -> String {
-    let special_characters = "!@#$%^&*()_+-=[]{}\\|;':\",./<>?0123456789\n\r";
-    input
-        .to_lowercase()
-        .chars()
-        .map(|c| {
-            if special_characters.contains(c) {
-                ' '
-            } else {
-                c
+    fn resolve(&self, headers: &Option<StringRecord>) -> Result<usize, String> {
+        match self {
+            ColumnSelector::Index(idx) => Ok(*idx),
+            ColumnSelector::Name(name) => {
+                let header_row = headers
+                    .as_ref()
+                    .ok_or(format!("Selecting the csv column {} by name needs a header row", name))?;
+                header_row
+                    .iter()
+                    .position(|h| h.trim() == name)
+                    .ok_or(format!("No csv column named {}, the columns are: {}", name, header_row.iter().join(", ")))
             }
-        })
-        .collect()
+        }
+    }
 }
 
-pub fn clean_words(input: &String, bl_words: &Vec<String>) -> String {
-    let stripped_input = strip_special_characters(input);
-    let words = stripped_input.split(" ");
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvInputConfig {
+    pub label: ColumnSelector,
+    pub text: ColumnSelector,
+    pub delimiter: u8,
+    // when false quote characters are read as part of the field
+    pub quoting: bool,
+    pub has_headers: bool,
+    // drop rows with one of these labels
+    pub exclude_labels: Vec<String>,
+    // keep only rows with one of these labels, empty keeps every label
+    pub include_labels: Vec<String>,
+    pub stop_words: StopWordsConfig,
+    pub tokenizer: TokenizerConfig,
+    // run on every token after the tokenizer, in order
+    pub normalizers: Vec<NormalizerConfig>
+}
 
-    let mut new_s = String::from("");
-    for wd in words {
-        if bl_words.contains(&String::from(wd)) {
-            continue;
+impl Default for CsvInputConfig {
+    // the twitter sentiment dataset layout: id,entity,label,text with a header row
+    fn default() -> CsvInputConfig {
+        CsvInputConfig {
+            label: ColumnSelector::Index(2),
+            text: ColumnSelector::Index(3),
+            delimiter: b',',
+            quoting: true,
+            has_headers: true,
+            exclude_labels: Vec::new(),
+            include_labels: Vec::new(),
+            stop_words: StopWordsConfig::default(),
+            tokenizer: TokenizerConfig::default(),
+            normalizers: Vec::new()
         }
-        new_s.push(' ');
-        new_s.push_str(wd);
     }
-    String::from(new_s)
 }
 
-pub fn get_stop_words(file_path: &str) -> Vec<String> {
-    fs::read_to_string(file_path)
-        .expect("Error reading stop word file")
-        .split("\n")
-        .map(|s| strip_special_characters(&String::from(s)))
-        .collect_vec()
+impl CsvInputConfig {
+    fn keep_label(&self, label: &str) -> bool {
+        if self.exclude_labels.iter().any(|l| l == label) {
+            return false;
+        }
+        self.include_labels.is_empty() || self.include_labels.iter().any(|l| l == label)
+    }
 }
 
-pub fn get_input_data_csv(csv_file: &str, stop_word_file: &str) -> Vec<InputTup> {
-    let stop_words = get_stop_words(stop_word_file);
+// (label, cleaned text) for every row of the csv file the config keeps,
+// errors name the file and what is wrong with it
+pub fn get_input_data_csv_with(csv_file: &str, config: &CsvInputConfig) -> Result<Vec<InputTup>, String> {
+    let stop_words = config.stop_words.load();
 
     let file_contents = fs::read_to_string(csv_file)
-        .expect("error reading input file");
+        .map_err(|e| format!("Error reading input file {}: {}", csv_file, e))?;
 
-    let mut rdr = Reader::from_reader(file_contents.as_bytes());
+    let mut rdr = ReaderBuilder::new()
+        .delimiter(config.delimiter)
+        .quoting(config.quoting)
+        .has_headers(config.has_headers)
+        .flexible(true)
+        .from_reader(file_contents.as_bytes());
 
-    let records = rdr.records()
-        .into_iter()
-        .map(|r| r.expect("Error parsing record"))
-        .map(|r| (String::from(r.index(2)), String::from(r.index(3))))
-        .collect_vec();
+    let headers = if config.has_headers
+        { Some(rdr.headers().map_err(|e| format!("Error reading the csv header of {}: {}", csv_file, e))?.clone()) }
+        else { None };
+    let label_column = config.label.resolve(&headers).map_err(|e| format!("{}: {}", csv_file, e))?;
+    let text_column = config.text.resolve(&headers).map_err(|e| format!("{}: {}", csv_file, e))?;
+
+    let mut records = Vec::new();
+    for r in rdr.records() {
+        let r = r.map_err(|e| format!("Error parsing {}: {}", csv_file, e))?;
+        let field = |idx: usize| r
+            .get(idx)
+            .map(String::from)
+            .ok_or(format!("{}: csv row has no column {}: {:?}", csv_file, idx, r));
+        let (label, text) = (field(label_column)?, field(text_column)?);
+        if config.keep_label(&label) {
+            records.push((label, text));
+        }
+    }
 
-    let f_thread = |bl_words: Vec<String>, chunk: &Vec<(String, String)>| -> Vec<(String, String)> {
+    let pipeline = TextPipeline::new(&config.tokenizer, &config.normalizers, stop_words);
+    let f_thread = |chunk: &[(String, String)]| -> Vec<(String, String)> {
         let mut ret = Vec::new();
         for (sentiment, tweet) in chunk {
-            let pair = (String::from(sentiment), clean_words(tweet, &bl_words));
+            let pair = (String::from(sentiment), pipeline.clean(tweet));
             ret.push(pair);
         }
         ret
     };
 
-    multi_thread_process_list(&records, stop_words, 16, f_thread, None)
+    let f_progress = |done, total| events::progress("clean input", done, Some(total));
+    Ok(process_chunks(&records, f_thread, Some(&f_progress)))
 }
 
 pub fn get_markov_data(text_file_path: &str) -> Vec<InputTup> {
+    get_markov_data_with(text_file_path, &TextPipeline::default())
+}
+
+// (word, next word) for every pair of neighbouring tokens in the file
+pub fn get_markov_data_with(text_file_path: &str, pipeline: &TextPipeline) -> Vec<InputTup> {
     let err = format!("Error reading input file: {}", text_file_path);
     let file_contents = fs::read_to_string(text_file_path).expect(&err);
-    let cleaned_text = clean_words(&file_contents, &Vec::new());
-    let mut last_word = "";
-    let mut ret: Vec<InputTup> = Vec::new();
-    for word in cleaned_text.split(" ") {
-        if last_word.eq(&String::from("")) { 
-            last_word = word.clone();
-            continue; 
-        }
-        ret.push((String::from(last_word), String::from(word)));
-        last_word = word.clone();
-    }
-    ret
+    pipeline
+        .tokens(&file_contents)
+        .into_iter()
+        .tuple_windows()
+        .collect_vec()
+}
+
+// Shard index of count contiguous shards of the list, every item is in exactly one shard
+pub fn shard<T>(list: &[T], index: usize, count: usize) -> &[T] {
+    if index >= count { panic!("Shard {} is out of range for {} shards", index, count) }
+    let start = list.len() * index / count;
+    let end = list.len() * (index + 1) / count;
+    &list[start..end]
 }
 
 pub fn get_percent(prob: &f32) -> f32 { 
@@ -145,11 +203,63 @@ pub fn reduce<VT, RT>(list: &Vec<VT>, initial: &RT, f: fn(&VT, RT) -> RT) -> RT
     ret_val
 }
 
-pub fn get_word_map(file_path: &str) -> HashMap<String, bool> {
-    let file = fs::read_to_string(file_path).expect("Error reading word map file");
-    let mut hm = HashMap::new();
-    for line in file.split("\n") {
-        hm.insert(String::from(line), true);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    const TWEETS: &str = "id,entity,label,text\n1,x,Positive,Great Day!\n2,x,Negative,bad day\n3,x,Irrelevant,what\n";
+
+    // writes the csv to a temp file, loads it and removes the file
+    fn load(name: &str, contents: &str, config: &CsvInputConfig) -> Result<Vec<InputTup>, String> {
+        let file = env::temp_dir().join(format!("util_csv_test_{}_{}.csv", process::id(), name)).to_string_lossy().to_string();
+        fs::write(&file, contents).unwrap();
+        let input = get_input_data_csv_with(&file, config);
+        fs::remove_file(&file).unwrap();
+        input
+    }
+
+    fn tups(pairs: &[(&str, &str)]) -> Vec<InputTup> {
+        pairs.iter().map(|(label, text)| (String::from(*label), String::from(*text))).collect_vec()
     }
-    hm
-}
\ No newline at end of file
+
+    #[test]
+    fn selects_columns_by_index_and_name() {
+        let expected = tups(&[("Positive", "great day"), ("Negative", "bad day"), ("Irrelevant", "what")]);
+        assert_eq!(load("index", TWEETS, &CsvInputConfig::default()).unwrap(), expected);
+        let by_name = CsvInputConfig { label: ColumnSelector::parse("label"), text: ColumnSelector::parse("text"), ..CsvInputConfig::default() };
+        assert_eq!(load("name", TWEETS, &by_name).unwrap(), expected);
+        // the columns in any order
+        let swapped = CsvInputConfig { label: ColumnSelector::Index(3), text: ColumnSelector::Index(0), ..CsvInputConfig::default() };
+        assert_eq!(load("swapped", "a;b\nq;r;s;x\n", &CsvInputConfig { delimiter: b';', ..swapped }).unwrap(), tups(&[("x", "q")]));
+    }
+
+    #[test]
+    fn unknown_columns_are_errors() {
+        let unknown = CsvInputConfig { label: ColumnSelector::parse("sentiment"), ..CsvInputConfig::default() };
+        let error = load("unknown", TWEETS, &unknown).unwrap_err();
+        assert!(error.contains("No csv column named sentiment") && error.contains("id, entity, label, text"), "{}", error);
+        let short_row = CsvInputConfig { text: ColumnSelector::Index(9), ..CsvInputConfig::default() };
+        assert!(load("short", TWEETS, &short_row).unwrap_err().contains("no column 9"));
+        assert!(get_input_data_csv_with("/nonexistent/input.csv", &CsvInputConfig::default()).is_err());
+    }
+
+    #[test]
+    fn without_headers() {
+        let config = CsvInputConfig { has_headers: false, label: ColumnSelector::Index(0), text: ColumnSelector::Index(1), ..CsvInputConfig::default() };
+        assert_eq!(load("no_headers", "spam,Buy now\nham,hello\n", &config).unwrap(), tups(&[("spam", "buy now"), ("ham", "hello")]));
+        let by_name = CsvInputConfig { label: ColumnSelector::parse("label"), ..config };
+        assert!(load("no_headers_name", "spam,Buy now\n", &by_name).unwrap_err().contains("needs a header row"));
+    }
+
+    #[test]
+    fn label_filters() {
+        let exclude = CsvInputConfig { exclude_labels: vec![String::from("Irrelevant")], ..CsvInputConfig::default() };
+        assert_eq!(load("exclude", TWEETS, &exclude).unwrap(), tups(&[("Positive", "great day"), ("Negative", "bad day")]));
+        let include = CsvInputConfig { include_labels: vec![String::from("Negative"), String::from("Irrelevant")], ..CsvInputConfig::default() };
+        assert_eq!(load("include", TWEETS, &include).unwrap(), tups(&[("Negative", "bad day"), ("Irrelevant", "what")]));
+        // excluding wins over including
+        let both = CsvInputConfig { exclude_labels: vec![String::from("Negative")], ..include };
+        assert_eq!(load("both", TWEETS, &both).unwrap(), tups(&[("Irrelevant", "what")]));
+    }
+}
@@ -0,0 +1,360 @@
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+use crate::n_gram::NGram;
+use crate::parallel::process_chunks;
+
+/*
+Document-term matrix over the same grams the n-gram models use.
+fit learns the terms and their document frequencies from a corpus, transform turns
+documents into sparse rows weighted by TF, TF-IDF or BM25. Documents are cleaned
+text (TextPipeline output), terms are grams of 1 to max_grams words joined by spaces.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Weighting {
+    // times the term appears in the document
+    Tf,
+    // tf * (ln((1 + documents) / (1 + document frequency)) + 1)
+    #[default]
+    TfIdf,
+    // Okapi BM25 with term saturation k1 and length normalization b
+    Bm25 { k1: f64, b: f64 }
+}
+
+impl Weighting {
+    // tf, tf_idf, bm25 (k1 1.2, b 0.75) or bm25(k1,b)
+    pub fn parse(s: &str) -> Option<Weighting> {
+        match s {
+            "tf" => Some(Weighting::Tf),
+            "tf_idf" => Some(Weighting::TfIdf),
+            "bm25" => Some(Weighting::Bm25 { k1: 1.2, b: 0.75 }),
+            _ => {
+                let (k1, b) = s.strip_prefix("bm25(")?.strip_suffix(")")?.split_once(',')?;
+                let (k1, b) = (k1.trim().parse::<f64>().ok()?, b.trim().parse::<f64>().ok()?);
+                if k1 < 0.0 || !(0.0..=1.0).contains(&b) { return None; }
+                Some(Weighting::Bm25 { k1, b })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Weighting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Weighting::Tf => write!(f, "tf"),
+            Weighting::TfIdf => write!(f, "tf_idf"),
+            Weighting::Bm25 { k1, b } => write!(f, "bm25({},{})", k1, b)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VectorizerConfig {
+    pub max_grams: usize,
+    pub weighting: Weighting,
+    // 1 + ln(tf) in place of tf
+    pub sublinear_tf: bool,
+    // scale every row to unit L2 length
+    pub normalize: bool,
+    // terms in fewer documents are dropped
+    pub min_df: usize,
+    // keep only the terms in the most documents, None keeps every term
+    pub max_features: Option<usize>
+}
+
+impl Default for VectorizerConfig {
+    fn default() -> VectorizerConfig {
+        VectorizerConfig {
+            max_grams: 1,
+            weighting: Weighting::default(),
+            sublinear_tf: false,
+            normalize: true,
+            min_df: 1,
+            max_features: None
+        }
+    }
+}
+
+// Row major sparse matrix, every row holds (column, value) sorted by column
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseMatrix {
+    pub columns: usize,
+    pub rows: Vec<Vec<(usize, f64)>>
+}
+
+impl SparseMatrix {
+    pub fn non_zero(&self) -> usize {
+        self.rows.iter().map(|row| row.len()).sum()
+    }
+
+    // Matrix Market coordinate format, indexes start at 1
+    pub fn write_matrix_market<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writeln!(writer, "%%MatrixMarket matrix coordinate real general")?;
+        writeln!(writer, "% rows are documents, columns are terms")?;
+        writeln!(writer, "{} {} {}", self.rows.len(), self.columns, self.non_zero())?;
+        for (i, row) in self.rows.iter().enumerate() {
+            for (j, value) in row {
+                writeln!(writer, "{} {} {}", i + 1, j + 1, value)?;
+            }
+        }
+        writer.flush()
+    }
+
+    // Dense csv with a header of the terms, labels (one per row) go in a first label column
+    pub fn write_csv<W: Write>(&self, writer: W, terms: &[String], labels: Option<&Vec<String>>) -> csv::Result<()> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        let label_header = labels.map(|_| String::from("label"));
+        csv_writer.write_record(label_header.iter().chain(terms.iter()))?;
+        for (i, row) in self.rows.iter().enumerate() {
+            let mut values = vec![String::from("0"); self.columns];
+            for (j, value) in row {
+                values[*j] = value.to_string();
+            }
+            let label = labels.map(|labels| labels[i].clone());
+            csv_writer.write_record(label.iter().chain(values.iter()))?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    }
+}
+
+pub struct Vectorizer {
+    pub config: VectorizerConfig,
+    // sorted, the column of a term is its index
+    pub terms: Vec<String>,
+    // documents each term appeared in
+    pub document_frequency: Vec<usize>,
+    pub documents: usize,
+    // mean number of grams in a fitted document, used by BM25
+    pub average_length: f64,
+    index: HashMap<String, usize>
+}
+
+// Count of every gram of 1 to max_grams words in the document
+fn term_counts(document: &str, max_grams: usize) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for n in 1..(max_grams + 1) {
+        for gram in NGram::create_grams(document, n) {
+            *counts.entry(gram).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+impl Vectorizer {
+    pub fn fit(documents: &[String], config: &VectorizerConfig) -> Vectorizer {
+        if config.max_grams < 1 { panic!("Vectorizer max_grams must be at least 1") }
+        let f_thread = |chunk: &[String]| -> Vec<(HashMap<String, usize>, usize)> {
+            let mut document_frequency = HashMap::new();
+            let mut length = 0;
+            for document in chunk {
+                let counts = term_counts(document, config.max_grams);
+                length += counts.values().sum::<usize>();
+                for term in counts.into_keys() {
+                    *document_frequency.entry(term).or_insert(0) += 1;
+                }
+            }
+            vec![(document_frequency, length)]
+        };
+        let mut document_frequency: HashMap<String, usize> = HashMap::new();
+        let mut total_length = 0;
+        for (partial, length) in process_chunks(documents, f_thread, None) {
+            total_length += length;
+            for (term, df) in partial {
+                *document_frequency.entry(term).or_insert(0) += df;
+            }
+        }
+
+        let mut kept = document_frequency
+            .into_iter()
+            .filter(|(_, df)| *df >= config.min_df)
+            .collect_vec();
+        if let Some(max_features) = config.max_features {
+            kept = kept
+                .into_iter()
+                .sorted_by(|(term1, df1), (term2, df2)| df2.cmp(df1).then(term1.cmp(term2)))
+                .take(max_features)
+                .collect_vec();
+        }
+        let (terms, document_frequency): (Vec<String>, Vec<usize>) = kept
+            .into_iter()
+            .sorted_by(|(term1, _), (term2, _)| term1.cmp(term2))
+            .unzip();
+        let average_length = if documents.is_empty() { 0.0 } else { total_length as f64 / documents.len() as f64 };
+        Vectorizer::from_parts(config.clone(), terms, document_frequency, documents.len(), average_length)
+    }
+
+    fn from_parts(config: VectorizerConfig, terms: Vec<String>, document_frequency: Vec<usize>, documents: usize, average_length: f64) -> Vectorizer {
+        let index = terms.iter().enumerate().map(|(i, term)| (term.clone(), i)).collect();
+        Vectorizer { config, terms, document_frequency, documents, average_length, index }
+    }
+
+    pub fn column(&self, term: &str) -> Option<usize> {
+        self.index.get(term).copied()
+    }
+
+    // Inverse document frequency of a column under the configured weighting, 1 for Tf
+    pub fn idf(&self, column: usize) -> f64 {
+        let n = self.documents as f64;
+        let df = self.document_frequency[column] as f64;
+        match self.config.weighting {
+            Weighting::Tf => 1.0,
+            Weighting::TfIdf => ((1.0 + n) / (1.0 + df)).ln() + 1.0,
+            Weighting::Bm25 { .. } => (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+        }
+    }
+
+    // Weighted row of one document, terms that were not fitted are ignored
+    pub fn transform_document(&self, document: &str) -> Vec<(usize, f64)> {
+        let counts = term_counts(document, self.config.max_grams);
+        let length = counts.values().sum::<usize>() as f64;
+        let mut row = counts
+            .into_iter()
+            .filter_map(|(term, count)| self.column(&term).map(|column| (column, count as f64)))
+            .map(|(column, count)| {
+                let tf = if self.config.sublinear_tf { 1.0 + count.ln() } else { count };
+                let weight = match self.config.weighting {
+                    Weighting::Tf => tf,
+                    Weighting::TfIdf => tf * self.idf(column),
+                    Weighting::Bm25 { k1, b } => {
+                        let length_norm = if self.average_length > 0.0 { length / self.average_length } else { 1.0 };
+                        self.idf(column) * tf * (k1 + 1.0) / (tf + k1 * (1.0 - b + b * length_norm))
+                    }
+                };
+                (column, weight)
+            })
+            .filter(|(_, weight)| *weight != 0.0)
+            .sorted_by(|(column1, _), (column2, _)| column1.cmp(column2))
+            .collect_vec();
+        if self.config.normalize {
+            let norm = row.iter().map(|(_, weight)| weight * weight).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                for (_, weight) in row.iter_mut() {
+                    *weight /= norm;
+                }
+            }
+        }
+        row
+    }
+
+    pub fn transform(&self, documents: &[String]) -> SparseMatrix {
+        let f_thread = |chunk: &[String]| -> Vec<Vec<(usize, f64)>> {
+            chunk.iter().map(|document| self.transform_document(document)).collect_vec()
+        };
+        SparseMatrix { columns: self.terms.len(), rows: process_chunks(documents, f_thread, None) }
+    }
+
+    pub fn fit_transform(documents: &[String], config: &VectorizerConfig) -> (Vectorizer, SparseMatrix) {
+        let vectorizer = Vectorizer::fit(documents, config);
+        let matrix = vectorizer.transform(documents);
+        (vectorizer, matrix)
+    }
+
+    // First line: documents<TAB>average length<TAB>max grams<TAB>weighting<TAB>sublinear<TAB>normalize
+    // then term<TAB>document frequency in column order
+    pub fn save(&self, file_name: &str) {
+        let mut writer = BufWriter::new(File::create(file_name).expect("Error creating file object"));
+        let c = &self.config;
+        writeln!(writer, "{}\t{}\t{}\t{}\t{}\t{}", self.documents, self.average_length, c.max_grams, c.weighting, c.sublinear_tf, c.normalize).expect("Error writing to file");
+        for (term, df) in self.terms.iter().zip(&self.document_frequency) {
+            writeln!(writer, "{}\t{}", term, df).expect("Error writing to file");
+        }
+        writer.flush().expect("Error writing to file");
+    }
+
+    pub fn load(file_name: &str) -> Vectorizer {
+        let err = format!("Error reading vectorizer: {}", file_name);
+        let contents = fs::read_to_string(file_name).expect(&err);
+        let mut lines = contents.lines();
+        let header = lines.next().expect(&err).split('\t').collect_vec();
+        if header.len() != 6 { panic!("{}: bad header", err) }
+        let config = VectorizerConfig {
+            max_grams: header[2].parse().expect(&err),
+            weighting: Weighting::parse(header[3]).expect(&err),
+            sublinear_tf: header[4].parse().expect(&err),
+            normalize: header[5].parse().expect(&err),
+            ..VectorizerConfig::default()
+        };
+        let (terms, document_frequency): (Vec<String>, Vec<usize>) = lines
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (term, df) = line.rsplit_once('\t').expect(&err);
+                (String::from(term), df.parse::<usize>().expect(&err))
+            })
+            .unzip();
+        Vectorizer::from_parts(config, terms, document_frequency, header[0].parse().expect(&err), header[1].parse().expect(&err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus() -> Vec<String> {
+        vec![String::from("a b"), String::from("a c"), String::from("a a b")]
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    fn config(weighting: Weighting, normalize: bool) -> VectorizerConfig {
+        VectorizerConfig { weighting, normalize, ..VectorizerConfig::default() }
+    }
+
+    #[test]
+    fn tf_idf_values() {
+        let (vectorizer, matrix) = Vectorizer::fit_transform(&corpus(), &config(Weighting::TfIdf, false));
+        assert_eq!(vectorizer.terms, vec!["a", "b", "c"]);
+        assert_eq!(vectorizer.document_frequency, vec![3, 2, 1]);
+        // smooth idf: ln((1 + 3) / (1 + df)) + 1
+        let idf_b = (4.0f64 / 3.0).ln() + 1.0;
+        assert_eq!(matrix.rows[2].len(), 2);
+        assert!(close(matrix.rows[2][0].1, 2.0) && close(matrix.rows[2][1].1, idf_b));
+        assert_eq!(matrix.rows[1][1].0, 2);
+        assert!(close(matrix.rows[1][1].1, 2.0f64.ln() + 1.0));
+
+        let sublinear = VectorizerConfig { sublinear_tf: true, ..config(Weighting::TfIdf, false) };
+        let matrix = Vectorizer::fit_transform(&corpus(), &sublinear).1;
+        assert!(close(matrix.rows[2][0].1, 1.0 + 2.0f64.ln()));
+    }
+
+    #[test]
+    fn bm25_values() {
+        let (vectorizer, matrix) = Vectorizer::fit_transform(&corpus(), &config(Weighting::Bm25 { k1: 1.2, b: 0.75 }, false));
+        assert!(close(vectorizer.average_length, 7.0 / 3.0));
+        // idf ln(1 + (3 - 3 + 0.5) / (3 + 0.5)), tf 2 in a document of 3 grams
+        let idf_a = (1.0f64 + 0.5 / 3.5).ln();
+        let expected = idf_a * 2.0 * 2.2 / (2.0 + 1.2 * (0.25 + 0.75 * 3.0 / (7.0 / 3.0)));
+        assert!(close(matrix.rows[2][0].1, expected));
+    }
+
+    #[test]
+    fn rows_are_l2_normalized() {
+        let matrix = Vectorizer::fit_transform(&corpus(), &VectorizerConfig::default()).1;
+        for row in &matrix.rows {
+            assert!(close(row.iter().map(|(_, v)| v * v).sum::<f64>(), 1.0));
+        }
+        let idf_b = (4.0f64 / 3.0).ln() + 1.0;
+        assert!(close(matrix.rows[2][0].1, 2.0 / (4.0 + idf_b * idf_b).sqrt()));
+    }
+
+    #[test]
+    fn matrix_market_header() {
+        let matrix = Vectorizer::fit_transform(&corpus(), &config(Weighting::Tf, false)).1;
+        let mut out = Vec::new();
+        matrix.write_matrix_market(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "%%MatrixMarket matrix coordinate real general");
+        assert!(lines[1].starts_with('%'));
+        assert_eq!(lines[2], "3 3 6");
+        assert_eq!(lines[3], "1 1 1");
+        assert_eq!(lines.last(), Some(&"3 2 1"));
+        assert_eq!(lines.len(), 3 + matrix.non_zero());
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+
+use csv::Reader;
+use itertools::Itertools;
+
+use crate::text::TextPipeline;
+use crate::util::InputTup;
+
+// Stands in for every token outside the vocabulary
+pub const UNK: &str = "<UNK>";
+
+// Token counts from a corpus, used to limit models to known words
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vocabulary {
+    counts: HashMap<String, u64>
+}
+
+impl Default for Vocabulary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vocabulary {
+    pub fn new() -> Vocabulary {
+        Vocabulary { counts: HashMap::new() }
+    }
+
+    pub fn add(&mut self, token: &str) {
+        self.add_count(token, 1);
+    }
+
+    pub fn add_count(&mut self, token: &str, count: u64) {
+        *self.counts.entry(String::from(token)).or_insert(0) += count;
+    }
+
+    pub fn add_text(&mut self, pipeline: &TextPipeline, text: &str) {
+        for token in pipeline.tokens(text) {
+            self.add(&token);
+        }
+    }
+
+    // every line of a text file run through the pipeline
+    pub fn from_corpus(file_path: &str, pipeline: &TextPipeline) -> Vocabulary {
+        let err = format!("Error reading corpus file: {}", file_path);
+        let mut vocabulary = Vocabulary::new();
+        for line in fs::read_to_string(file_path).expect(&err).lines() {
+            vocabulary.add_text(pipeline, line);
+        }
+        vocabulary
+    }
+
+    // word,count csv with a header row, like unigram_freq.csv
+    pub fn from_counts_csv(file_path: &str) -> Result<Vocabulary, String> {
+        let file_contents = fs::read_to_string(file_path).map_err(|e| format!("Error reading {}: {}", file_path, e))?;
+        let mut rdr = Reader::from_reader(file_contents.as_bytes());
+        let mut vocabulary = Vocabulary::new();
+        for r in rdr.records() {
+            let record = r.map_err(|e| format!("Error parsing record: {}", e))?;
+            let (word, count) = match (record.get(0), record.get(1)) {
+                (Some(word), Some(count)) => (word, count),
+                _ => return Err(format!("Expected word,count got: {:?}", record))
+            };
+            let count = count.parse::<u64>().map_err(|_| format!("Bad count for word {}", word))?;
+            vocabulary.add_count(word, count);
+        }
+        Ok(vocabulary)
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.counts.contains_key(token)
+    }
+
+    pub fn count(&self, token: &str) -> u64 {
+        *self.counts.get(token).unwrap_or(&0)
+    }
+
+    // most frequent first, ties by word
+    pub fn ranked(&self) -> Vec<(String, u64)> {
+        self.counts
+            .iter()
+            .map(|(wd, count)| (wd.clone(), *count))
+            .sorted_by(|(wd1, n1), (wd2, n2)| n2.cmp(n1).then(wd1.cmp(wd2)))
+            .collect_vec()
+    }
+
+    // the n most frequent words
+    pub fn top(&self, n: usize) -> Vocabulary {
+        Vocabulary { counts: self.ranked().into_iter().take(n).collect() }
+    }
+
+    pub fn min_count(&self, min: u64) -> Vocabulary {
+        Vocabulary {
+            counts: self.counts
+                .iter()
+                .filter(|(_, count)| **count >= min)
+                .map(|(wd, count)| (wd.clone(), *count))
+                .collect()
+        }
+    }
+
+    pub fn map_token(&self, token: &str) -> String {
+        if self.contains(token) { String::from(token) } else { String::from(UNK) }
+    }
+
+    // space separated tokens, as produced by TextPipeline::clean
+    pub fn map_sentence(&self, sentence: &str) -> String {
+        sentence.split_whitespace().map(|wd| self.map_token(wd)).join(" ")
+    }
+
+    // maps the text of (label, text) input, labels are kept
+    pub fn map_input(&self, input: &[InputTup]) -> Vec<InputTup> {
+        input
+            .iter()
+            .map(|(label, text)| (label.clone(), self.map_sentence(text)))
+            .collect_vec()
+    }
+
+    // word<TAB>count per line, most frequent first
+    pub fn save(&self, file_name: &str) {
+        let mut file = File::create(file_name).expect("Error creating file object");
+        for (wd, count) in self.ranked() {
+            file.write_all(format!("{}\t{}\n", wd, count).as_bytes()).expect("Error writing to file");
+        }
+    }
+
+    // lines without a count, like a plain word list, count as 1
+    pub fn load(file_name: &str) -> Result<Vocabulary, String> {
+        let file_contents = fs::read_to_string(file_name).map_err(|e| format!("Error reading vocabulary file {}: {}", file_name, e))?;
+        let mut vocabulary = Vocabulary::new();
+        for line in file_contents.lines() {
+            if line.trim().is_empty() { continue; }
+            match line.rsplit_once('\t') {
+                Some((wd, count)) => {
+                    let count = count.trim().parse::<u64>().map_err(|_| format!("Bad count in vocabulary line: {}", line))?;
+                    vocabulary.add_count(wd, count);
+                },
+                None => vocabulary.add(line.trim())
+            }
+        }
+        Ok(vocabulary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn vocabulary(counts: &[(&str, u64)]) -> Vocabulary {
+        let mut vocabulary = Vocabulary::new();
+        for (wd, count) in counts {
+            vocabulary.add_count(wd, *count);
+        }
+        vocabulary
+    }
+
+    fn temp_file(name: &str) -> String {
+        env::temp_dir().join(format!("vocabulary_test_{}_{}.txt", process::id(), name)).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn ranked_by_count_then_word() {
+        let vocabulary = vocabulary(&[("b", 2), ("c", 5), ("a", 2), ("d", 1)]);
+        let ranked = vocabulary.ranked().into_iter().map(|(wd, _)| wd).collect_vec();
+        assert_eq!(ranked, vec!["c", "a", "b", "d"]);
+        assert_eq!(vocabulary.top(2), self::vocabulary(&[("c", 5), ("a", 2)]));
+        assert_eq!(vocabulary.top(10), vocabulary);
+        assert_eq!(vocabulary.min_count(2), self::vocabulary(&[("b", 2), ("c", 5), ("a", 2)]));
+        assert!(vocabulary.min_count(6).is_empty());
+    }
+
+    #[test]
+    fn unknown_tokens_map_to_unk() {
+        let vocabulary = vocabulary(&[("the", 3), ("cat", 1)]);
+        assert_eq!(vocabulary.map_sentence("the  dog ate the cat"), "the <UNK> <UNK> the cat");
+        let input = vec![(String::from("pets"), String::from("cat dog"))];
+        assert_eq!(vocabulary.map_input(&input), vec![(String::from("pets"), String::from("cat <UNK>"))]);
+        assert_eq!(vocabulary.count("dog"), 0);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let file = temp_file("round_trip");
+        let vocabulary = vocabulary(&[("the", 3), ("don't", 2), ("cat", 1)]);
+        vocabulary.save(&file);
+        let loaded = Vocabulary::load(&file);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(loaded.unwrap(), vocabulary);
+    }
+
+    #[test]
+    fn load_reads_word_lists_and_rejects_bad_counts() {
+        let file = temp_file("word_list");
+        fs::write(&file, "the\n\ncat\nthe\n").unwrap();
+        let word_list = Vocabulary::load(&file);
+        fs::write(&file, "the\t3\ncat\tmany\n").unwrap();
+        let bad_count = Vocabulary::load(&file);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(word_list.unwrap(), vocabulary(&[("the", 2), ("cat", 1)]));
+        assert!(bad_count.unwrap_err().contains("cat\tmany"));
+        assert!(Vocabulary::load("/nonexistent/vocabulary.txt").is_err());
+    }
+}